@@ -57,6 +57,18 @@ impl AnalogBackupIterator {
                 self.done = true;
                 AnalogSection::UpperADCOffset(analog.upper_adc_offset)
             }
+            AnalogSectionId::MidiIdMSB => {
+                self.done = true;
+                AnalogSection::MidiIdMSB((analog.midi_id >> 7) as u8)
+            }
+            AnalogSectionId::LowerCCLimitMSB => {
+                self.done = true;
+                AnalogSection::LowerCCLimitMSB((analog.lower_limit >> 7) as u8)
+            }
+            AnalogSectionId::UpperCCLimitMSB => {
+                self.done = true;
+                AnalogSection::UpperCCLimitMSB((analog.upper_limit >> 7) as u8)
+            }
         };
 
         Some(OpenDeckResponse::Configuration(
@@ -1,12 +1,13 @@
 use crate::analog::{Analog, AnalogMessageType};
 use crate::handler::{ChannelMessages, HiRes};
+use crate::ump::{self, UmpMode};
 use crate::ChannelOrAll;
 
 use midi2::{
     channel_voice1::{ControlChange, NoteOn, PitchBend},
     error::BufferOverflow,
     prelude::*,
-    BytesMessage,
+    BytesMessage, UmpMessage,
 };
 
 pub struct AnalogMessages<'a> {
@@ -101,6 +102,50 @@ impl<'a> AnalogMessages<'a> {
             AnalogMessageType::Reserved => Ok(None),
         }
     }
+    /// Same as [`AnalogMessages::next`], rendered as UMP for `group`.
+    ///
+    /// With MIDI 2.0 a 14-bit control change is a single control change and NRPN
+    /// an assignable controller, both with a 32-bit value.
+    pub fn next_ump<'buf>(
+        &mut self,
+        mode: UmpMode,
+        group: u4,
+        buffer: &'buf mut [u32],
+    ) -> Result<Option<UmpMessage<&'buf [u32]>>, BufferOverflow> {
+        let (status, index, bits) = match (mode, self.analog.message_type) {
+            (UmpMode::Midi2, AnalogMessageType::PotentiometerWithCCMessage14Bit) => {
+                (ump::CONTROL_CHANGE, (self.analog.midi_id as u8, 0), 14)
+            }
+            (UmpMode::Midi2, AnalogMessageType::NRPN7 | AnalogMessageType::NRPN14) => {
+                let id = self.analog.midi_id;
+                let bits = match self.analog.message_type {
+                    AnalogMessageType::NRPN7 => 7,
+                    _ => 14,
+                };
+                (
+                    ump::ASSIGNABLE_CONTROLLER,
+                    (HiRes::new(id).msb().into(), HiRes::new(id).lsb().into()),
+                    bits,
+                )
+            }
+            _ => {
+                let bytes = &mut [0; ump::MAX_BYTES];
+                return match self.next(bytes)? {
+                    Some(m) => ump::from_bytes(mode, group, m.data(), buffer).map(Some),
+                    None => Ok(None),
+                };
+            }
+        };
+        if !self.analog.enabled {
+            return Ok(None);
+        }
+        let Some((channel, _)) = self.channel_messages.next_first() else {
+            return Ok(None);
+        };
+        // like the data bytes of MIDI 1.0, only the low `bits` of the value are sent
+        let data = ump::scale_up(self.value as u32 & ((1 << bits) - 1), bits, 32);
+        ump::midi2(group, status, channel, index, data, buffer).map(Some)
+    }
 }
 
 impl Analog {
@@ -119,6 +164,10 @@ impl Analog {
         self.last_value = scaled;
         AnalogMessages::new_with_channel(self, scaled, channel_override)
     }
+    /// Whether `value` results in new messages.
+    pub(crate) fn changes(&self, value: u16) -> bool {
+        self.enabled && self.scale_value(value) != self.last_value
+    }
     fn scale_value(&self, value: u16) -> u16 {
         let adc_max = self.adc_max;
         let input = if self.inverted {
@@ -390,6 +439,61 @@ mod tests {
         assert_eq!(Ok(None), it.next(&mut message_buffer));
     }
 
+    #[test]
+    fn test_ump() {
+        let buffer = &mut [0u32; 4];
+        let mut analog = Analog {
+            enabled: true,
+            inverted: false,
+            upper_limit: 1000,
+            lower_limit: 0,
+            lower_adc_offset: 0,
+            upper_adc_offset: 0,
+            message_type: AnalogMessageType::PotentiometerWithCCMessage14Bit,
+            midi_id: 0x03,
+            channel: ChannelOrAll::default(),
+            adc_max: MAX_ADC_VALUE,
+            last_value: u16::MAX,
+        };
+        let mut it = analog.handle(MAX_ADC_VALUE);
+        let m = it.next_ump(UmpMode::Midi1, u4::new(2), buffer).unwrap();
+        assert_eq!(m.unwrap().data(), [0x22B0_0307]);
+        let m = it.next_ump(UmpMode::Midi1, u4::new(2), buffer).unwrap();
+        assert_eq!(m.unwrap().data(), [0x22B0_2368]);
+        assert_eq!(Ok(None), it.next_ump(UmpMode::Midi1, u4::new(2), buffer));
+
+        // a single control change with the full value
+        analog.last_value = u16::MAX;
+        let mut it = analog.handle(MAX_ADC_VALUE);
+        let m = it.next_ump(UmpMode::Midi2, u4::new(0), buffer).unwrap();
+        assert_eq!(m.unwrap().data(), [0x40B0_0300, 1000 << 18]);
+        assert_eq!(Ok(None), it.next_ump(UmpMode::Midi2, u4::new(0), buffer));
+
+        analog.message_type = AnalogMessageType::NRPN14;
+        analog.midi_id = 1624;
+        analog.upper_limit = 8234;
+        analog.channel = ChannelOrAll::All;
+        analog.last_value = u16::MAX;
+        let mut it = analog.handle(MAX_ADC_VALUE);
+        for channel in 0..16 {
+            let m = it.next_ump(UmpMode::Midi2, u4::new(0), buffer).unwrap();
+            assert_eq!(
+                m.unwrap().data(),
+                [0x4030_0C58 | channel << 16, 0x80A8_0540]
+            );
+        }
+        assert_eq!(Ok(None), it.next_ump(UmpMode::Midi2, u4::new(0), buffer));
+
+        // a 7-bit NRPN sends the low 7 bits of a value above 127, as with MIDI 1.0
+        analog.message_type = AnalogMessageType::NRPN7;
+        analog.upper_limit = 160;
+        analog.channel = ChannelOrAll::default();
+        analog.last_value = u16::MAX;
+        let mut it = analog.handle(MAX_ADC_VALUE);
+        let m = it.next_ump(UmpMode::Midi2, u4::new(0), buffer).unwrap();
+        assert_eq!(m.unwrap().data(), [0x4030_0C58, 32 << 25]);
+    }
+
     #[test]
     fn test_overflow() {
         let mut message_buffer = [0x00u8; 1];
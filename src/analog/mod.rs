@@ -1,4 +1,4 @@
-use crate::ChannelOrAll;
+use crate::{with_msb, ChannelOrAll};
 use int_enum::IntEnum;
 
 pub mod backup;
@@ -44,6 +44,9 @@ impl Analog {
     pub fn set_adc_max(&mut self, adc_max: u16) {
         self.adc_max = adc_max;
     }
+    pub fn adc_max(&self) -> u16 {
+        self.adc_max
+    }
     pub fn set(&mut self, section: AnalogSection) {
         match section {
             AnalogSection::MessageType(v) => self.message_type = v,
@@ -55,6 +58,9 @@ impl Analog {
             AnalogSection::UpperCCLimit(v) => self.upper_limit = v,
             AnalogSection::LowerADCOffset(v) => self.lower_adc_offset = v,
             AnalogSection::UpperADCOffset(v) => self.upper_adc_offset = v,
+            AnalogSection::MidiIdMSB(v) => self.midi_id = with_msb(self.midi_id, v),
+            AnalogSection::LowerCCLimitMSB(v) => self.lower_limit = with_msb(self.lower_limit, v),
+            AnalogSection::UpperCCLimitMSB(v) => self.upper_limit = with_msb(self.upper_limit, v),
         }
     }
     pub fn get(&self, section: AnalogSection) -> u16 {
@@ -68,6 +74,9 @@ impl Analog {
             AnalogSection::UpperCCLimit(_) => self.upper_limit,
             AnalogSection::LowerADCOffset(_) => self.lower_adc_offset.into(),
             AnalogSection::UpperADCOffset(_) => self.upper_adc_offset.into(),
+            AnalogSection::MidiIdMSB(_) => self.midi_id >> 7,
+            AnalogSection::LowerCCLimitMSB(_) => self.lower_limit >> 7,
+            AnalogSection::UpperCCLimitMSB(_) => self.upper_limit >> 7,
         }
     }
 }
@@ -95,11 +104,11 @@ enum AnalogSectionId {
     InvertState = 0x1,
     MessageType = 0x2,
     MidiIdLSB = 0x3,
-    // MidiIdMSB = 0x4, // only used in 1 byte protocol
+    MidiIdMSB = 0x4, // only used in 1 byte protocol
     LowerCCLimitLSB = 0x5,
-    //LowerCCLimitMSB = 0x6, // only used in 1 byte protocol
+    LowerCCLimitMSB = 0x6, // only used in 1 byte protocol
     UpperCCLimitLSB = 0x7,
-    // UpperCCLimitMSB = 0x8, // only used in 1 byte protocol
+    UpperCCLimitMSB = 0x8, // only used in 1 byte protocol
     Channel = 0x9,
     LowerADCOffset = 0xA,
     UpperADCOffset = 0xB,
@@ -115,6 +124,12 @@ pub enum AnalogSection {
     LowerCCLimit(u16),
     UpperCCLimit(u16),
     Channel(ChannelOrAll),
+    /// Upper 7 bits of the MIDI ID, only used in 1 byte protocol.
+    MidiIdMSB(u8),
+    /// Upper 7 bits of the lower CC limit, only used in 1 byte protocol.
+    LowerCCLimitMSB(u8),
+    /// Upper 7 bits of the upper CC limit, only used in 1 byte protocol.
+    UpperCCLimitMSB(u8),
     LowerADCOffset(u8),
     UpperADCOffset(u8),
 }
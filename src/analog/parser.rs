@@ -1,7 +1,7 @@
 use crate::{
     analog::{AnalogMessageType, AnalogSection, AnalogSectionId},
     parser::OpenDeckParseError,
-    ChannelOrAll, MessageStatus, Section,
+    MessageStatus, Section,
 };
 
 impl TryFrom<Section> for AnalogSection {
@@ -9,17 +9,24 @@ impl TryFrom<Section> for AnalogSection {
     fn try_from(v: Section) -> Result<Self, Self::Error> {
         if let Ok(id) = AnalogSectionId::try_from(v.id) {
             match id {
-                AnalogSectionId::Enabled => Ok(AnalogSection::Enabled(v.value > 0)),
-                AnalogSectionId::InvertState => Ok(AnalogSection::Inverted(v.value > 0)),
+                AnalogSectionId::Enabled => v.flag().map(AnalogSection::Enabled),
+                AnalogSectionId::InvertState => v.flag().map(AnalogSection::Inverted),
                 AnalogSectionId::MessageType => AnalogMessageType::try_from(v.value)
                     .map(AnalogSection::MessageType)
                     .map_err(OpenDeckParseError::new_value_err),
                 AnalogSectionId::MidiIdLSB => Ok(AnalogSection::MidiId(v.value)),
                 AnalogSectionId::LowerCCLimitLSB => Ok(AnalogSection::LowerCCLimit(v.value)),
                 AnalogSectionId::UpperCCLimitLSB => Ok(AnalogSection::UpperCCLimit(v.value)),
-                AnalogSectionId::Channel => Ok(AnalogSection::Channel(ChannelOrAll::from(v.value))),
-                AnalogSectionId::LowerADCOffset => Ok(AnalogSection::LowerADCOffset(v.value as u8)),
-                AnalogSectionId::UpperADCOffset => Ok(AnalogSection::UpperADCOffset(v.value as u8)),
+                AnalogSectionId::Channel => v.channel().map(AnalogSection::Channel),
+                AnalogSectionId::LowerADCOffset => v.data_byte().map(AnalogSection::LowerADCOffset),
+                AnalogSectionId::UpperADCOffset => v.data_byte().map(AnalogSection::UpperADCOffset),
+                AnalogSectionId::MidiIdMSB => v.data_byte().map(AnalogSection::MidiIdMSB),
+                AnalogSectionId::LowerCCLimitMSB => {
+                    v.data_byte().map(AnalogSection::LowerCCLimitMSB)
+                }
+                AnalogSectionId::UpperCCLimitMSB => {
+                    v.data_byte().map(AnalogSection::UpperCCLimitMSB)
+                }
             }
         } else {
             Err(OpenDeckParseError::StatusError(MessageStatus::SectionError))
@@ -40,6 +47,15 @@ mod tests {
         assert_eq!(result, Ok(AnalogSection::MidiId(value)));
     }
 
+    #[test]
+    fn test_msb_sections() {
+        let result = AnalogSection::try_from(Section {
+            id: 0x08,
+            value: 0x07,
+        });
+        assert_eq!(result, Ok(AnalogSection::UpperCCLimitMSB(0x07)));
+    }
+
     #[test]
     fn test_message_type_value_error() {
         let result = AnalogSection::try_from(Section {
@@ -42,6 +42,18 @@ impl From<AnalogSection> for Section {
                 id: AnalogSectionId::UpperADCOffset.into(),
                 value: value as u16,
             },
+            AnalogSection::MidiIdMSB(value) => Section {
+                id: AnalogSectionId::MidiIdMSB.into(),
+                value: value as u16,
+            },
+            AnalogSection::LowerCCLimitMSB(value) => Section {
+                id: AnalogSectionId::LowerCCLimitMSB.into(),
+                value: value as u16,
+            },
+            AnalogSection::UpperCCLimitMSB(value) => Section {
+                id: AnalogSectionId::UpperCCLimitMSB.into(),
+                value: value as u16,
+            },
         }
     }
 }
@@ -1,11 +1,14 @@
 //! BPM state for MIDI Clock generation.
 //!
 //! The library stores and adjusts BPM via button/encoder handlers.
-//! Firmware uses `tick_interval_us()` to schedule 0xF8 timing messages at 24 PPQN.
+//! Firmware uses `tick_interval_us()` to schedule 0xF8 timing messages at 24 PPQN,
+//! or [`crate::clock::ClockGenerator`] which doesn't drift.
 
 const MIN_BPM: u16 = 30;
 const MAX_BPM: u16 = 300;
 const DEFAULT_BPM: u16 = 120;
+/// MIDI clock pulses per quarter note.
+pub const PPQN: u16 = 24;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -45,7 +48,107 @@ impl Bpm {
     /// Returns the interval in microseconds between MIDI Clock (0xF8) messages.
     /// MIDI Clock runs at 24 PPQN (pulses per quarter note).
     pub fn tick_interval_us(&self) -> u32 {
-        60_000_000 / (self.value as u32 * 24)
+        60_000_000 / (self.value as u32 * PPQN as u32)
+    }
+}
+
+/// Most taps averaged by [`TapTempo`].
+pub const MAX_TAPS: usize = 8;
+const DEFAULT_TAPS: usize = 4;
+/// A pause longer than the slowest tempo starts a new tempo.
+const DEFAULT_TAP_TIMEOUT_US: u64 = 2_500_000;
+/// Deviation from the average, in percent, above which an interval is an outlier.
+const TAP_TOLERANCE: u64 = 25;
+
+/// Computes a tempo from the timestamps of taps.
+///
+/// The intervals between the last `taps` taps are averaged. An interval that
+/// deviates from the average by more than a quarter, like a missed or doubled
+/// tap, is dropped; a second one in a row starts over at the new tempo.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TapTempo {
+    taps: usize,
+    timeout_us: u64,
+    last_tap_us: Option<u64>,
+    intervals: [u64; MAX_TAPS - 1],
+    count: usize,
+    next: usize,
+    outlier: bool,
+}
+
+impl Default for TapTempo {
+    fn default() -> Self {
+        Self::new(DEFAULT_TAPS, DEFAULT_TAP_TIMEOUT_US)
+    }
+}
+
+impl TapTempo {
+    /// `taps` is clamped to 2..=[`MAX_TAPS`], a pause of `timeout_us` resets.
+    pub fn new(taps: usize, timeout_us: u64) -> Self {
+        TapTempo {
+            taps: taps.clamp(2, MAX_TAPS),
+            timeout_us,
+            last_tap_us: None,
+            intervals: [0; MAX_TAPS - 1],
+            count: 0,
+            next: 0,
+            outlier: false,
+        }
+    }
+
+    pub fn set_taps(&mut self, taps: usize) {
+        *self = Self::new(taps, self.timeout_us);
+    }
+
+    pub fn set_timeout_us(&mut self, timeout_us: u64) {
+        self.timeout_us = timeout_us;
+    }
+
+    /// Registers a tap at `now_us`, returns the tempo in BPM once there are two
+    /// taps.
+    pub fn tap(&mut self, now_us: u64) -> Option<u16> {
+        let last = self.last_tap_us.replace(now_us);
+        let interval = match last {
+            Some(last) if now_us.saturating_sub(last) < self.timeout_us => now_us - last,
+            _ => {
+                self.clear();
+                return None;
+            }
+        };
+        if interval == 0 {
+            return None;
+        }
+        if let Some(average) = self.average() {
+            let deviation = interval.abs_diff(average);
+            if deviation * 100 > average * TAP_TOLERANCE {
+                if !self.outlier {
+                    self.outlier = true;
+                    return None;
+                }
+                self.clear();
+            }
+        }
+        self.outlier = false;
+        self.intervals[self.next] = interval;
+        self.next = (self.next + 1) % (self.taps - 1);
+        self.count = (self.count + 1).min(self.taps - 1);
+        let average = self.average()?;
+        let bpm = (60_000_000 + average / 2) / average;
+        Some(bpm.clamp(MIN_BPM as u64, MAX_BPM as u64) as u16)
+    }
+
+    fn clear(&mut self) {
+        self.count = 0;
+        self.next = 0;
+        self.outlier = false;
+    }
+
+    fn average(&self) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.intervals[..self.count].iter().sum::<u64>() / self.count as u64)
     }
 }
 
@@ -106,4 +209,44 @@ mod tests {
         let bpm = Bpm::new(60);
         assert_eq!(bpm.tick_interval_us(), 41666);
     }
+
+    #[test]
+    fn test_tap_tempo_averages_taps() {
+        let mut tap = TapTempo::default();
+        assert_eq!(tap.tap(1_000_000), None);
+        // 500 ms is 120 BPM
+        assert_eq!(tap.tap(1_500_000), Some(120));
+        assert_eq!(tap.tap(2_010_000), Some(119));
+        assert_eq!(tap.tap(2_500_000), Some(120));
+        // only the last three intervals are averaged
+        assert_eq!(tap.tap(2_980_000), Some(122));
+        assert_eq!(tap.tap(3_460_000), Some(124));
+    }
+
+    #[test]
+    fn test_tap_tempo_timeout() {
+        let mut tap = TapTempo::new(2, 1_000_000);
+        assert_eq!(tap.tap(0), None);
+        assert_eq!(tap.tap(600_000), Some(100));
+        assert_eq!(tap.tap(1_600_000), None);
+        assert_eq!(tap.tap(2_000_000), Some(150));
+        // the tempo is clamped
+        assert_eq!(tap.tap(2_100_000), None);
+        assert_eq!(tap.tap(2_200_000), Some(300));
+    }
+
+    #[test]
+    fn test_tap_tempo_rejects_outliers() {
+        let mut tap = TapTempo::default();
+        for (i, now) in [0, 500_000, 1_000_000].into_iter().enumerate() {
+            assert_eq!(tap.tap(now), (i > 0).then_some(120));
+        }
+        // a missed tap is dropped
+        assert_eq!(tap.tap(2_000_000), None);
+        assert_eq!(tap.tap(2_500_000), Some(120));
+        // a new tempo is taken after two taps
+        assert_eq!(tap.tap(3_166_667), None);
+        assert_eq!(tap.tap(3_833_333), Some(90));
+        assert_eq!(tap.tap(4_500_000), Some(90));
+    }
 }
@@ -1,6 +1,7 @@
 use crate::{
     button::{Button, ButtonMessageType, ButtonType},
     handler::ChannelMessages,
+    ump::{self, UmpMode},
     ChannelOrAll,
 };
 
@@ -10,7 +11,7 @@ use midi2::{
     prelude::*,
     sysex7::Sysex7,
     system_common::{ActiveSensing, Continue, Reset, Start, Stop, TimingClock},
-    BytesMessage,
+    BytesMessage, UmpMessage,
 };
 
 const MAX_MIDI_ID: u8 = 127;
@@ -32,6 +33,7 @@ pub struct ButtonMessages<'a> {
     action: Action,
     channel_messages: ChannelMessages,
     standard_note_off: bool,
+    program_offset: u8,
 }
 
 impl<'a> ButtonMessages<'a> {
@@ -42,6 +44,7 @@ impl<'a> ButtonMessages<'a> {
             action,
             channel_messages: ChannelMessages::new(ch),
             standard_note_off: false,
+            program_offset: 0,
         }
     }
     pub fn new_with_options(
@@ -56,8 +59,15 @@ impl<'a> ButtonMessages<'a> {
             action,
             channel_messages: ChannelMessages::new(ch),
             standard_note_off,
+            program_offset: 0,
         }
     }
+    /// Adds `offset` to the program of a Program Change button, wrapping
+    /// within the 7-bit program range.
+    pub fn with_program_offset(mut self, offset: u8) -> Self {
+        self.program_offset = offset;
+        self
+    }
     pub fn next<'buf>(
         &mut self,
         buffer: &'buf mut [u8],
@@ -104,7 +114,15 @@ impl<'a> ButtonMessages<'a> {
                 Ok(None)
             }
             ButtonMessageType::ProgramChange => {
-                self.button.program_change(&self.action, channel, buffer)
+                if let Action::Pressed = self.action {
+                    let program =
+                        self.button.midi_id.wrapping_add(self.program_offset) & MAX_MIDI_ID;
+                    let mut m = ProgramChange::try_new_with_buffer(buffer)?;
+                    m.set_channel(channel);
+                    m.set_program(u7::new(program));
+                    return Ok(Some(m.into()));
+                }
+                Ok(None)
             }
             ButtonMessageType::ControlChange => {
                 if let Action::Pressed = self.action {
@@ -282,6 +300,7 @@ impl<'a> ButtonMessages<'a> {
             ButtonMessageType::ProgramChangeOffsetDecr => Ok(None),
             ButtonMessageType::BPMIncr => Ok(None),
             ButtonMessageType::BPMDecr => Ok(None),
+            ButtonMessageType::TapTempo => Ok(None),
             ButtonMessageType::OpenDeckPresetChange => Ok(None),
 
             ButtonMessageType::NoMessage => Ok(None),
@@ -289,6 +308,19 @@ impl<'a> ButtonMessages<'a> {
             ButtonMessageType::MMCPlayStop => Ok(None),
         }
     }
+    /// Same as [`ButtonMessages::next`], rendered as UMP for `group`.
+    pub fn next_ump<'buf>(
+        &mut self,
+        mode: UmpMode,
+        group: u4,
+        buffer: &'buf mut [u32],
+    ) -> Result<Option<UmpMessage<&'buf [u32]>>, BufferOverflow> {
+        let bytes = &mut [0; ump::MAX_BYTES];
+        match self.next(bytes)? {
+            Some(m) => ump::from_bytes(mode, group, m.data(), buffer).map(Some),
+            None => Ok(None),
+        }
+    }
 }
 
 impl Button {
@@ -521,6 +553,26 @@ mod tests {
         let mut m = button.handle(Action::Released);
         assert_eq!(m.next(&mut buf), Ok(None));
     }
+    #[test]
+    fn test_program_change_offset() {
+        let mut buf = [0x00u8; 8];
+        let mut button = Button {
+            button_type: ButtonType::Momentary,
+            message_type: ButtonMessageType::ProgramChange,
+            midi_id: 0x7E,
+            value: 0x7F,
+            channel: ChannelOrAll::default(),
+            state: ButtonState::default(),
+        };
+        let mut m = button
+            .handle_with_options(Action::Pressed, false, None)
+            .with_program_offset(1);
+        assert_eq!(m.next(&mut buf).unwrap().unwrap().data(), [0xC0, 0x7F]);
+        let mut m = button
+            .handle_with_options(Action::Pressed, false, None)
+            .with_program_offset(3);
+        assert_eq!(m.next(&mut buf).unwrap().unwrap().data(), [0xC0, 0x01]);
+    }
 
     #[test]
     fn test_control_change() {
@@ -127,4 +127,6 @@ pub enum ButtonMessageType {
     BPMIncr = 0x1B,
     BPMDecr = 0x1C,
     MMCPlayStop = 0x1D,
+    /// Sets the BPM from the intervals between presses.
+    TapTempo = 0x1E,
 }
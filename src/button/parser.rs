@@ -1,7 +1,7 @@
 use crate::{
     button::{ButtonMessageType, ButtonSection, ButtonSectionId, ButtonType},
     parser::OpenDeckParseError,
-    ChannelOrAll, MessageStatus, Section,
+    MessageStatus, Section,
 };
 
 impl TryFrom<Section> for ButtonSection {
@@ -9,15 +9,15 @@ impl TryFrom<Section> for ButtonSection {
     fn try_from(v: Section) -> Result<Self, Self::Error> {
         if let Ok(id) = ButtonSectionId::try_from(v.id) {
             match id {
-                ButtonSectionId::MidiId => Ok(ButtonSection::MidiId(v.value as u8)),
+                ButtonSectionId::MidiId => v.data_byte().map(ButtonSection::MidiId),
                 ButtonSectionId::MessageType => ButtonMessageType::try_from(v.value)
                     .map(ButtonSection::MessageType)
                     .map_err(OpenDeckParseError::new_value_err),
                 ButtonSectionId::Type => ButtonType::try_from(v.value)
                     .map(ButtonSection::Type)
                     .map_err(OpenDeckParseError::new_value_err),
-                ButtonSectionId::Value => Ok(ButtonSection::Value(v.value as u8)),
-                ButtonSectionId::Channel => Ok(ButtonSection::Channel(ChannelOrAll::from(v.value))),
+                ButtonSectionId::Value => v.data_byte().map(ButtonSection::Value),
+                ButtonSectionId::Channel => v.channel().map(ButtonSection::Channel),
             }
         } else {
             Err(OpenDeckParseError::StatusError(MessageStatus::SectionError))
@@ -0,0 +1,143 @@
+//! Host side of the protocol: renders [`OpenDeckRequest`](crate::OpenDeckRequest)s for a
+//! board and parses the [`OpenDeckResponse`]s it sends back. [`DeviceSession`] builds a
+//! complete configuration session on top of them.
+
+use crate::{parser::OpenDeckParseError, Amount, MessageStatus, OpenDeckResponse};
+use heapless::Vec;
+
+pub mod model;
+pub mod parser;
+pub mod renderer;
+pub mod session;
+
+pub use model::DeviceModel;
+pub use parser::ResponseParser;
+pub use renderer::RequestRenderer;
+pub use session::{DeviceSession, SessionError, SessionOptions, Transport};
+
+/// Message part requesting every part of an `Amount::All` request.
+pub const PART_ALL: u8 = 0x7F;
+/// Message part requesting every part followed by an empty terminating message.
+pub const PART_ALL_WITH_END: u8 = 0x7E;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClientError {
+    /// The message isn't a well-formed OpenDeck response.
+    Parse(OpenDeckParseError),
+    /// The board answered with an error status.
+    Status(MessageStatus),
+    /// The message doesn't answer the request it was parsed for.
+    UnexpectedResponse,
+    /// A part of a multi-part response arrived out of order.
+    UnexpectedPart(u8),
+    /// The values don't fit into the collector.
+    Overflow,
+}
+
+impl From<OpenDeckParseError> for ClientError {
+    fn from(value: OpenDeckParseError) -> Self {
+        ClientError::Parse(value)
+    }
+}
+
+/// Joins the parts of an `Amount::All` response into a single list of values.
+///
+/// The collection is complete once `expected` values arrived or the board sent the
+/// [`PART_ALL_WITH_END`] terminator.
+pub struct MultiPartCollector<const N: usize> {
+    values: Vec<u16, N>,
+    expected: usize,
+    next_part: u8,
+    complete: bool,
+}
+
+impl<const N: usize> MultiPartCollector<N> {
+    pub fn new(expected: usize) -> Self {
+        MultiPartCollector {
+            values: Vec::new(),
+            expected,
+            next_part: 0,
+            complete: expected == 0,
+        }
+    }
+
+    /// Adds a parsed response, returns true once all values are collected.
+    pub fn push(&mut self, response: &OpenDeckResponse) -> Result<bool, ClientError> {
+        match response {
+            OpenDeckResponse::Configuration(_, Amount::All(PART_ALL_WITH_END), _, values)
+                if values.is_empty() =>
+            {
+                self.complete = true;
+            }
+            OpenDeckResponse::Configuration(_, Amount::All(part), _, values) => {
+                if self.complete || *part != self.next_part {
+                    return Err(ClientError::UnexpectedPart(*part));
+                }
+                self.values
+                    .extend_from_slice(values)
+                    .map_err(|_| ClientError::Overflow)?;
+                self.next_part += 1;
+                self.complete = self.values.len() >= self.expected;
+            }
+            _ => return Err(ClientError::UnexpectedResponse),
+        }
+        Ok(self.complete)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn values(&self) -> &[u16] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<u16, N> {
+        self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{button::ButtonSection, Block, NewValues, Wish};
+
+    fn part(part: u8, values: &[u16]) -> OpenDeckResponse {
+        OpenDeckResponse::Configuration(
+            Wish::Get,
+            Amount::All(part),
+            Block::Button(0, ButtonSection::MidiId(0)),
+            NewValues::from_slice(values).unwrap(),
+        )
+    }
+
+    #[test]
+    fn should_collect_parts_until_expected_count() {
+        let mut collector = MultiPartCollector::<4>::new(3);
+        assert_eq!(collector.push(&part(0, &[1, 2])), Ok(false));
+        assert_eq!(collector.push(&part(1, &[3])), Ok(true));
+        assert_eq!(collector.values(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn should_complete_on_terminator() {
+        let mut collector = MultiPartCollector::<4>::new(4);
+        assert_eq!(collector.push(&part(0, &[1, 2])), Ok(false));
+        assert_eq!(collector.push(&part(PART_ALL_WITH_END, &[])), Ok(true));
+        assert_eq!(collector.into_values().as_slice(), &[1, 2]);
+    }
+
+    #[test]
+    fn should_reject_out_of_order_parts_and_overflow() {
+        let mut collector = MultiPartCollector::<2>::new(4);
+        assert_eq!(
+            collector.push(&part(1, &[1])),
+            Err(ClientError::UnexpectedPart(1))
+        );
+        assert_eq!(
+            collector.push(&part(0, &[1, 2, 3])),
+            Err(ClientError::Overflow)
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use crate::{
+    analog::AnalogSection,
+    config::{get_block, set_block, FirmwareVersion, GlobalConfig, Preset},
+    encoder::EncoderSection,
+    global::{GlobalSection, MidiIndex, PresetIndex},
+    led::{LedIndex, LedSection},
+    parser::OpenDeckParseError,
+    Block, BlockId, MessageStatus, NrOfSupportedComponents, Section,
+};
+use heapless::Vec;
+
+/// Host-side copy of a board's configuration, filled by
+/// [`DeviceSession::read`](super::DeviceSession::read).
+///
+/// The capacities are upper bounds, the board may have fewer presets and
+/// components than the model can hold.
+#[derive(Clone)]
+pub struct DeviceModel<
+    const P: usize,
+    const B: usize,
+    const A: usize,
+    const E: usize,
+    const L: usize,
+> {
+    firmware_version: FirmwareVersion,
+    components: NrOfSupportedComponents,
+    nr_of_presets: usize,
+    presets: Vec<Preset<B, A, E, L>, P>,
+    global: GlobalConfig,
+}
+
+impl<const P: usize, const B: usize, const A: usize, const E: usize, const L: usize>
+    DeviceModel<P, B, A, E, L>
+{
+    /// Creates a model with default values, returns `None` if the board doesn't fit.
+    pub fn new(
+        firmware_version: FirmwareVersion,
+        components: NrOfSupportedComponents,
+        nr_of_presets: usize,
+    ) -> Option<Self> {
+        if nr_of_presets > P
+            || components.buttons > B
+            || components.encoders > E
+            || components.analog > A
+            || components.leds > L
+        {
+            return None;
+        }
+        let mut presets = Vec::new();
+        for _ in 0..nr_of_presets {
+            presets.push(Preset::default()).ok()?;
+        }
+        Some(DeviceModel {
+            firmware_version,
+            components,
+            nr_of_presets,
+            presets,
+            global: GlobalConfig::default(),
+        })
+    }
+
+    pub fn firmware_version(&self) -> FirmwareVersion {
+        self.firmware_version
+    }
+
+    pub fn components(&self) -> NrOfSupportedComponents {
+        self.components
+    }
+
+    pub fn nr_of_presets(&self) -> usize {
+        self.nr_of_presets
+    }
+
+    pub fn preset(&self, index: usize) -> Option<&Preset<B, A, E, L>> {
+        self.presets.get(index)
+    }
+
+    pub fn global(&self) -> &GlobalConfig {
+        &self.global
+    }
+
+    /// Returns the value of the section addressed by `block`, the value carried by
+    /// `block` itself is ignored. Global blocks ignore `preset`.
+    pub fn get(&self, preset: usize, block: Block) -> Option<u16> {
+        if !self.contains(block) {
+            return None;
+        }
+        get_block(&self.presets, &self.global, preset_of(preset, block), block)
+    }
+
+    /// Applies `block` to `preset`, returns false if the board has no such value.
+    pub fn set(&mut self, preset: usize, block: Block) -> bool {
+        if !self.contains(block) {
+            return false;
+        }
+        set_block(
+            &mut self.presets,
+            &mut self.global,
+            preset_of(preset, block),
+            block,
+        )
+    }
+
+    /// Every per-preset value of the board, with the values of the model.
+    pub(crate) fn preset_blocks(&self, preset: usize) -> impl Iterator<Item = Block> + '_ {
+        let c = self.components;
+        let buttons = (0..c.buttons as u16).flat_map(|i| sections(BlockId::Button, i));
+        let encoders = (0..c.encoders as u16).flat_map(|i| sections(BlockId::Encoder, i));
+        let analogs = (0..c.analog as u16).flat_map(|i| sections(BlockId::Analog, i));
+        let leds = (0..c.leds as u16).flat_map(|i| sections(BlockId::Led, i));
+        buttons
+            .chain(encoders)
+            .chain(analogs)
+            .chain(leds)
+            .filter_map(move |block| with_value(block, self.get(preset, block)?))
+    }
+
+    /// Every global value of the board, with the values of the model.
+    pub(crate) fn global_blocks(&self) -> impl Iterator<Item = Block> + '_ {
+        let midi = (0..=u8::MAX.into())
+            .filter_map(|i| MidiIndex::try_from(i).ok())
+            .map(|i| Block::Global(GlobalSection::Midi(i, 0)));
+        let presets = (0..=u8::MAX.into())
+            .filter_map(|i| PresetIndex::try_from(i).ok())
+            .map(|i| Block::Global(GlobalSection::Presets(i, 0)));
+        let leds = (0..=u8::MAX.into())
+            .filter_map(|i| LedIndex::try_from(i).ok())
+            .map(|i| Block::Led(u16::from(i), LedSection::Global(0)));
+        midi.chain(presets)
+            .chain(leds)
+            .filter_map(|block| with_value(block, self.get(0, block)?))
+    }
+
+    fn contains(&self, block: Block) -> bool {
+        let c = self.components;
+        match block {
+            Block::Button(i, _) => (i as usize) < c.buttons,
+            Block::Encoder(i, _) => (i as usize) < c.encoders,
+            Block::Analog(i, _) => (i as usize) < c.analog,
+            Block::Led(_, LedSection::Global(_)) => true,
+            Block::Led(i, _) => (i as usize) < c.leds,
+            Block::Global(_) => true,
+            Block::Display | Block::Touchscreen => false,
+        }
+    }
+}
+
+/// Global values are kept next to the first preset.
+fn preset_of(preset: usize, block: Block) -> usize {
+    match block {
+        Block::Global(_) | Block::Led(_, LedSection::Global(_)) => 0,
+        _ => preset,
+    }
+}
+
+/// Every section the protocol defines for component `index` of `block_id`, in
+/// the order of the section IDs. The MSB sections of the one-byte protocol and
+/// the global and reserved LED sections are left out.
+pub(crate) fn sections(block_id: BlockId, index: u16) -> impl Iterator<Item = Block> {
+    let block_id = block_id as u8;
+    (0..=u8::MAX)
+        .map_while(
+            move |id| match Block::from_parts(block_id, index, Section { id, value: 0 }) {
+                Err(OpenDeckParseError::StatusError(MessageStatus::SectionError)) => None,
+                block => Some(block.ok()),
+            },
+        )
+        .flatten()
+        .filter(|block| {
+            !matches!(
+                block,
+                Block::Encoder(_, EncoderSection::MidiIdMSB(_))
+                    | Block::Analog(
+                        _,
+                        AnalogSection::MidiIdMSB(_)
+                            | AnalogSection::LowerCCLimitMSB(_)
+                            | AnalogSection::UpperCCLimitMSB(_)
+                    )
+                    | Block::Led(_, LedSection::Global(_) | LedSection::Reserved(_))
+            )
+        })
+}
+
+/// Rebuilds `block` with the section carrying `value`, as received over SysEx.
+pub(crate) fn with_value(block: Block, value: u16) -> Option<Block> {
+    let (index, block_id, section) = block.into_parts();
+    Block::from_parts(
+        block_id as u8,
+        index,
+        Section {
+            id: section.id,
+            value,
+        },
+    )
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{button::ButtonSection, global::PresetIndex, ChannelOrAll};
+
+    fn model() -> DeviceModel<2, 4, 1, 1, 2> {
+        DeviceModel::new(
+            FirmwareVersion {
+                major: 1,
+                minor: 0,
+                revision: 0,
+            },
+            NrOfSupportedComponents {
+                buttons: 3,
+                encoders: 1,
+                analog: 1,
+                leds: 2,
+                touchscreen_buttons: 0,
+            },
+            2,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn should_reject_boards_that_dont_fit() {
+        let components = model().components();
+        let version = model().firmware_version();
+        assert!(DeviceModel::<1, 4, 1, 1, 2>::new(version, components, 2).is_none());
+        assert!(DeviceModel::<2, 2, 1, 1, 2>::new(version, components, 2).is_none());
+    }
+
+    #[test]
+    fn should_only_address_components_of_the_board() {
+        let mut model = model();
+        assert!(model.set(1, Block::Button(2, ButtonSection::MidiId(9))));
+        assert_eq!(
+            model.get(1, Block::Button(2, ButtonSection::MidiId(0))),
+            Some(9)
+        );
+        assert_eq!(
+            model.get(0, Block::Button(2, ButtonSection::MidiId(0))),
+            Some(2)
+        );
+        // the model has room for a fourth button, the board doesn't
+        assert!(!model.set(0, Block::Button(3, ButtonSection::MidiId(9))));
+        assert_eq!(
+            model.get(0, Block::Button(3, ButtonSection::MidiId(0))),
+            None
+        );
+        assert!(!model.set(2, Block::Button(0, ButtonSection::MidiId(9))));
+    }
+
+    #[test]
+    fn should_enumerate_blocks_with_values() {
+        let mut model = model();
+        model.set(
+            0,
+            Block::Button(1, ButtonSection::Channel(ChannelOrAll::Channel(4))),
+        );
+        model.set(
+            0,
+            Block::Global(GlobalSection::Presets(PresetIndex::Active, 1)),
+        );
+
+        let c = model.components();
+        assert_eq!(sections(BlockId::Button, 0).count(), 5);
+        assert_eq!(sections(BlockId::Encoder, 0).count(), 12);
+        assert_eq!(sections(BlockId::Analog, 0).count(), 9);
+        assert_eq!(sections(BlockId::Led, 0).count(), 6);
+        assert_eq!(
+            model.preset_blocks(0).count(),
+            c.buttons * 5 + c.encoders * 12 + c.analog * 9 + c.leds * 6
+        );
+        assert_eq!(model.global_blocks().count(), 16 + 4 + 3);
+        assert!(model
+            .preset_blocks(0)
+            .any(|b| b == Block::Button(1, ButtonSection::Channel(ChannelOrAll::Channel(4)))));
+        assert!(model
+            .global_blocks()
+            .any(|b| b == Block::Global(GlobalSection::Presets(PresetIndex::Active, 1))));
+    }
+}
@@ -0,0 +1,498 @@
+use super::ClientError;
+use crate::{
+    config::FirmwareVersion,
+    parser::{check_frame, OpenDeckParseError, OpenDeckParser},
+    Amount, BlockId, ByteOrder, HardwareUid, MessageStatus, NewValues, NrOfSupportedComponents,
+    OpenDeckRequest, OpenDeckResponse, SpecialRequest, SpecialResponse, ValueSize, Wish,
+    COMPONENT_INFO_ID, SPECIAL_REQ_MSG_SIZE,
+};
+
+const LENGTH_ERROR: ClientError = ClientError::Parse(OpenDeckParseError::StatusError(
+    MessageStatus::MessageLengthError,
+));
+
+/// Parses responses sent from the board to the host.
+///
+/// Special responses and configuration responses share their wish bytes, so every
+/// response is parsed against the request it answers.
+pub struct ResponseParser {
+    value_size: ValueSize,
+}
+
+impl ResponseParser {
+    pub fn new(value_size: ValueSize) -> Self {
+        ResponseParser { value_size }
+    }
+
+    pub fn parse(
+        &self,
+        request: &OpenDeckRequest,
+        buf: &[u8],
+    ) -> Result<OpenDeckResponse, ClientError> {
+        check_frame(buf)?;
+
+        let status = MessageStatus::try_from(ByteOrder::Status.get(buf))?;
+        if status != MessageStatus::Response {
+            return Err(ClientError::Status(status));
+        }
+
+        match request {
+            // a backup is streamed as Set messages followed by the Backup special response
+            OpenDeckRequest::Special(SpecialRequest::Backup)
+                if buf.len() > SPECIAL_REQ_MSG_SIZE =>
+            {
+                self.parse_configuration(Wish::Set, buf)
+            }
+            OpenDeckRequest::Special(special) => self
+                .parse_special(*special, buf)
+                .map(OpenDeckResponse::Special),
+            OpenDeckRequest::Configuration(wish, _, _) => self.parse_configuration(*wish, buf),
+            OpenDeckRequest::ComponentInfo => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    /// Parses a Component Info message, returns the block and index of the component.
+    /// https://github.com/shanteacontrols/OpenDeck/wiki/Sysex-Configuration#component-info-messages
+    pub fn parse_component_info(&self, buf: &[u8]) -> Result<(BlockId, u16), ClientError> {
+        check_frame(buf)?;
+        if ByteOrder::Status.get(buf) != MessageStatus::Response as u8
+            || ByteOrder::Wish.get(buf) != COMPONENT_INFO_ID
+        {
+            return Err(ClientError::UnexpectedResponse);
+        }
+        let start = ByteOrder::Amount as usize;
+        if buf.len() != start + 1 + self.value_size as usize + 1 {
+            return Err(LENGTH_ERROR);
+        }
+        let block = match buf[start] {
+            x if x == BlockId::Global as u8 => BlockId::Global,
+            x if x == BlockId::Button as u8 => BlockId::Button,
+            x if x == BlockId::Encoder as u8 => BlockId::Encoder,
+            x if x == BlockId::Analog as u8 => BlockId::Analog,
+            x if x == BlockId::Led as u8 => BlockId::Led,
+            x if x == BlockId::Display as u8 => BlockId::Display,
+            x if x == BlockId::Touchscreen as u8 => BlockId::Touchscreen,
+            _ => {
+                return Err(ClientError::Parse(OpenDeckParseError::StatusError(
+                    MessageStatus::BlockError,
+                )))
+            }
+        };
+        Ok((block, self.value_size.parse_at(buf, start + 1, 0)))
+    }
+
+    fn parse_special(
+        &self,
+        special: SpecialRequest,
+        buf: &[u8],
+    ) -> Result<SpecialResponse, ClientError> {
+        // the board acknowledges ConnectionClose with a handshake response
+        let wish = match special {
+            SpecialRequest::ConnectionClose => SpecialRequest::Handshake,
+            special => special,
+        };
+        if ByteOrder::Wish.get(buf) != wish as u8 {
+            return Err(ClientError::UnexpectedResponse);
+        }
+
+        let values = self.values(buf, ByteOrder::Amount as usize)?;
+        let value = |i: usize| values.get(i).copied().ok_or(LENGTH_ERROR);
+        let firmware_version = |offset: usize| -> Result<FirmwareVersion, ClientError> {
+            Ok(FirmwareVersion {
+                major: value(offset)? as u8,
+                minor: value(offset + 1)? as u8,
+                revision: value(offset + 2)? as u8,
+            })
+        };
+        let hardware_uid = |offset: usize| -> Result<HardwareUid, ClientError> {
+            let mut uid = 0;
+            for i in offset..offset + 4 {
+                uid = (uid << 8) | (value(i)? as u32 & 0xFF);
+            }
+            Ok(HardwareUid(uid))
+        };
+
+        match special {
+            SpecialRequest::ConnectionClose | SpecialRequest::Handshake => {
+                Ok(SpecialResponse::Handshake)
+            }
+            SpecialRequest::ValueSize => {
+                value(0)?;
+                Ok(SpecialResponse::ValueSize)
+            }
+            SpecialRequest::ValuesPerMessage => {
+                Ok(SpecialResponse::ValuesPerMessage(value(0)? as u8))
+            }
+            SpecialRequest::FirmwareVersion => {
+                Ok(SpecialResponse::FirmwareVersion(firmware_version(0)?))
+            }
+            SpecialRequest::HardwareUID => Ok(SpecialResponse::HardwareUID(hardware_uid(0)?)),
+            SpecialRequest::FirmwareVersionAndHardwareUUID => {
+                Ok(SpecialResponse::FirmwareVersionAndHardwareUUID(
+                    firmware_version(0)?,
+                    hardware_uid(3)?,
+                ))
+            }
+            SpecialRequest::NrOfSupportedComponents => Ok(
+                SpecialResponse::NrOfSupportedComponents(NrOfSupportedComponents {
+                    buttons: value(0)? as usize,
+                    encoders: value(1)? as usize,
+                    analog: value(2)? as usize,
+                    leds: value(3)? as usize,
+                    touchscreen_buttons: value(4)? as usize,
+                }),
+            ),
+            SpecialRequest::NrOfSupportedPresets => {
+                Ok(SpecialResponse::NrOfSupportedPresets(value(0)? as usize))
+            }
+            SpecialRequest::BootloaderSupport => {
+                Ok(SpecialResponse::BootloaderSupport(value(0)? != 0))
+            }
+            SpecialRequest::Backup => Ok(SpecialResponse::Backup),
+            SpecialRequest::SerialNumber => Ok(SpecialResponse::SerialNumber(
+                values.iter().map(|v| *v as u8).collect(),
+            )),
+            SpecialRequest::RestoreStart => Ok(SpecialResponse::RestoreStart),
+            SpecialRequest::RestoreEnd => Ok(SpecialResponse::RestoreEnd),
+            // the board doesn't answer these
+            SpecialRequest::Reboot
+            | SpecialRequest::BootloaderMode
+            | SpecialRequest::FactoryReset => Err(ClientError::UnexpectedResponse),
+        }
+    }
+
+    fn parse_configuration(&self, wish: Wish, buf: &[u8]) -> Result<OpenDeckResponse, ClientError> {
+        let start = ByteOrder::Index as usize + 2 * self.value_size as usize;
+        if buf.len() < start + 1 {
+            return Err(LENGTH_ERROR);
+        }
+        if Wish::try_from(ByteOrder::Wish.get(buf))? != wish {
+            return Err(ClientError::UnexpectedResponse);
+        }
+        let amount = Amount::try_from((ByteOrder::Amount.get(buf), ByteOrder::Part.get(buf)))?;
+        let block = OpenDeckParser::new(self.value_size).parse_block(buf)?;
+        let values = self.values(buf, start)?;
+        Ok(OpenDeckResponse::Configuration(wish, amount, block, values))
+    }
+
+    /// Decodes the values between `start` and the SysEx end byte.
+    fn values(&self, buf: &[u8], start: usize) -> Result<NewValues, ClientError> {
+        let size = self.value_size as usize;
+        let len = buf.len() - 1 - start;
+        if !len.is_multiple_of(size) {
+            return Err(LENGTH_ERROR);
+        }
+        let mut values = NewValues::new();
+        for i in 0..len / size {
+            values
+                .push(self.value_size.parse_at(buf, start, i))
+                .map_err(|_| LENGTH_ERROR)?;
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        button::ButtonSection,
+        client::{MultiPartCollector, RequestRenderer, PART_ALL, PART_ALL_WITH_END},
+        config::{tests::NoopHandler, Config},
+        global::{GlobalSection, PresetIndex},
+        renderer::OpenDeckRenderer,
+        Block, MAX_MESSAGE_SIZE,
+    };
+    use heapless::Vec;
+    use midi2::Data;
+
+    type TestConfig = Config<1, 40, 1, 1, 1, NoopHandler>;
+
+    fn config() -> TestConfig {
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 2,
+            revision: 3,
+        };
+        let mut config = TestConfig::new(version, 0x12345678, NoopHandler);
+        config.set_serial_number(&[7, 8, 9]);
+        send(
+            &mut config,
+            OpenDeckRequest::Special(SpecialRequest::Handshake),
+        );
+        config
+    }
+
+    /// Renders `req`, feeds it to the board and parses every response.
+    fn send(
+        config: &mut TestConfig,
+        req: OpenDeckRequest,
+    ) -> Vec<Result<OpenDeckResponse, ClientError>, 8> {
+        let request_buffer = &mut [0; MAX_MESSAGE_SIZE];
+        let request = RequestRenderer::new(ValueSize::TwoBytes, request_buffer)
+            .render(req)
+            .unwrap();
+        let parser = ResponseParser::new(ValueSize::TwoBytes);
+        let mut responses = config.process_sysex(request.data());
+        let mut parsed = Vec::new();
+        let buffer = &mut [0; MAX_MESSAGE_SIZE];
+        while let Some(response) = responses.next(buffer, config).unwrap() {
+            parsed.push(parser.parse(&req, response.data())).unwrap();
+        }
+        parsed
+    }
+
+    #[test]
+    fn should_parse_special_responses() {
+        let mut config = config();
+        let cases = [
+            (SpecialRequest::Handshake, SpecialResponse::Handshake),
+            (SpecialRequest::ValueSize, SpecialResponse::ValueSize),
+            (
+                SpecialRequest::ValuesPerMessage,
+                SpecialResponse::ValuesPerMessage(32),
+            ),
+            (
+                SpecialRequest::FirmwareVersionAndHardwareUUID,
+                SpecialResponse::FirmwareVersionAndHardwareUUID(
+                    FirmwareVersion {
+                        major: 1,
+                        minor: 2,
+                        revision: 3,
+                    },
+                    HardwareUid(0x12345678),
+                ),
+            ),
+            (
+                SpecialRequest::NrOfSupportedComponents,
+                SpecialResponse::NrOfSupportedComponents(NrOfSupportedComponents {
+                    buttons: 40,
+                    encoders: 1,
+                    analog: 1,
+                    leds: 1,
+                    touchscreen_buttons: 0,
+                }),
+            ),
+            (
+                SpecialRequest::NrOfSupportedPresets,
+                SpecialResponse::NrOfSupportedPresets(1),
+            ),
+            (
+                SpecialRequest::BootloaderSupport,
+                SpecialResponse::BootloaderSupport(true),
+            ),
+            (
+                SpecialRequest::SerialNumber,
+                SpecialResponse::SerialNumber(Vec::from_slice(&[7, 8, 9]).unwrap()),
+            ),
+            (SpecialRequest::ConnectionClose, SpecialResponse::Handshake),
+        ];
+        for (req, res) in cases {
+            assert_eq!(
+                send(&mut config, OpenDeckRequest::Special(req)).as_slice(),
+                &[Ok(OpenDeckResponse::Special(res))]
+            );
+        }
+    }
+
+    #[test]
+    fn should_parse_single_get_and_set() {
+        let mut config = config();
+        let block = Block::Button(3, ButtonSection::MidiId(42));
+        assert_eq!(
+            send(
+                &mut config,
+                OpenDeckRequest::Configuration(Wish::Set, Amount::Single, block)
+            )
+            .as_slice(),
+            &[Ok(OpenDeckResponse::Configuration(
+                Wish::Set,
+                Amount::Single,
+                block,
+                NewValues::new()
+            ))]
+        );
+
+        let block = Block::Button(3, ButtonSection::MidiId(0));
+        assert_eq!(
+            send(
+                &mut config,
+                OpenDeckRequest::Configuration(Wish::Get, Amount::Single, block)
+            )
+            .as_slice(),
+            &[Ok(OpenDeckResponse::Configuration(
+                Wish::Get,
+                Amount::Single,
+                block,
+                NewValues::from_slice(&[42]).unwrap()
+            ))]
+        );
+    }
+
+    #[test]
+    fn should_collect_multi_part_responses() {
+        let mut config = config();
+        let block = Block::Button(0, ButtonSection::MidiId(0));
+
+        for part in [PART_ALL, PART_ALL_WITH_END] {
+            let responses = send(
+                &mut config,
+                OpenDeckRequest::Configuration(Wish::Get, Amount::All(part), block),
+            );
+            let expected_messages = if part == PART_ALL_WITH_END { 3 } else { 2 };
+            assert_eq!(responses.len(), expected_messages);
+
+            let mut collector = MultiPartCollector::<64>::new(40);
+            for response in responses.iter() {
+                collector.push(response.as_ref().unwrap()).unwrap();
+            }
+            assert!(collector.is_complete());
+            let expected: Vec<u16, 64> = (0..40).collect();
+            assert_eq!(collector.values(), expected.as_slice());
+        }
+
+        // the terminator is an empty message with part 0x7E
+        let responses = send(
+            &mut config,
+            OpenDeckRequest::Configuration(Wish::Get, Amount::All(PART_ALL_WITH_END), block),
+        );
+        assert_eq!(
+            responses.last(),
+            Some(&Ok(OpenDeckResponse::Configuration(
+                Wish::Get,
+                Amount::All(PART_ALL_WITH_END),
+                block,
+                NewValues::new()
+            )))
+        );
+    }
+
+    #[test]
+    fn should_parse_backup_stream() {
+        let mut config = config();
+        let block = Block::Button(5, ButtonSection::MidiId(99));
+        send(
+            &mut config,
+            OpenDeckRequest::Configuration(Wish::Set, Amount::Single, block),
+        );
+
+        let request_buffer = &mut [0; MAX_MESSAGE_SIZE];
+        let req = OpenDeckRequest::Special(SpecialRequest::Backup);
+        let request = RequestRenderer::new(ValueSize::TwoBytes, request_buffer)
+            .render(req)
+            .unwrap();
+        let parser = ResponseParser::new(ValueSize::TwoBytes);
+        let mut responses = config.process_sysex(request.data());
+        let buffer = &mut [0; MAX_MESSAGE_SIZE];
+        let backup = OpenDeckResponse::Special(SpecialResponse::Backup);
+        let mut parsed = core::iter::from_fn(|| {
+            let response = responses.next(buffer, &mut config).unwrap()?;
+            Some(parser.parse(&req, response.data()).unwrap())
+        });
+
+        // the Set messages are framed by a Backup response on either side
+        assert_eq!(parsed.next(), Some(backup.clone()));
+        let mut found = false;
+        for response in parsed.by_ref() {
+            if response == backup {
+                break;
+            }
+            let OpenDeckResponse::Configuration(Wish::Set, Amount::Single, b, _) = response else {
+                panic!("unexpected backup message {:?}", response);
+            };
+            found |= b == block;
+        }
+        assert!(found);
+        assert_eq!(parsed.next(), None);
+    }
+
+    #[test]
+    fn should_report_error_status() {
+        let mut config = config();
+        // block 0x09 doesn't exist, the board answers with BlockError
+        let request = [
+            0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xF7,
+        ];
+        let mut responses = config.process_sysex(&request);
+        let buffer = &mut [0; MAX_MESSAGE_SIZE];
+        let response = responses.next(buffer, &mut config).unwrap().unwrap();
+        let parser = ResponseParser::new(ValueSize::TwoBytes);
+        assert_eq!(
+            parser.parse(
+                &OpenDeckRequest::Configuration(
+                    Wish::Get,
+                    Amount::Single,
+                    Block::Button(0, ButtonSection::MidiId(0)),
+                ),
+                response.data()
+            ),
+            Err(ClientError::Status(MessageStatus::BlockError))
+        );
+
+        assert_eq!(
+            parser.parse(
+                &OpenDeckRequest::Special(SpecialRequest::Handshake),
+                &[0xF0, 0x00, 0x53, 0x43, 0x03, 0x00, 0x01, 0xF7]
+            ),
+            Err(ClientError::Status(MessageStatus::HandshakeError))
+        );
+    }
+
+    #[test]
+    fn should_reject_malformed_responses() {
+        let parser = ResponseParser::new(ValueSize::TwoBytes);
+        let handshake = OpenDeckRequest::Special(SpecialRequest::Handshake);
+        assert_eq!(
+            parser.parse(
+                &handshake,
+                &[0xF0, 0x00, 0x53, 0x44, 0x01, 0x00, 0x01, 0xF7]
+            ),
+            Err(ClientError::Parse(OpenDeckParseError::WrongManufacturer))
+        );
+        assert_eq!(
+            parser.parse(
+                &handshake,
+                &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x02, 0xF7]
+            ),
+            Err(ClientError::UnexpectedResponse)
+        );
+        // odd number of payload bytes with two-byte values
+        assert_eq!(
+            parser.parse(
+                &OpenDeckRequest::Special(SpecialRequest::ValuesPerMessage),
+                &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x03, 0x20, 0xF7]
+            ),
+            Err(LENGTH_ERROR)
+        );
+        assert_eq!(
+            parser.parse(
+                &OpenDeckRequest::Configuration(
+                    Wish::Get,
+                    Amount::Single,
+                    Block::Global(GlobalSection::Presets(PresetIndex::Active, 0))
+                ),
+                &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x00, 0x00, 0x00, 0xF7]
+            ),
+            Err(LENGTH_ERROR)
+        );
+    }
+
+    #[test]
+    fn should_parse_component_info() {
+        let buffer = &mut [0; MAX_MESSAGE_SIZE];
+        let message = OpenDeckRenderer::new(ValueSize::TwoBytes, buffer)
+            .render_component_info(BlockId::Analog, 130)
+            .unwrap()
+            .unwrap();
+        let parser = ResponseParser::new(ValueSize::TwoBytes);
+        assert_eq!(
+            parser.parse_component_info(message.data()),
+            Ok((BlockId::Analog, 130))
+        );
+        assert_eq!(
+            parser.parse_component_info(&[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x01, 0xF7]),
+            Err(ClientError::UnexpectedResponse)
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{
+    renderer::{Buffer, RenderError},
+    Amount, Block, MessageStatus, OpenDeckRequest, ValueSize, Wish, M_ID_0, M_ID_1, M_ID_2,
+    PARAMS_PER_MESSAGE,
+};
+
+use heapless::Vec;
+use midi2::{prelude::*, sysex7::Sysex7};
+
+/// Renders requests sent from the host to the board.
+pub struct RequestRenderer<'buf> {
+    buffer: &'buf mut [u8],
+    value_size: ValueSize,
+}
+
+impl<'buf> RequestRenderer<'buf> {
+    pub fn new<'a: 'buf>(value_size: ValueSize, buffer: &'a mut [u8]) -> Self {
+        RequestRenderer { value_size, buffer }
+    }
+
+    pub fn render(self, req: OpenDeckRequest) -> Result<Sysex7<&'buf mut [u8]>, RenderError> {
+        self.render_with_values(req, &[])
+    }
+
+    /// Renders a Set of all values of the section of `block` in `part`, one value
+    /// per component starting at index `part * 32`.
+    pub fn render_set_all(
+        self,
+        part: u8,
+        block: Block,
+        values: &[u16],
+    ) -> Result<Sysex7<&'buf mut [u8]>, RenderError> {
+        if values.len() > PARAMS_PER_MESSAGE {
+            return Err(RenderError::InvalidValue);
+        }
+        let req = OpenDeckRequest::Configuration(Wish::Set, Amount::All(part), block);
+        self.render_with_values(req, values)
+    }
+
+    pub(crate) fn render_with_values(
+        self,
+        req: OpenDeckRequest,
+        values: &[u16],
+    ) -> Result<Sysex7<&'buf mut [u8]>, RenderError> {
+        let mut buf: Buffer = Vec::new();
+        buf.push(M_ID_0).unwrap();
+        buf.push(M_ID_1).unwrap();
+        buf.push(M_ID_2).unwrap();
+        buf.push(MessageStatus::Request as u8).unwrap();
+        buf.push(0).unwrap();
+
+        match req {
+            OpenDeckRequest::Special(special) => {
+                buf.push(special as u8).unwrap();
+            }
+            OpenDeckRequest::Configuration(wish, amount, block) => {
+                buf.push(wish as u8).unwrap();
+                buf = amount.push(buf);
+                buf = block.push(buf, &self.value_size)?;
+                for value in values {
+                    buf = self.value_size.push(*value, buf)?;
+                }
+            }
+            // Component info messages are only ever sent by the board.
+            OpenDeckRequest::ComponentInfo => return Err(RenderError::InvalidValue),
+        }
+
+        let mut m = Sysex7::try_new_with_buffer(self.buffer)?;
+        m.try_set_payload(buf.into_iter().map(u7::new))?;
+        Ok(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        button::ButtonSection,
+        global::{GlobalSection, PresetIndex},
+        parser::OpenDeckParser,
+        Amount, Block, SpecialRequest, Wish, MAX_MESSAGE_SIZE,
+    };
+    use midi2::Data;
+
+    fn render(value_size: ValueSize, req: OpenDeckRequest) -> Vec<u8, MAX_MESSAGE_SIZE> {
+        let buffer = &mut [0; MAX_MESSAGE_SIZE];
+        let m = RequestRenderer::new(value_size, buffer)
+            .render(req)
+            .unwrap();
+        Vec::from_slice(m.data()).unwrap()
+    }
+
+    #[test]
+    fn should_render_special_requests() {
+        assert_eq!(
+            render(
+                ValueSize::TwoBytes,
+                OpenDeckRequest::Special(SpecialRequest::Handshake)
+            )
+            .as_slice(),
+            &[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0xF7]
+        );
+        assert_eq!(
+            render(
+                ValueSize::TwoBytes,
+                OpenDeckRequest::Special(SpecialRequest::Backup)
+            )
+            .as_slice(),
+            &[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x1B, 0xF7]
+        );
+    }
+
+    #[test]
+    fn should_render_configuration_requests() {
+        // wiki example: set button 6 MIDI ID to 5
+        assert_eq!(
+            render(
+                ValueSize::TwoBytes,
+                OpenDeckRequest::Configuration(
+                    Wish::Set,
+                    Amount::Single,
+                    Block::Button(6, ButtonSection::MidiId(5))
+                )
+            )
+            .as_slice(),
+            &[
+                0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x01, 0x02, 0x00, 0x06, 0x00, 0x05,
+                0xF7
+            ]
+        );
+        assert_eq!(
+            render(
+                ValueSize::OneByte,
+                OpenDeckRequest::Configuration(
+                    Wish::Get,
+                    Amount::All(0x7E),
+                    Block::Button(0, ButtonSection::MidiId(0))
+                )
+            )
+            .as_slice(),
+            &[0xF0, 0x00, 0x53, 0x43, 0x00, 0x7E, 0x00, 0x01, 0x01, 0x02, 0x00, 0x00, 0xF7]
+        );
+    }
+
+    #[test]
+    fn should_round_trip_through_the_board_parser() {
+        let requests = [
+            OpenDeckRequest::Special(SpecialRequest::FirmwareVersionAndHardwareUUID),
+            OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                Block::Global(GlobalSection::Presets(PresetIndex::Active, 3)),
+            ),
+            OpenDeckRequest::Configuration(
+                Wish::Get,
+                Amount::All(1),
+                Block::Button(0, ButtonSection::Value(0)),
+            ),
+            OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                Block::Button(200, ButtonSection::Value(100)),
+            ),
+        ];
+        let parser = OpenDeckParser::new(ValueSize::TwoBytes);
+        for req in requests {
+            let bytes = render(ValueSize::TwoBytes, req);
+            assert_eq!(parser.parse(&bytes), Ok(req));
+        }
+    }
+
+    #[test]
+    fn should_render_set_all() {
+        let buffer = &mut [0; MAX_MESSAGE_SIZE];
+        let m = RequestRenderer::new(ValueSize::TwoBytes, buffer)
+            .render_set_all(1, Block::Button(0, ButtonSection::MidiId(0)), &[5, 0x100])
+            .unwrap();
+        assert_eq!(
+            m.data(),
+            &[
+                0xF0, 0x00, 0x53, 0x43, 0x00, 0x01, 0x01, 0x01, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x05, 0x02, 0x00, 0xF7
+            ]
+        );
+        let parser = OpenDeckParser::new(ValueSize::TwoBytes);
+        assert_eq!(
+            parser.parse_values(m.data()).unwrap().as_slice(),
+            &[5, 0x100]
+        );
+
+        let buffer = &mut [0; MAX_MESSAGE_SIZE];
+        assert_eq!(
+            RequestRenderer::new(ValueSize::TwoBytes, buffer)
+                .render_set_all(0, Block::Button(0, ButtonSection::MidiId(0)), &[0; 33])
+                .err(),
+            Some(RenderError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn should_refuse_component_info() {
+        let buffer = &mut [0; MAX_MESSAGE_SIZE];
+        assert_eq!(
+            RequestRenderer::new(ValueSize::TwoBytes, buffer)
+                .render(OpenDeckRequest::ComponentInfo)
+                .err(),
+            Some(RenderError::InvalidValue)
+        );
+    }
+}
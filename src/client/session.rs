@@ -0,0 +1,642 @@
+use super::{
+    model::{sections, with_value},
+    ClientError, DeviceModel, RequestRenderer, ResponseParser,
+};
+use crate::{
+    global::{GlobalSection, PresetIndex},
+    parser::OpenDeckParseError,
+    renderer::RenderError,
+    Amount, Block, BlockId, OpenDeckRequest, OpenDeckResponse, SpecialRequest, SpecialResponse,
+    ValueSize, Wish, MAX_MESSAGE_SIZE, PARAMS_PER_MESSAGE,
+};
+use midi2::Data;
+
+/// Connection to a board, e.g. a MIDI port of the host.
+pub trait Transport {
+    type Error;
+    /// Sends a complete SysEx message.
+    fn send(&mut self, message: &[u8]) -> Result<(), Self::Error>;
+    /// Waits up to `timeout_ms` for the next SysEx message, copies it into `buffer`
+    /// and returns its length. Returns `None` if nothing arrived in time.
+    fn receive(&mut self, buffer: &mut [u8], timeout_ms: u32)
+        -> Result<Option<usize>, Self::Error>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SessionOptions {
+    /// How long to wait for each response message.
+    pub timeout_ms: u32,
+    /// How often a request is repeated after a timeout.
+    pub retries: u8,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        SessionOptions {
+            timeout_ms: 1000,
+            retries: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SessionError<E> {
+    Transport(E),
+    /// The board didn't answer, retries included.
+    Timeout,
+    Client(ClientError),
+    Render(RenderError),
+    /// The board has more presets or components than the model can hold.
+    Capacity,
+}
+
+impl<E> From<ClientError> for SessionError<E> {
+    fn from(value: ClientError) -> Self {
+        SessionError::Client(value)
+    }
+}
+
+impl<E> From<RenderError> for SessionError<E> {
+    fn from(value: RenderError) -> Self {
+        SessionError::Render(value)
+    }
+}
+
+/// Drives a configuration session with a board: handshake, reading the
+/// configuration into a [`DeviceModel`], writing changes back and closing.
+pub struct DeviceSession<T: Transport> {
+    transport: T,
+    options: SessionOptions,
+    parser: ResponseParser,
+}
+
+impl<T: Transport> DeviceSession<T> {
+    pub fn new(transport: T, options: SessionOptions) -> Self {
+        DeviceSession {
+            transport,
+            options,
+            parser: ResponseParser::new(ValueSize::TwoBytes),
+        }
+    }
+
+    pub fn handshake(&mut self) -> Result<(), SessionError<T::Error>> {
+        self.request(OpenDeckRequest::Special(SpecialRequest::Handshake))
+            .map(|_| ())
+    }
+
+    /// Closes the connection and hands back the transport.
+    pub fn close(mut self) -> Result<T, SessionError<T::Error>> {
+        self.request(OpenDeckRequest::Special(SpecialRequest::ConnectionClose))?;
+        Ok(self.transport)
+    }
+
+    /// Sends `req` and returns the first response to it.
+    pub fn request(
+        &mut self,
+        req: OpenDeckRequest,
+    ) -> Result<OpenDeckResponse, SessionError<T::Error>> {
+        let mut result = None;
+        self.exchange(req, &[], &mut |response| {
+            result = Some(response);
+            true
+        })?;
+        result.ok_or(SessionError::Timeout)
+    }
+
+    /// Reads a single value.
+    pub fn get(&mut self, block: Block) -> Result<u16, SessionError<T::Error>> {
+        match self.request(OpenDeckRequest::Configuration(
+            Wish::Get,
+            Amount::Single,
+            block,
+        ))? {
+            OpenDeckResponse::Configuration(_, _, _, values) if !values.is_empty() => Ok(values[0]),
+            _ => Err(ClientError::UnexpectedResponse.into()),
+        }
+    }
+
+    /// Writes a single value.
+    pub fn set(&mut self, block: Block) -> Result<(), SessionError<T::Error>> {
+        self.request(OpenDeckRequest::Configuration(
+            Wish::Set,
+            Amount::Single,
+            block,
+        ))
+        .map(|_| ())
+    }
+
+    /// Reads the section of `block` for all components, `apply` receives the index
+    /// and value of each one.
+    pub fn get_all(
+        &mut self,
+        block: Block,
+        apply: &mut dyn FnMut(u16, u16),
+    ) -> Result<(), SessionError<T::Error>> {
+        let req =
+            OpenDeckRequest::Configuration(Wish::Get, Amount::All(super::PART_ALL_WITH_END), block);
+        self.exchange(req, &[], &mut |response| match response {
+            OpenDeckResponse::Configuration(_, Amount::All(super::PART_ALL_WITH_END), _, _) => true,
+            OpenDeckResponse::Configuration(_, Amount::All(part), _, values) => {
+                let start = part as usize * PARAMS_PER_MESSAGE;
+                for (i, value) in values.iter().enumerate() {
+                    apply((start + i) as u16, *value);
+                }
+                false
+            }
+            _ => false,
+        })
+    }
+
+    /// Writes the section of `block` for all components, `values` holds one value
+    /// per component starting at index 0.
+    pub fn set_all(&mut self, block: Block, values: &[u16]) -> Result<(), SessionError<T::Error>> {
+        for (part, values) in values.chunks(PARAMS_PER_MESSAGE).enumerate() {
+            let req = OpenDeckRequest::Configuration(Wish::Set, Amount::All(part as u8), block);
+            self.exchange(req, values, &mut |_| true)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the whole configuration of the board.
+    ///
+    /// Presets are read by activating them one after another, so a board with
+    /// more than one preset temporarily switches presets while it is read. The
+    /// board persists the active preset, the original one is restored
+    /// afterwards, also when reading fails.
+    pub fn read<const P: usize, const B: usize, const A: usize, const E: usize, const L: usize>(
+        &mut self,
+    ) -> Result<DeviceModel<P, B, A, E, L>, SessionError<T::Error>> {
+        let components = match self.special(SpecialRequest::NrOfSupportedComponents)? {
+            SpecialResponse::NrOfSupportedComponents(components) => components,
+            _ => return Err(ClientError::UnexpectedResponse.into()),
+        };
+        let nr_of_presets = match self.special(SpecialRequest::NrOfSupportedPresets)? {
+            SpecialResponse::NrOfSupportedPresets(presets) => presets,
+            _ => return Err(ClientError::UnexpectedResponse.into()),
+        };
+        let firmware_version = match self.special(SpecialRequest::FirmwareVersion)? {
+            SpecialResponse::FirmwareVersion(version) => version,
+            _ => return Err(ClientError::UnexpectedResponse.into()),
+        };
+        let mut model = DeviceModel::new(firmware_version, components, nr_of_presets)
+            .ok_or(SessionError::Capacity)?;
+
+        let globals: heapless::Vec<Block, 32> = model.global_blocks().collect();
+        for block in globals {
+            let value = self.get(block)?;
+            Self::apply(&mut model, 0, block, value)?;
+        }
+
+        let active = model.get(0, ACTIVE_PRESET).unwrap_or(0);
+        let result = self.read_presets(&mut model);
+        if nr_of_presets > 1 {
+            // restore the active preset even if reading failed, the error of
+            // the read is reported in favour of this one
+            let restored = self.set(active_preset(active));
+            result?;
+            restored?;
+        } else {
+            result?;
+        }
+        Ok(model)
+    }
+
+    /// Reads the components of every preset into `model`, leaves the last
+    /// preset active.
+    fn read_presets<
+        const P: usize,
+        const B: usize,
+        const A: usize,
+        const E: usize,
+        const L: usize,
+    >(
+        &mut self,
+        model: &mut DeviceModel<P, B, A, E, L>,
+    ) -> Result<(), SessionError<T::Error>> {
+        let nr_of_presets = model.nr_of_presets();
+        for preset in 0..nr_of_presets {
+            if nr_of_presets > 1 {
+                self.set(active_preset(preset as u16))?;
+            }
+            let sections = sections(BlockId::Button, 0)
+                .chain(sections(BlockId::Encoder, 0))
+                .chain(sections(BlockId::Analog, 0))
+                .chain(sections(BlockId::Led, 0));
+            for section in sections {
+                let mut result = Ok(());
+                self.get_all(section, &mut |index, value| {
+                    let block = match section {
+                        Block::Button(_, s) => Block::Button(index, s),
+                        Block::Encoder(_, s) => Block::Encoder(index, s),
+                        Block::Analog(_, s) => Block::Analog(index, s),
+                        Block::Led(_, s) => Block::Led(index, s),
+                        block => block,
+                    };
+                    if result.is_ok() {
+                        result = Self::apply(model, preset, block, value);
+                    }
+                })?;
+                result?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes every value that differs between `before` and `after`, returns the
+    /// number of values written.
+    pub fn write_changes<
+        const P: usize,
+        const B: usize,
+        const A: usize,
+        const E: usize,
+        const L: usize,
+    >(
+        &mut self,
+        before: &DeviceModel<P, B, A, E, L>,
+        after: &DeviceModel<P, B, A, E, L>,
+    ) -> Result<usize, SessionError<T::Error>> {
+        let mut written = 0;
+        // the preset the board currently edits
+        let mut current = before.get(0, ACTIVE_PRESET).unwrap_or(0);
+        let active = current;
+        for preset in 0..after.nr_of_presets() {
+            for block in after.preset_blocks(preset) {
+                if before.get(preset, block) == after.get(preset, block) {
+                    continue;
+                }
+                if preset as u16 != current {
+                    self.set(active_preset(preset as u16))?;
+                    current = preset as u16;
+                }
+                self.set(block)?;
+                written += 1;
+            }
+        }
+        for block in after.global_blocks() {
+            if matches!(
+                block,
+                Block::Global(GlobalSection::Presets(PresetIndex::Active, _))
+            ) {
+                continue;
+            }
+            if before.get(0, block) != after.get(0, block) {
+                self.set(block)?;
+                written += 1;
+            }
+        }
+        // the active preset goes last, it was changed to reach the other presets
+        let target = after.get(0, ACTIVE_PRESET).unwrap_or(0);
+        if target != active {
+            written += 1;
+        }
+        if target != current || target != active {
+            self.set(active_preset(target))?;
+        }
+        Ok(written)
+    }
+
+    fn special(
+        &mut self,
+        special: SpecialRequest,
+    ) -> Result<SpecialResponse, SessionError<T::Error>> {
+        match self.request(OpenDeckRequest::Special(special))? {
+            OpenDeckResponse::Special(response) => Ok(response),
+            _ => Err(ClientError::UnexpectedResponse.into()),
+        }
+    }
+
+    fn apply<const P: usize, const B: usize, const A: usize, const E: usize, const L: usize>(
+        model: &mut DeviceModel<P, B, A, E, L>,
+        preset: usize,
+        block: Block,
+        value: u16,
+    ) -> Result<(), SessionError<T::Error>> {
+        match with_value(block, value) {
+            Some(block) if model.set(preset, block) => Ok(()),
+            _ => Err(ClientError::UnexpectedResponse.into()),
+        }
+    }
+
+    /// Sends `req`, followed by `values` for a Set of all values, and feeds its
+    /// responses to `on_response` until it returns true.
+    ///
+    /// The request is repeated when no response arrives in time. Messages that
+    /// don't answer `req`, like component info messages or late responses to an
+    /// earlier request, are skipped.
+    fn exchange(
+        &mut self,
+        req: OpenDeckRequest,
+        values: &[u16],
+        on_response: &mut dyn FnMut(OpenDeckResponse) -> bool,
+    ) -> Result<(), SessionError<T::Error>> {
+        let mut attempts = 0;
+        'attempt: loop {
+            let buffer = &mut [0; MAX_MESSAGE_SIZE];
+            let message = RequestRenderer::new(ValueSize::TwoBytes, buffer)
+                .render_with_values(req, values)?;
+            self.transport
+                .send(message.data())
+                .map_err(SessionError::Transport)?;
+
+            loop {
+                let buffer = &mut [0; MAX_MESSAGE_SIZE];
+                let received = self
+                    .transport
+                    .receive(buffer, self.options.timeout_ms)
+                    .map_err(SessionError::Transport)?;
+                let Some(len) = received else {
+                    if attempts == self.options.retries {
+                        return Err(SessionError::Timeout);
+                    }
+                    attempts += 1;
+                    #[cfg(feature = "defmt")]
+                    defmt::warn!("no response to {}, retrying", req);
+                    continue 'attempt;
+                };
+                match self
+                    .parser
+                    .parse(&req, &buffer[..len.min(MAX_MESSAGE_SIZE)])
+                {
+                    Ok(response) => {
+                        if on_response(response) {
+                            return Ok(());
+                        }
+                    }
+                    Err(ClientError::UnexpectedResponse)
+                    | Err(ClientError::Parse(OpenDeckParseError::NoSysex))
+                    | Err(ClientError::Parse(OpenDeckParseError::WrongManufacturer)) => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+    }
+}
+
+const ACTIVE_PRESET: Block = Block::Global(GlobalSection::Presets(PresetIndex::Active, 0));
+
+fn active_preset(preset: u16) -> Block {
+    Block::Global(GlobalSection::Presets(PresetIndex::Active, preset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        button::ButtonSection,
+        config::{
+            tests::{test_config, NoopHandler},
+            Config,
+        },
+        encoder::EncoderSection,
+        global::MidiIndex,
+        led::LedIndex,
+        led::LedSection,
+        ChannelOrAll,
+    };
+    use heapless::{Deque, Vec};
+
+    type TestConfig = Config<2, 40, 2, 3, 4, NoopHandler>;
+    type TestModel = DeviceModel<2, 40, 2, 3, 4>;
+
+    /// Runs the board in-process, every sent request is answered right away.
+    struct Loopback {
+        config: TestConfig,
+        pending: Deque<Vec<u8, MAX_MESSAGE_SIZE>, 4>,
+        /// Number of requests to lose before the board sees them.
+        drop: usize,
+        /// Fails requests other than sets while this preset is active.
+        fail_in_preset: Option<usize>,
+        sets: usize,
+    }
+
+    impl Loopback {
+        fn new() -> Self {
+            Loopback {
+                config: test_config(),
+                pending: Deque::new(),
+                drop: 0,
+                fail_in_preset: None,
+                sets: 0,
+            }
+        }
+
+        fn device_get(&mut self, block: Block) -> u16 {
+            match self.config.process_req(OpenDeckRequest::Configuration(
+                Wish::Get,
+                Amount::Single,
+                block,
+            )) {
+                Some(OpenDeckResponse::Configuration(_, _, _, values)) => values[0],
+                _ => panic!("no value for {:?}", block),
+            }
+        }
+
+        fn device_set(&mut self, block: Block) {
+            self.config.process_req(OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                block,
+            ));
+        }
+    }
+
+    impl Transport for Loopback {
+        type Error = ();
+
+        fn send(&mut self, message: &[u8]) -> Result<(), ()> {
+            if self.drop > 0 {
+                self.drop -= 1;
+                return Ok(());
+            }
+            let set = message.len() > 8 && message[6] == Wish::Set as u8;
+            if set {
+                self.sets += 1;
+            } else if self.fail_in_preset == Some(self.config.active_preset()) {
+                return Err(());
+            }
+            let mut responses = self.config.process_sysex(message);
+            let buffer = &mut [0; MAX_MESSAGE_SIZE];
+            while let Some(response) = responses.next(buffer, &mut self.config).unwrap() {
+                self.pending
+                    .push_back(Vec::from_slice(response.data()).unwrap())
+                    .unwrap();
+            }
+            Ok(())
+        }
+
+        fn receive(&mut self, buffer: &mut [u8], _timeout_ms: u32) -> Result<Option<usize>, ()> {
+            Ok(self.pending.pop_front().map(|message| {
+                buffer[..message.len()].copy_from_slice(&message);
+                message.len()
+            }))
+        }
+    }
+
+    fn session(transport: Loopback) -> DeviceSession<Loopback> {
+        let mut session = DeviceSession::new(transport, SessionOptions::default());
+        session.handshake().unwrap();
+        session
+    }
+
+    #[test]
+    fn should_read_the_board_into_a_model() {
+        let mut board = Loopback::new();
+        board.device_set(Block::Button(33, ButtonSection::MidiId(99)));
+        board.device_set(Block::Led(3, LedSection::Channel(ChannelOrAll::Channel(7))));
+        board.device_set(Block::Global(GlobalSection::Midi(
+            MidiIndex::GlobalMIDIchannel,
+            5,
+        )));
+        board.device_set(Block::Led(
+            LedIndex::EnableStartupAnimation as u16,
+            LedSection::Global(1),
+        ));
+        // preset 1 has its own value, preset 0 stays active
+        board.device_set(active_preset(1));
+        board.device_set(Block::Encoder(2, EncoderSection::UpperLimit(1000)));
+        board.device_set(active_preset(0));
+
+        let mut session = session(board);
+        let model: TestModel = session.read().unwrap();
+
+        assert_eq!(model.nr_of_presets(), 2);
+        assert_eq!(model.components().buttons, 40);
+        assert_eq!(
+            model.get(0, Block::Button(33, ButtonSection::MidiId(0))),
+            Some(99)
+        );
+        assert_eq!(
+            model.get(0, Block::Button(34, ButtonSection::MidiId(0))),
+            Some(34)
+        );
+        assert_eq!(
+            model.preset(0).unwrap().leds()[3].channel_direct(),
+            ChannelOrAll::Channel(7)
+        );
+        assert_eq!(
+            model.global().midi().global_channel(),
+            ChannelOrAll::Channel(4)
+        );
+        assert!(model.global().led().startup_animation());
+        assert_eq!(
+            model.get(1, Block::Encoder(2, EncoderSection::UpperLimit(0))),
+            Some(1000)
+        );
+        assert_ne!(
+            model.get(0, Block::Encoder(2, EncoderSection::UpperLimit(0))),
+            Some(1000)
+        );
+        // reading doesn't change the active preset
+        assert_eq!(session.close().unwrap().config.active_preset(), 0);
+    }
+
+    #[test]
+    fn should_restore_the_active_preset_when_reading_fails() {
+        let mut board = Loopback::new();
+        board.fail_in_preset = Some(1);
+        let mut session = session(board);
+        assert_eq!(
+            session.read::<2, 40, 2, 3, 4>().err(),
+            Some(SessionError::Transport(()))
+        );
+        assert_eq!(session.close().unwrap().config.active_preset(), 0);
+    }
+
+    #[test]
+    fn should_write_back_changed_values_only() {
+        let mut session = session(Loopback::new());
+        let before: TestModel = session.read().unwrap();
+        let mut after = before.clone();
+        assert!(after.set(0, Block::Button(39, ButtonSection::Value(12))));
+        assert!(after.set(
+            1,
+            Block::Analog(1, crate::analog::AnalogSection::MidiId(77))
+        ));
+        assert!(after.set(
+            0,
+            Block::Global(GlobalSection::Midi(MidiIndex::RunningStatus, 1))
+        ));
+
+        let sets = session.transport.sets;
+        assert_eq!(session.write_changes(&before, &after), Ok(3));
+        // switching to preset 1 and back to the active preset
+        assert_eq!(session.transport.sets - sets, 3 + 2);
+        assert_eq!(session.write_changes(&after, &after), Ok(0));
+
+        let mut board = session.close().unwrap();
+        assert_eq!(board.config.active_preset(), 0);
+        assert!(!board.config.sysex_enabled());
+        assert_eq!(
+            board.device_get(Block::Button(39, ButtonSection::Value(0))),
+            12
+        );
+        assert_eq!(
+            board.device_get(Block::Global(GlobalSection::Midi(
+                MidiIndex::RunningStatus,
+                0
+            ))),
+            1
+        );
+        board.device_set(active_preset(1));
+        assert_eq!(
+            board.device_get(Block::Analog(1, crate::analog::AnalogSection::MidiId(0))),
+            77
+        );
+    }
+
+    #[test]
+    fn should_set_all_values_in_parts() {
+        let mut session = session(Loopback::new());
+        let values: Vec<u16, 40> = (0..40).map(|i| 40 - i).collect();
+        let block = Block::Button(0, ButtonSection::MidiId(0));
+        assert_eq!(session.set_all(block, &values), Ok(()));
+        assert_eq!(session.transport.sets, 2);
+        assert_eq!(
+            session
+                .transport
+                .device_get(Block::Button(0, ButtonSection::MidiId(0))),
+            40
+        );
+        assert_eq!(
+            session
+                .transport
+                .device_get(Block::Button(39, ButtonSection::MidiId(0))),
+            1
+        );
+
+        assert_eq!(
+            session.set_all(block, &values[..3]),
+            Err(SessionError::Client(ClientError::Status(
+                crate::MessageStatus::MessageLengthError
+            )))
+        );
+    }
+
+    #[test]
+    fn should_retry_lost_requests() {
+        let mut board = Loopback::new();
+        board.drop = 2;
+        let mut session = DeviceSession::new(board, SessionOptions::default());
+        assert_eq!(session.handshake(), Ok(()));
+        assert!(session.transport.config.sysex_enabled());
+    }
+
+    #[test]
+    fn should_time_out_after_retries() {
+        let mut board = Loopback::new();
+        board.drop = 3;
+        let mut session = DeviceSession::new(board, SessionOptions::default());
+        assert_eq!(session.handshake(), Err(SessionError::Timeout));
+    }
+
+    #[test]
+    fn should_refuse_boards_larger_than_the_model() {
+        let mut session = session(Loopback::new());
+        assert_eq!(
+            session.read::<2, 39, 2, 3, 4>().err(),
+            Some(SessionError::Capacity)
+        );
+    }
+}
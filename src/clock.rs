@@ -0,0 +1,382 @@
+//! MIDI clock generation.
+//!
+//! [`ClockGenerator`] schedules `F8` Timing Clock messages at 24 PPQN from the
+//! monotonic microsecond timestamps of the firmware. The interval at most tempos
+//! isn't a whole number of microseconds, so each pulse is scheduled from the
+//! start of the current tempo instead of the previous pulse, which keeps the
+//! clock from drifting.
+//!
+//! [`ClockFollower`] estimates the tempo of a received clock, so the BPM can
+//! follow another device.
+
+use crate::{bpm::PPQN, midi_in::Realtime};
+
+const US_PER_MINUTE: u64 = 60_000_000;
+
+/// Intervals averaged by the follower, one quarter note.
+const FOLLOWER_WINDOW: usize = PPQN as usize;
+/// Intervals needed for a first estimate.
+const FOLLOWER_MIN_INTERVALS: usize = 6;
+/// Change of the estimated tempo, in tenths of a BPM, before the BPM follows.
+const FOLLOWER_HYSTERESIS: u32 = 7;
+/// Consecutive outliers after which the tempo is taken to have changed.
+const FOLLOWER_MAX_OUTLIERS: u8 = 3;
+/// Time without a pulse after which the clock is lost, three pulses at 30 BPM.
+pub const CLOCK_TIMEOUT_US: u64 = 250_000;
+
+/// Schedules MIDI clock pulses and tracks the transport.
+///
+/// The clock runs while the transport is stopped, so followers keep the tempo.
+/// Start restarts the clock phase, Continue keeps it.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ClockGenerator {
+    running: bool,
+    /// Time of the first pulse at the current tempo, set by the first poll.
+    anchor_us: Option<u64>,
+    /// Pulses sent since the anchor.
+    pulses: u64,
+    bpm: u16,
+}
+
+impl ClockGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the transport is running.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Starts the transport from the beginning, the next poll sends a pulse.
+    pub fn start(&mut self) {
+        self.running = true;
+        self.resync();
+    }
+
+    /// Schedules the pulses from the next poll, which sends one, instead of
+    /// catching up on pulses that weren't sent.
+    pub fn resync(&mut self) {
+        self.anchor_us = None;
+        self.pulses = 0;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    /// Continues the transport, keeping the clock phase.
+    pub fn resume(&mut self) {
+        self.running = true;
+    }
+
+    /// Returns whether a pulse is due at `now_us` at `bpm`, and counts it as sent.
+    ///
+    /// A late poll doesn't shift the following pulses, poll until it returns
+    /// `false` to catch up. A changed tempo applies from the last pulse, at 0 BPM
+    /// no pulses are sent and the first one at a tempo again is sent right away.
+    pub fn poll(&mut self, now_us: u64, bpm: u16) -> bool {
+        if bpm != self.bpm {
+            if let Some(anchor) = self.anchor_us {
+                if self.pulses > 0 {
+                    self.anchor_us = Some(anchor + self.offset_us(self.pulses - 1));
+                    self.pulses = 1;
+                }
+            }
+            self.bpm = bpm;
+        }
+        if bpm == 0 {
+            self.resync();
+            return false;
+        }
+        let anchor = *self.anchor_us.get_or_insert(now_us);
+        if now_us < anchor + self.offset_us(self.pulses) {
+            return false;
+        }
+        self.pulses += 1;
+        true
+    }
+
+    /// Time of the next pulse, `None` before the first poll or after a resync.
+    pub fn next_pulse_us(&self) -> Option<u64> {
+        self.anchor_us
+            .map(|anchor| anchor + self.offset_us(self.pulses))
+    }
+
+    /// Time of `pulse` after the anchor at the current tempo.
+    fn offset_us(&self, pulse: u64) -> u64 {
+        match self.bpm {
+            0 => 0,
+            bpm => pulse * US_PER_MINUTE / (bpm as u64 * PPQN as u64),
+        }
+    }
+}
+
+/// Estimates the tempo of a received MIDI clock.
+///
+/// The intervals of the last quarter note are averaged, an interval that is off
+/// the average by a half, like a lost or doubled pulse, is rejected. A few
+/// outliers in a row restart the estimate at the new tempo.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ClockFollower {
+    last_pulse_us: Option<u64>,
+    /// Time of the last clock or transport message.
+    last_received_us: Option<u64>,
+    intervals: [u32; FOLLOWER_WINDOW],
+    count: usize,
+    next: usize,
+    outliers: u8,
+    /// Tempo last reported, in tenths of a BPM.
+    reported: Option<u32>,
+}
+
+impl Default for ClockFollower {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClockFollower {
+    pub fn new() -> Self {
+        ClockFollower {
+            last_pulse_us: None,
+            last_received_us: None,
+            intervals: [0; FOLLOWER_WINDOW],
+            count: 0,
+            next: 0,
+            outliers: 0,
+            reported: None,
+        }
+    }
+
+    /// Forgets the received clock.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Whether a clock is being received.
+    pub fn is_following(&self) -> bool {
+        self.last_received_us.is_some()
+    }
+
+    /// Feeds a clock or transport message received at `now_us`, returns the
+    /// tempo in BPM when it changed.
+    pub fn receive(&mut self, realtime: Realtime, now_us: u64) -> Option<u16> {
+        self.last_received_us = Some(now_us);
+        match realtime {
+            Realtime::Clock => self.pulse(now_us),
+            // the first pulse after a jump may come late, don't measure across
+            Realtime::Start | Realtime::Continue => {
+                self.last_pulse_us = None;
+                None
+            }
+            Realtime::Stop => None,
+        }
+    }
+
+    /// Returns `true` once when no clock or transport message was received for
+    /// [`CLOCK_TIMEOUT_US`].
+    pub fn check_timeout(&mut self, now_us: u64) -> bool {
+        match self.last_received_us {
+            Some(last) if now_us.saturating_sub(last) >= CLOCK_TIMEOUT_US => {
+                self.reset();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn pulse(&mut self, now_us: u64) -> Option<u16> {
+        let last = self.last_pulse_us.replace(now_us)?;
+        let interval = now_us.saturating_sub(last).min(CLOCK_TIMEOUT_US) as u32;
+        if interval == 0 {
+            return None;
+        }
+        if let Some(average) = self.average() {
+            if self.count == FOLLOWER_WINDOW
+                && (interval < average * 2 / 3 || interval > average * 3 / 2)
+            {
+                self.outliers += 1;
+                if self.outliers < FOLLOWER_MAX_OUTLIERS {
+                    return None;
+                }
+                self.count = 0;
+                self.next = 0;
+            }
+        }
+        self.outliers = 0;
+        self.intervals[self.next] = interval;
+        self.next = (self.next + 1) % FOLLOWER_WINDOW;
+        self.count = (self.count + 1).min(FOLLOWER_WINDOW);
+        if self.count < FOLLOWER_MIN_INTERVALS {
+            return None;
+        }
+
+        let average = self.average()?;
+        let tenths = (10 * US_PER_MINUTE / (average as u64 * PPQN as u64)) as u32;
+        if self
+            .reported
+            .is_some_and(|reported| reported.abs_diff(tenths) < FOLLOWER_HYSTERESIS)
+        {
+            return None;
+        }
+        self.reported = Some(tenths);
+        Some(((tenths + 5) / 10) as u16)
+    }
+
+    fn average(&self) -> Option<u32> {
+        if self.count == 0 {
+            return None;
+        }
+        let sum: u64 = self.intervals[..self.count]
+            .iter()
+            .map(|&interval| interval as u64)
+            .sum();
+        Some((sum / self.count as u64) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pulses(generator: &mut ClockGenerator, from_us: u64, to_us: u64, bpm: u16) -> u64 {
+        let mut count = 0;
+        for now in from_us..to_us {
+            while generator.poll(now, bpm) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn test_no_drift() {
+        // 20833.33 µs per pulse at 120 BPM
+        let mut generator = ClockGenerator::new();
+        assert!(generator.poll(0, 120));
+        assert!(!generator.poll(20_832, 120));
+        assert!(generator.poll(20_833, 120));
+        assert_eq!(generator.next_pulse_us(), Some(41_666));
+        // a minute has exactly 2880 pulses, polled late by 3 ms each time
+        let mut sent = 2;
+        let mut now = 0;
+        while now < 60_000_000 {
+            now += 3_000;
+            while generator.poll(now, 120) {
+                sent += 1;
+            }
+        }
+        assert_eq!(sent, 2881);
+        assert_eq!(generator.next_pulse_us(), Some(60_020_833));
+    }
+
+    #[test]
+    fn test_tempo_change_retimes_from_last_pulse() {
+        let mut generator = ClockGenerator::new();
+        assert_eq!(pulses(&mut generator, 0, 50_000, 120), 3);
+        // the last pulse was at 41666 µs, at 60 BPM the next is 41666.67 µs later
+        assert!(!generator.poll(83_331, 60));
+        assert!(generator.poll(83_332, 60));
+        assert_eq!(generator.next_pulse_us(), Some(41_666 + 83_333));
+    }
+
+    #[test]
+    fn test_no_pulses_at_zero_bpm() {
+        let mut generator = ClockGenerator::new();
+        assert_eq!(pulses(&mut generator, 0, 30_000, 120), 2);
+        assert_eq!(pulses(&mut generator, 30_000, 100_000, 0), 0);
+        assert_eq!(generator.next_pulse_us(), None);
+        assert!(generator.poll(100_000, 120));
+        assert!(!generator.poll(100_001, 120));
+        assert_eq!(generator.next_pulse_us(), Some(120_833));
+    }
+
+    #[test]
+    fn test_transport() {
+        let mut generator = ClockGenerator::new();
+        assert!(!generator.is_running());
+        assert_eq!(pulses(&mut generator, 0, 30_000, 120), 2);
+        generator.start();
+        assert!(generator.is_running());
+        assert_eq!(generator.next_pulse_us(), None);
+        // the first pulse after Start is sent right away
+        assert!(generator.poll(30_000, 120));
+        assert_eq!(generator.next_pulse_us(), Some(50_833));
+        generator.stop();
+        assert!(!generator.is_running());
+        assert!(generator.poll(50_833, 120));
+        generator.resume();
+        assert!(generator.is_running());
+        assert_eq!(generator.next_pulse_us(), Some(71_666));
+    }
+
+    fn follow(
+        follower: &mut ClockFollower,
+        from_us: u64,
+        interval_us: u64,
+        pulses: u64,
+    ) -> Option<u16> {
+        let mut bpm = None;
+        for pulse in 0..pulses {
+            // up to ±500 µs of jitter
+            let jitter = [0, 500, 0, -500, 250, -250][pulse as usize % 6];
+            let now = (from_us + pulse * interval_us).saturating_add_signed(jitter);
+            if let Some(new) = follower.receive(Realtime::Clock, now) {
+                bpm = Some(new);
+            }
+        }
+        bpm
+    }
+
+    #[test]
+    fn test_follower_estimates_tempo() {
+        let mut follower = ClockFollower::new();
+        assert!(!follower.is_following());
+        // 20833 µs per pulse at 120 BPM
+        assert_eq!(follow(&mut follower, 0, 20_833, 48), Some(120));
+        assert!(follower.is_following());
+        // jitter doesn't move the tempo
+        assert_eq!(follow(&mut follower, 48 * 20_833, 20_833, 96), None);
+        // 17361 µs at 144 BPM
+        assert_eq!(follow(&mut follower, 144 * 20_833, 17_361, 48), Some(144));
+    }
+
+    #[test]
+    fn test_follower_rejects_outliers() {
+        let mut follower = ClockFollower::new();
+        assert_eq!(follow(&mut follower, 0, 20_833, 25), Some(120));
+        // a lost pulse
+        let last = 24 * 20_833;
+        assert_eq!(follower.receive(Realtime::Clock, last + 2 * 20_833), None);
+        assert_eq!(follow(&mut follower, last + 3 * 20_833, 20_833, 24), None);
+        // a tempo jump is followed after a few pulses
+        assert_eq!(follow(&mut follower, 100 * 20_833, 41_667, 24), Some(60));
+    }
+
+    #[test]
+    fn test_follower_clock_loss() {
+        let mut follower = ClockFollower::new();
+        follow(&mut follower, 0, 20_833, 24);
+        // with the jitter of the last pulse
+        let last = 23 * 20_833 - 250;
+        assert!(!follower.check_timeout(last + CLOCK_TIMEOUT_US - 1));
+        assert!(follower.check_timeout(last + CLOCK_TIMEOUT_US));
+        assert!(!follower.is_following());
+        assert!(!follower.check_timeout(last + 2 * CLOCK_TIMEOUT_US));
+        // the estimate starts over
+        assert_eq!(follower.receive(Realtime::Clock, 10_000_000), None);
+        assert_eq!(follow(&mut follower, 10_020_833, 20_833, 6), Some(120));
+
+        // the clock stops after a transport jump
+        let last = 10_020_833 + 5 * 20_833 - 250;
+        follower.receive(Realtime::Stop, last + 1_000);
+        follower.receive(Realtime::Start, last + 2_000);
+        assert!(follower.is_following());
+        assert!(!follower.check_timeout(last + 2_000 + CLOCK_TIMEOUT_US - 1));
+        assert!(follower.check_timeout(last + 2_000 + CLOCK_TIMEOUT_US));
+        assert!(!follower.is_following());
+    }
+}
@@ -1,14 +1,14 @@
 use crate::{
     analog::backup::AnalogBackupIterator,
     button::backup::ButtonBackupIterator,
-    config::{Config, Preset},
+    config::{split, Config, Preset},
     encoder::backup::EncoderBackupIterator,
     global::{
         backup::{GlobalMidiBackupIterator, GlobalPresetBackupIterator},
         GlobalSection, PresetIndex,
     },
-    led::backup::LedBackupIterator,
-    Amount, Block, NewValues, OpenDeckResponse, SpecialResponse, Wish,
+    led::backup::{GlobalLedBackupIterator, LedBackupIterator},
+    Amount, Block, NewValues, OpenDeckResponse, SpecialResponse, ValueSize, Wish,
 };
 
 enum BackupStatus {
@@ -17,6 +17,8 @@ enum BackupStatus {
     Presets,
     GlobalPresets,
     GlobalMidi,
+    GlobalLed,
+    ActivePreset,
     Done,
 }
 
@@ -31,7 +33,10 @@ pub struct ConfigBackupIterator<
     presets: PresetBackupIterator<B, A, E, L>,
     global_presets: GlobalPresetBackupIterator,
     global_midi: GlobalMidiBackupIterator,
+    global_led: GlobalLedBackupIterator,
     status: BackupStatus,
+    /// MSB section of the last 14-bit value, sent next with one-byte values.
+    pending: Option<Block>,
 }
 
 impl<const P: usize, const B: usize, const A: usize, const E: usize, const L: usize>
@@ -43,9 +48,44 @@ impl<const P: usize, const B: usize, const A: usize, const E: usize, const L: us
             presets: PresetBackupIterator::new(),
             global_presets: GlobalPresetBackupIterator::new(),
             global_midi: GlobalMidiBackupIterator::new(),
+            global_led: GlobalLedBackupIterator::new(),
             status: BackupStatus::Init,
+            pending: None,
         }
     }
+
+    /// Like [`Self::next`], but sends 14-bit values as their LSB and MSB sections
+    /// when the configuration uses one-byte values.
+    pub fn next_split<H: crate::SystemHandler>(
+        &mut self,
+        config: &mut Config<P, B, A, E, L, H>,
+    ) -> Option<OpenDeckResponse> {
+        if let Some(msb) = self.pending.take() {
+            return Some(OpenDeckResponse::Configuration(
+                Wish::Set,
+                Amount::Single,
+                msb,
+                NewValues::new(),
+            ));
+        }
+        let res = self.next(config);
+        if config.value_size != ValueSize::OneByte {
+            return res;
+        }
+        match res {
+            Some(OpenDeckResponse::Configuration(wish, amount, block, values)) => {
+                match split::split(block) {
+                    Some((lsb, msb)) => {
+                        self.pending = Some(msb);
+                        Some(OpenDeckResponse::Configuration(wish, amount, lsb, values))
+                    }
+                    None => Some(OpenDeckResponse::Configuration(wish, amount, block, values)),
+                }
+            }
+            res => res,
+        }
+    }
+
     pub fn next<H: crate::SystemHandler>(
         &mut self,
         config: &mut Config<P, B, A, E, L, H>,
@@ -99,11 +139,32 @@ impl<const P: usize, const B: usize, const A: usize, const E: usize, const L: us
             BackupStatus::GlobalMidi => {
                 let res = self.global_midi.next(&config.global.midi);
                 if res.is_none() {
-                    self.status = BackupStatus::Done;
-                    return Some(OpenDeckResponse::Special(SpecialResponse::Backup));
+                    self.status = BackupStatus::GlobalLed;
+                    return self.global_led.next(&config.global.led);
+                }
+                res
+            }
+            BackupStatus::GlobalLed => {
+                let res = self.global_led.next(&config.global.led);
+                if res.is_none() {
+                    self.status = BackupStatus::ActivePreset;
+                    // the preset switches above leave the last preset active on restore
+                    return Some(OpenDeckResponse::Configuration(
+                        Wish::Set,
+                        Amount::Single,
+                        Block::Global(GlobalSection::Presets(
+                            PresetIndex::Active,
+                            config.global.preset.current as u16,
+                        )),
+                        NewValues::new(),
+                    ));
                 }
                 res
             }
+            BackupStatus::ActivePreset => {
+                self.status = BackupStatus::Done;
+                Some(OpenDeckResponse::Special(SpecialResponse::Backup))
+            }
 
             BackupStatus::Done => None,
         }
@@ -201,10 +262,13 @@ mod tests {
     use crate::{
         analog::{AnalogMessageType, AnalogSection},
         button::{ButtonMessageType, ButtonSection, ButtonType},
-        config::{Config, FirmwareVersion},
+        config::{
+            tests::{test_config, NoopHandler},
+            Config, FirmwareVersion,
+        },
         encoder::{Accelleration, EncoderMessageType, EncoderSection},
         global::{GlobalSection, MidiIndex, PresetIndex},
-        led::{Color, LedSection},
+        led::{Color, LedIndex, LedSection},
         Amount, Block, ChannelOrAll, NewValues, Wish,
     };
 
@@ -775,6 +839,31 @@ mod tests {
             ))
         );
 
+        for index in [
+            LedIndex::BlinkWithMIDIClock,
+            LedIndex::EnableStartupAnimation,
+            LedIndex::UseMidiProgramChangeOffset,
+        ] {
+            assert_eq!(
+                iterator.next(config),
+                Some(OpenDeckResponse::Configuration(
+                    Wish::Set,
+                    Amount::Single,
+                    Block::Led(index as u16, LedSection::Global(0)),
+                    NewValues::new(),
+                ))
+            );
+        }
+        assert_eq!(
+            iterator.next(config),
+            Some(OpenDeckResponse::Configuration(
+                Wish::Set,
+                Amount::Single,
+                Block::Global(GlobalSection::Presets(PresetIndex::Active, 0)),
+                NewValues::new(),
+            ))
+        );
+
         assert_eq!(
             iterator.next(config),
             Some(OpenDeckResponse::Special(SpecialResponse::Backup))
@@ -782,4 +871,151 @@ mod tests {
 
         assert_eq!(iterator.next(config), None);
     }
+
+    type RoundtripConfig = Config<2, 2, 1, 1, 2, NoopHandler>;
+
+    fn roundtrip_config() -> RoundtripConfig {
+        test_config()
+    }
+
+    fn backup(config: &mut RoundtripConfig) -> heapless::Vec<OpenDeckResponse, 256> {
+        let mut responses = heapless::Vec::new();
+        let mut iterator = ConfigBackupIterator::new();
+        while let Some(res) = iterator.next(config) {
+            responses.push(res).unwrap();
+        }
+        responses
+    }
+
+    #[test]
+    fn test_backup_restore_roundtrip() {
+        use crate::storage::MemoryStorage;
+        use crate::{OpenDeckRequest, SpecialRequest};
+
+        let mut original = roundtrip_config();
+        for (p, preset) in original.presets.iter_mut().enumerate() {
+            let p = p as u8;
+            for button in preset.buttons.iter_mut() {
+                button.set(ButtonSection::Type(ButtonType::Latching));
+                button.set(ButtonSection::MessageType(ButtonMessageType::ControlChange));
+                button.set(ButtonSection::MidiId(30 + p));
+                button.set(ButtonSection::Value(100));
+                button.set(ButtonSection::Channel(ChannelOrAll::Channel(3 + p)));
+            }
+            let encoder = &mut preset.encoders[0];
+            encoder.set(EncoderSection::Enabled(true));
+            encoder.set(EncoderSection::Inverted(true));
+            encoder.set(EncoderSection::MessageType(
+                EncoderMessageType::ProgramChange,
+            ));
+            encoder.set(EncoderSection::MidiIdLSB(40 + p as u16));
+            encoder.set(EncoderSection::Channel(ChannelOrAll::All));
+            encoder.set(EncoderSection::PulsesPerStep(2));
+            encoder.set(EncoderSection::Accelleration(Accelleration::Medium));
+            encoder.set(EncoderSection::RemoteSync(true));
+            encoder.set(EncoderSection::LowerLimit(5));
+            encoder.set(EncoderSection::UpperLimit(500));
+            encoder.set(EncoderSection::RepeatedValue(7));
+            encoder.set(EncoderSection::SecondMidiId(60));
+            let analog = &mut preset.analogs[0];
+            analog.set(AnalogSection::Enabled(true));
+            analog.set(AnalogSection::Inverted(true));
+            analog.set(AnalogSection::MessageType(AnalogMessageType::FSR));
+            analog.set(AnalogSection::MidiId(50 + p as u16));
+            analog.set(AnalogSection::LowerCCLimit(10));
+            analog.set(AnalogSection::UpperCCLimit(1000));
+            analog.set(AnalogSection::Channel(ChannelOrAll::Channel(15)));
+            analog.set(AnalogSection::LowerADCOffset(3));
+            analog.set(AnalogSection::UpperADCOffset(4));
+            for led in preset.leds.iter_mut() {
+                led.set(LedSection::State(true));
+                led.set(LedSection::ColorTesting(Color::Green));
+                led.set(LedSection::ActivationId(70 + p));
+                led.set(LedSection::ControlType(
+                    crate::led::ControlType::MidiInCcSingleValue,
+                ));
+                led.set(LedSection::ActivationValue(90));
+                led.set(LedSection::Channel(ChannelOrAll::Channel(0)));
+            }
+        }
+        original.global.midi.set(MidiIndex::RunningStatus, 1);
+        original.global.midi.set(MidiIndex::USBtoDINthru, 1);
+        original.global.midi.set(MidiIndex::GlobalMIDIchannel, 4);
+        original.global.preset.set(PresetIndex::Preservation, 1);
+        original.global.preset.set(PresetIndex::Active, 1);
+        original.global.led.set(LedIndex::BlinkWithMIDIClock, &1);
+        original
+            .global
+            .led
+            .set(LedIndex::UseMidiProgramChangeOffset, &1);
+        let stream = backup(&mut original);
+
+        let mut restored = roundtrip_config();
+        restored.process_req(OpenDeckRequest::Special(SpecialRequest::RestoreStart));
+        for res in stream.iter() {
+            if let OpenDeckResponse::Configuration(Wish::Set, amount, block, _) = res {
+                restored.process_req(OpenDeckRequest::Configuration(Wish::Set, *amount, *block));
+            }
+        }
+        restored.process_req(OpenDeckRequest::Special(SpecialRequest::RestoreEnd));
+
+        assert_eq!(restored.active_preset(), 1);
+        assert_eq!(backup(&mut restored), stream);
+
+        let mut expected = MemoryStorage::<512>::new();
+        original.save_to(&mut expected).unwrap();
+        let mut actual = MemoryStorage::<512>::new();
+        restored.save_to(&mut actual).unwrap();
+        assert_eq!(actual.as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn test_one_byte_backup_splits_14_bit_values() {
+        use crate::{OpenDeckRequest, SpecialRequest};
+
+        let mut original = roundtrip_config();
+        original.set_value_size(ValueSize::OneByte);
+        original.presets[1].encoders[0].set(EncoderSection::MidiIdLSB(200));
+        original.presets[1].analogs[0].set(AnalogSection::UpperCCLimit(1000));
+
+        let mut stream = heapless::Vec::<OpenDeckResponse, 256>::new();
+        let mut iterator = ConfigBackupIterator::new();
+        while let Some(res) = iterator.next_split(&mut original) {
+            stream.push(res).unwrap();
+        }
+        let blocks = stream.iter().filter_map(|res| match res {
+            OpenDeckResponse::Configuration(_, _, block, _) => Some(*block),
+            _ => None,
+        });
+        let upper = AnalogSection::UpperCCLimit(1000 & 0x7F);
+        let upper_msb = AnalogSection::UpperCCLimitMSB((1000 >> 7) as u8);
+        assert!(blocks
+            .clone()
+            .zip(blocks.clone().skip(1))
+            .any(|pair| pair == (Block::Analog(0, upper), Block::Analog(0, upper_msb))));
+        assert!(blocks.clone().all(
+            |block| !matches!(block, Block::Encoder(_, EncoderSection::MidiIdLSB(v)) if v > 0x7F)
+        ));
+
+        let mut restored = roundtrip_config();
+        restored.set_value_size(ValueSize::OneByte);
+        restored.process_req(OpenDeckRequest::Special(SpecialRequest::RestoreStart));
+        for block in blocks {
+            restored.process_req(OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                block,
+            ));
+        }
+        restored.process_req(OpenDeckRequest::Special(SpecialRequest::RestoreEnd));
+        assert_eq!(
+            restored.presets[1].encoders[0].get(EncoderSection::MidiIdLSB(0)),
+            200
+        );
+        assert_eq!(
+            restored.presets[1].analogs[0].get(AnalogSection::UpperCCLimit(0)),
+            1000
+        );
+        assert_eq!(backup(&mut restored), backup(&mut original));
+    }
 }
@@ -1,23 +1,39 @@
 use crate::{
     analog::Analog,
+    bpm::TapTempo,
     button::{handler::Action, Button},
-    config::backup::ConfigBackupIterator,
+    clock::{ClockFollower, ClockGenerator},
+    config::{backup::ConfigBackupIterator, restore::RestoreSession},
     encoder::{handler::EncoderPulse, Encoder},
-    global::{GlobalMidi, GlobalPreset, GlobalSection},
-    handler::Messages,
-    led::{ControlType, Led, LedSection},
+    global::{GlobalMidi, GlobalPreset, GlobalSection, PresetIndex},
+    handler::{ComponentInfo, Messages},
+    led::{blink::BlinkEngine, blink::OutputDrive, ControlType, Led, LedSection},
+    midi_in::{MidiEvent, Realtime},
     parser::{OpenDeckParseError, OpenDeckParser},
     renderer::{OpenDeckRenderer, RenderError},
-    Amount, Block, HardwareUid, MessageStatus, NewValues, NrOfSupportedComponents, OpenDeckRequest,
-    OpenDeckResponse, SpecialRequest, SpecialResponse, ValueSize, Wish, PARAMS_PER_MESSAGE,
+    storage::{StorageError, StorageKey},
+    sysex::OPENDECK_PREFIX,
+    ump::UmpMode,
+    Amount, Block, BlockId, HardwareUid, MessageStatus, NewValues, NrOfSupportedComponents,
+    OpenDeckRequest, OpenDeckResponse, SpecialRequest, SpecialResponse, ValueSize, Wish,
+    PARAMS_PER_MESSAGE,
 };
 
 use heapless::Vec;
-use midi2::sysex7::Sysex7;
+use midi2::{error::BufferOverflow, sysex7::Sysex7, ux::u4, BytesMessage, UmpMessage};
 
 mod backup;
+mod restore;
+mod split;
+mod storage;
+mod validate;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub use storage::LAYOUT_VERSION;
+
+/// Number of changes of an analog component between two Component Info messages.
+pub const ANALOG_INFO_INTERVAL: u8 = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct FirmwareVersion {
     pub major: u8,
@@ -25,7 +41,7 @@ pub struct FirmwareVersion {
     pub revision: u8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Preset<const B: usize, const A: usize, const E: usize, const L: usize> {
     buttons: Vec<Button, B>,
@@ -65,6 +81,18 @@ impl<const B: usize, const A: usize, const E: usize, const L: usize> Default
 }
 
 impl<const B: usize, const A: usize, const E: usize, const L: usize> Preset<B, E, A, L> {
+    pub fn buttons(&self) -> &[Button] {
+        &self.buttons
+    }
+    pub fn encoders(&self) -> &[Encoder] {
+        &self.encoders
+    }
+    pub fn analogs(&self) -> &[Analog] {
+        &self.analogs
+    }
+    pub fn leds(&self) -> &[Led] {
+        &self.leds
+    }
     fn button_mut(&mut self, index: u16) -> Option<&mut Button> {
         self.buttons.get_mut(index as usize)
     }
@@ -91,13 +119,32 @@ impl<const B: usize, const A: usize, const E: usize, const L: usize> Preset<B, E
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct GlobalConfig {
     midi: GlobalMidi,
     preset: GlobalPreset,
     led: crate::led::GlobalLed,
 }
 
+impl GlobalConfig {
+    pub fn midi(&self) -> &GlobalMidi {
+        &self.midi
+    }
+    pub fn preset(&self) -> &GlobalPreset {
+        &self.preset
+    }
+    pub fn led(&self) -> &crate::led::GlobalLed {
+        &self.led
+    }
+}
+
+/// Configuration of a board with `P` presets of `B` buttons, `A` analogs, `E`
+/// encoders and `L` LEDs.
+///
+/// A SysEx restore is staged in a second copy of the presets and global
+/// settings that is kept inside the `Config`, so it takes about twice the RAM of
+/// the configuration itself. Keep that in mind when placing it, e.g. in a
+/// `static` rather than on the stack.
 pub struct Config<
     const P: usize,
     const B: usize,
@@ -107,6 +154,7 @@ pub struct Config<
     H: crate::SystemHandler,
 > {
     parser: OpenDeckParser,
+    value_size: ValueSize,
     global: GlobalConfig,
     bpm: crate::bpm::Bpm,
     enabled: bool,
@@ -115,6 +163,19 @@ pub struct Config<
     uid: u32,
     serial_number: Vec<u8, 32>,
     handler: H,
+    /// Staged restore, as large as `presets` and `global` together.
+    restore: Option<RestoreSession<P, B, A, E, L>>,
+    /// Component Info message of the last component used.
+    pending_info: Option<ComponentInfo>,
+    /// Changes of each analog component since it was last reported.
+    analog_changes: [u8; A],
+    /// Set by the program change offset buttons, added to the program sent by
+    /// Program Change buttons.
+    program_offset: u8,
+    blink: BlinkEngine,
+    clock: ClockGenerator,
+    follower: ClockFollower,
+    tap_tempo: TapTempo,
 }
 
 pub enum SysexResponseIterator<
@@ -138,18 +199,18 @@ impl<const P: usize, const B: usize, const A: usize, const E: usize, const L: us
         buffer: &'c mut [u8],
         config: &mut Config<P, B, A, E, L, H>,
     ) -> Result<Option<Sysex7<&'c mut [u8]>>, RenderError> {
-        let renderer = OpenDeckRenderer::new(ValueSize::TwoBytes, buffer);
+        let renderer = OpenDeckRenderer::new(config.value_size, buffer);
         match self {
             SysexResponseIterator::Config(i) => {
-                if let Some(res) = i.next(config) {
+                if let Some((res, status)) = i.next(config) {
                     #[cfg(feature = "defmt")]
-                    defmt::info!("opendeck-res: {}", res);
-                    return renderer.render(res, MessageStatus::Response);
+                    defmt::info!("opendeck-res: {} {}", res, status);
+                    return renderer.render(res, status);
                 }
                 Ok(None)
             }
             SysexResponseIterator::Backup(i) => {
-                if let Some(res) = i.next(config) {
+                if let Some(res) = i.next_split(config) {
                     #[cfg(feature = "defmt")]
                     defmt::info!("opendeck-bak: {}", res);
                     return renderer.render(res, MessageStatus::Response);
@@ -169,6 +230,21 @@ impl<const P: usize, const B: usize, const A: usize, const E: usize, const L: us
     }
 }
 
+/// What [`Config::receive_midi`] did with a received message.
+pub enum Received<const P: usize, const B: usize, const A: usize, const E: usize, const L: usize> {
+    /// The number of outputs a note, control change or program change updated.
+    Outputs(usize),
+    /// A program change selected this preset.
+    PresetChange(usize),
+    /// Responses to a SysEx request, to be sent where the request came from.
+    Sysex(SysexResponseIterator<P, B, A, E, L>),
+    /// Clock and transport messages, the BPM and the blinking of outputs follow
+    /// them. They are forwarded by the thru settings, see
+    /// [`crate::routing::Router::route_incoming`].
+    Realtime(Realtime),
+    Ignored,
+}
+
 pub struct SingleResponseIterator {
     response: OpenDeckResponse,
     message_status: MessageStatus,
@@ -194,6 +270,7 @@ impl Iterator for SingleResponseIterator {
         if self.done {
             return None;
         }
+        self.done = true;
         Some((self.response.clone(), self.message_status))
     }
 }
@@ -206,6 +283,8 @@ pub struct ConfigResponseIterator<
     const L: usize,
 > {
     request: OpenDeckRequest,
+    /// Values of a Set of all values.
+    values: NewValues,
     part: u8,
     done: bool,
 }
@@ -214,8 +293,13 @@ impl<const P: usize, const B: usize, const A: usize, const E: usize, const L: us
     ConfigResponseIterator<P, B, A, E, L>
 {
     pub fn new(request: OpenDeckRequest) -> Self {
+        Self::with_values(request, NewValues::new())
+    }
+
+    pub fn with_values(request: OpenDeckRequest, values: NewValues) -> Self {
         ConfigResponseIterator {
             request,
+            values,
             part: 0,
             done: false,
         }
@@ -223,7 +307,7 @@ impl<const P: usize, const B: usize, const A: usize, const E: usize, const L: us
     fn next<H: crate::SystemHandler>(
         &mut self,
         config: &mut Config<P, B, A, E, L, H>,
-    ) -> Option<OpenDeckResponse> {
+    ) -> Option<(OpenDeckResponse, MessageStatus)> {
         if self.done {
             return None;
         }
@@ -234,6 +318,25 @@ impl<const P: usize, const B: usize, const A: usize, const E: usize, const L: us
             {
                 let count = config.component_count(&block);
                 let total_parts = count.div_ceil(PARAMS_PER_MESSAGE) as u8;
+                let status = match config.validate(wish, Amount::All(orig_part), block) {
+                    Err(status) => Some(status),
+                    Ok(()) if !matches!(orig_part, 0x7F | 0x7E) && orig_part >= total_parts => {
+                        Some(MessageStatus::PartError)
+                    }
+                    Ok(()) => None,
+                };
+                if let Some(status) = status {
+                    self.done = true;
+                    return Some((
+                        OpenDeckResponse::Configuration(
+                            wish,
+                            Amount::All(orig_part),
+                            block,
+                            Vec::new(),
+                        ),
+                        status,
+                    ));
+                }
 
                 match orig_part {
                     // Stream all parts
@@ -242,19 +345,25 @@ impl<const P: usize, const B: usize, const A: usize, const E: usize, const L: us
                             let values = config.get_all_part(wish, block, self.part);
                             let response_part = self.part;
                             self.part += 1;
-                            Some(OpenDeckResponse::Configuration(
-                                wish,
-                                Amount::All(response_part),
-                                block,
-                                values,
+                            Some((
+                                OpenDeckResponse::Configuration(
+                                    wish,
+                                    Amount::All(response_part),
+                                    block,
+                                    values,
+                                ),
+                                MessageStatus::Response,
                             ))
                         } else if orig_part == 0x7E {
                             self.done = true;
-                            Some(OpenDeckResponse::Configuration(
-                                wish,
-                                Amount::All(0x7E),
-                                block,
-                                Vec::new(),
+                            Some((
+                                OpenDeckResponse::Configuration(
+                                    wish,
+                                    Amount::All(0x7E),
+                                    block,
+                                    Vec::new(),
+                                ),
+                                MessageStatus::Response,
                             ))
                         } else {
                             self.done = true;
@@ -264,23 +373,30 @@ impl<const P: usize, const B: usize, const A: usize, const E: usize, const L: us
                     // Specific part requested
                     part => {
                         self.done = true;
-                        if part < total_parts {
-                            let values = config.get_all_part(wish, block, part);
-                            Some(OpenDeckResponse::Configuration(
-                                wish,
-                                Amount::All(part),
-                                block,
-                                values,
-                            ))
-                        } else {
-                            None
-                        }
+                        let values = config.get_all_part(wish, block, part);
+                        Some((
+                            OpenDeckResponse::Configuration(wish, Amount::All(part), block, values),
+                            MessageStatus::Response,
+                        ))
                     }
                 }
             }
+            OpenDeckRequest::Configuration(Wish::Set, Amount::All(part), block) => {
+                self.done = true;
+                let status = config.set_all(part, block, &self.values);
+                Some((
+                    OpenDeckResponse::Configuration(
+                        Wish::Set,
+                        Amount::All(part),
+                        block,
+                        self.values.clone(),
+                    ),
+                    status,
+                ))
+            }
             _ => {
                 self.done = true;
-                config.process_req(self.request)
+                config.process_req_with_status(self.request)
             }
         }
     }
@@ -311,12 +427,21 @@ impl<
 
         Config {
             parser: OpenDeckParser::new(ValueSize::TwoBytes),
+            value_size: ValueSize::TwoBytes,
             enabled: false,
             presets,
             version,
             uid,
             serial_number: Vec::new(),
             handler,
+            restore: None,
+            pending_info: None,
+            analog_changes: [0; A],
+            program_offset: 0,
+            blink: BlinkEngine::new(),
+            clock: ClockGenerator::new(),
+            follower: ClockFollower::new(),
+            tap_tempo: TapTempo::default(),
             global: GlobalConfig::default(),
             bpm: crate::bpm::Bpm::default(),
         }
@@ -328,40 +453,102 @@ impl<
             self.serial_number.push(b).ok();
         }
     }
+    /// Selects the protocol variant used to parse requests and render responses.
+    ///
+    /// With [`ValueSize::OneByte`] 14-bit values are exchanged through their LSB and
+    /// MSB sections.
+    pub fn set_value_size(&mut self, value_size: ValueSize) {
+        self.value_size = value_size;
+        self.parser = OpenDeckParser::new(value_size);
+    }
+
+    pub fn value_size(&self) -> ValueSize {
+        self.value_size
+    }
+
     /// Processes a SysEx request and returns an optional response.
-    pub fn process_sysex(&mut self, request: &[u8]) -> SysexResponseIterator<P, B, A, E, L> {
-        let request = self.parser.parse(request);
+    ///
+    /// Until a handshake opens the session, requests other than the handshake and
+    /// the firmware version and hardware UID are answered with `HandshakeError`.
+    pub fn process_sysex(&mut self, message: &[u8]) -> SysexResponseIterator<P, B, A, E, L> {
+        let request = self
+            .parser
+            .parse(message)
+            .and_then(|request| match request {
+                OpenDeckRequest::Configuration(Wish::Set, Amount::All(_), _) => {
+                    Ok((request, self.parser.parse_values(message)?))
+                }
+                request => Ok((request, NewValues::new())),
+            });
         match request {
-            Ok(OpenDeckRequest::Special(SpecialRequest::Backup)) => {
+            Ok((request, _)) if !self.enabled && !allowed_before_handshake(&request) => {
+                let response = OpenDeckResponse::Special(SpecialResponse::Handshake);
+                SysexResponseIterator::Error(SingleResponseIterator::new(
+                    response,
+                    MessageStatus::HandshakeError,
+                ))
+            }
+            Ok((OpenDeckRequest::Special(SpecialRequest::Backup), _)) => {
                 SysexResponseIterator::Backup(ConfigBackupIterator::new())
             }
-            Ok(request) => SysexResponseIterator::Config(ConfigResponseIterator::new(request)),
+            Ok((request, values)) => {
+                SysexResponseIterator::Config(ConfigResponseIterator::with_values(request, values))
+            }
             Err(OpenDeckParseError::StatusError(message_status)) => {
+                self.abort_restore();
                 let response = OpenDeckResponse::Special(SpecialResponse::Handshake);
                 SysexResponseIterator::Error(SingleResponseIterator::new(response, message_status))
             }
             Err(_err) => {
                 #[cfg(feature = "defmt")]
                 defmt::error!("error parsing sysex message: {}", _err);
+                // SysEx of other devices on the port leaves a restore running
+                if message.starts_with(&OPENDECK_PREFIX) {
+                    self.abort_restore();
+                }
                 SysexResponseIterator::None
             }
         }
     }
 
+    /// Processes a request, the message status of the response is dropped.
     pub fn process_req(&mut self, req: OpenDeckRequest) -> Option<OpenDeckResponse> {
+        self.process_req_with_status(req).map(|(res, _)| res)
+    }
+
+    pub(crate) fn process_req_with_status(
+        &mut self,
+        req: OpenDeckRequest,
+    ) -> Option<(OpenDeckResponse, MessageStatus)> {
         #[cfg(feature = "defmt")]
         defmt::info!("opendeck-req: {}", req);
         match req {
+            OpenDeckRequest::Special(SpecialRequest::RestoreEnd) => {
+                let status = self.finish_restore();
+                Some((
+                    OpenDeckResponse::Special(SpecialResponse::RestoreEnd),
+                    status,
+                ))
+            }
             OpenDeckRequest::Special(special) => {
                 if let Some(spec_res) = self.process_special_req(special) {
-                    return Some(OpenDeckResponse::Special(spec_res));
+                    return Some((OpenDeckResponse::Special(spec_res), MessageStatus::Response));
                 }
                 None
             }
             OpenDeckRequest::Configuration(wish, amount, block) => {
-                let (res_values, for_amount) = self.process_config(wish, amount, block);
-                Some(OpenDeckResponse::Configuration(
-                    wish, for_amount, block, res_values,
+                let (res_values, for_amount, status) = match self.validate(wish, amount, block) {
+                    Ok(()) => self.process_config(wish, amount, block),
+                    Err(status) => {
+                        if wish == Wish::Set {
+                            self.abort_restore();
+                        }
+                        (Vec::new(), amount, status)
+                    }
+                };
+                Some((
+                    OpenDeckResponse::Configuration(wish, for_amount, block, res_values),
+                    status,
                 ))
             }
             // Component info messages are outbound-only (board → host), never received.
@@ -381,6 +568,7 @@ impl<
             }
             SpecialRequest::ConnectionClose => {
                 self.enabled = false;
+                self.abort_restore();
                 Some(SpecialResponse::Handshake)
             }
             SpecialRequest::Handshake => {
@@ -418,7 +606,10 @@ impl<
             SpecialRequest::SerialNumber => {
                 Some(SpecialResponse::SerialNumber(self.serial_number.clone()))
             }
-            SpecialRequest::RestoreStart => Some(SpecialResponse::RestoreStart),
+            SpecialRequest::RestoreStart => {
+                self.start_restore();
+                Some(SpecialResponse::RestoreStart)
+            }
             SpecialRequest::RestoreEnd => Some(SpecialResponse::RestoreEnd),
         }
     }
@@ -466,132 +657,273 @@ impl<
                     },
                     _ => 0,
                 };
-                values.push(v).unwrap();
+                values.push(self.mask(block, v)).unwrap();
             }
         }
         values
     }
 
-    fn process_config(&mut self, wish: Wish, amount: Amount, block: Block) -> (NewValues, Amount) {
+    fn process_config(
+        &mut self,
+        wish: Wish,
+        amount: Amount,
+        block: Block,
+    ) -> (NewValues, Amount, MessageStatus) {
         let mut res_values = Vec::new();
         let mut for_amount = amount;
 
+        if let (Wish::Set, Amount::All(part)) = (wish, amount) {
+            // without a SysEx message the block carries the only value of the part
+            let (_, _, section) = block.into_parts();
+            res_values.push(section.value).unwrap();
+            let status = self.set_all(part, block, &res_values);
+            return (res_values, for_amount, status);
+        }
+        if wish == Wish::Set {
+            return (res_values, for_amount, self.set_value(block));
+        }
+
         if let Some(preset) = self.current_preset_mut() {
             match block {
-                Block::Global(GlobalSection::Midi(i, value)) => match wish {
-                    Wish::Set => self.global.midi.set(i, value),
-                    Wish::Get | Wish::Backup => {
-                        res_values.push(self.global.midi.get(i)).unwrap();
-                    }
-                },
-                Block::Global(GlobalSection::Presets(pi, value)) => match wish {
-                    Wish::Set => self.global.preset.set(pi, value),
-                    Wish::Get | Wish::Backup => {
-                        res_values.push(self.global.preset.get(pi)).unwrap();
-                    }
-                },
+                Block::Global(GlobalSection::Midi(i, _)) => {
+                    res_values.push(self.global.midi.get(i)).unwrap();
+                }
+                Block::Global(GlobalSection::Presets(pi, _)) => {
+                    res_values.push(self.global.preset.get(pi)).unwrap();
+                }
                 Block::Global(GlobalSection::OSC(_, _)) => {}
                 Block::Global(GlobalSection::MDNS(_, _)) => {}
                 Block::Global(GlobalSection::ConfigurationUnlock(_, _)) => {}
-                Block::Button(index, section) => match wish {
-                    Wish::Set => {
-                        if let Some(b) = preset.button_mut(index) {
-                            b.set(section)
+                Block::Button(index, section) => match amount {
+                    Amount::Single => {
+                        if let Some(b) = preset.button(index) {
+                            res_values.push(b.get(section)).unwrap();
                         }
                     }
-                    Wish::Get | Wish::Backup => match amount {
-                        Amount::Single => {
-                            if let Some(b) = preset.button(index) {
-                                res_values.push(b.get(section)).unwrap();
-                            }
-                        }
-                        Amount::All(_) => {
-                            for b in preset.buttons.iter() {
-                                res_values.push(b.get(section)).unwrap();
-                            }
-                            for_amount = Amount::All(0)
+                    Amount::All(_) => {
+                        for b in preset.buttons.iter() {
+                            res_values.push(b.get(section)).unwrap();
                         }
-                    },
+                        for_amount = Amount::All(0)
+                    }
                 },
-                Block::Encoder(index, section) => match wish {
-                    Wish::Set => {
-                        if let Some(b) = preset.encoder_mut(index) {
-                            b.set(section)
+                Block::Encoder(index, section) => match amount {
+                    Amount::Single => {
+                        if let Some(b) = preset.encoder(index) {
+                            res_values.push(b.get(section)).unwrap();
                         }
                     }
-                    Wish::Get | Wish::Backup => match amount {
-                        Amount::Single => {
-                            if let Some(b) = preset.encoder(index) {
-                                res_values.push(b.get(section)).unwrap();
-                            }
+                    Amount::All(_) => {
+                        for b in preset.encoders.iter() {
+                            res_values.push(b.get(section)).unwrap();
                         }
-                        Amount::All(_) => {
-                            for b in preset.encoders.iter() {
-                                res_values.push(b.get(section)).unwrap();
-                            }
-                            for_amount = Amount::All(0)
+                        for_amount = Amount::All(0)
+                    }
+                },
+                Block::Analog(index, section) => match amount {
+                    Amount::Single => {
+                        if let Some(b) = preset.analog(index) {
+                            res_values.push(b.get(section)).unwrap();
                         }
-                    },
+                    }
+                    Amount::All(_) => {
+                        for b in preset.analogs.iter() {
+                            res_values.push(b.get(section)).unwrap();
+                        }
+                        for_amount = Amount::All(0)
+                    }
                 },
-                Block::Analog(index, section) => match wish {
-                    Wish::Set => {
-                        if let Some(b) = preset.analog_mut(index) {
-                            b.set(section)
+                Block::Led(index, section) => match section {
+                    LedSection::Global(_) => {
+                        if let Ok(led_index) = crate::led::LedIndex::try_from(index) {
+                            res_values.push(self.global.led.get(&led_index)).unwrap();
                         }
                     }
-                    Wish::Get | Wish::Backup => match amount {
+                    _ => match amount {
                         Amount::Single => {
-                            if let Some(b) = preset.analog(index) {
+                            if let Some(b) = preset.led(index) {
                                 res_values.push(b.get(section)).unwrap();
                             }
                         }
                         Amount::All(_) => {
-                            for b in preset.analogs.iter() {
+                            for b in preset.leds.iter() {
                                 res_values.push(b.get(section)).unwrap();
                             }
                             for_amount = Amount::All(0)
                         }
                     },
                 },
-                Block::Led(index, section) => match section {
-                    LedSection::Global(value) => {
-                        if let Ok(led_index) = crate::led::LedIndex::try_from(index) {
-                            match wish {
-                                Wish::Set => self.global.led.set(led_index, &value),
-                                Wish::Get | Wish::Backup => {
-                                    res_values.push(self.global.led.get(&led_index)).unwrap();
-                                }
-                            }
-                        }
-                    }
-                    _ => match wish {
-                        Wish::Set => {
-                            if let Some(b) = preset.led_mut(index) {
-                                b.set(section)
-                            }
-                        }
-                        Wish::Get | Wish::Backup => match amount {
-                            Amount::Single => {
-                                if let Some(b) = preset.led(index) {
-                                    res_values.push(b.get(section)).unwrap();
-                                }
-                            }
-                            Amount::All(_) => {
-                                for b in preset.leds.iter() {
-                                    res_values.push(b.get(section)).unwrap();
-                                }
-                                for_amount = Amount::All(0)
-                            }
-                        },
-                    },
-                },
 
                 Block::Display => {}
                 Block::Touchscreen => {}
             };
         };
+        for v in res_values.iter_mut() {
+            *v = self.mask(block, *v);
+        }
+
+        (res_values, for_amount, MessageStatus::Response)
+    }
+
+    /// Applies a single value, to the staged configuration during a restore.
+    fn set_value(&mut self, block: Block) -> MessageStatus {
+        if self.restore.is_some() {
+            return self.stage_restore(block);
+        }
+        let preset = self.global.preset.current;
+        let block = split::join(block, self.value_size, |lsb| {
+            get_block(&self.presets, &self.global, preset, lsb)
+        });
+        let previous = self.current_block(preset, block);
+        if set_block(&mut self.presets, &mut self.global, preset, block) {
+            if let Err(err) = self.store_block(preset, block) {
+                // keep RAM in line with what survives a reboot
+                if let Some(previous) = previous {
+                    set_block(&mut self.presets, &mut self.global, preset, previous);
+                }
+                return err.into();
+            }
+        }
+        MessageStatus::Response
+    }
+
+    /// `block` with the value it currently has in `preset`.
+    fn current_block(&self, preset: usize, block: Block) -> Option<Block> {
+        let (key, _) = StorageKey::from_block(preset, block);
+        let value = get_block(&self.presets, &self.global, preset, block)?;
+        key.to_block(value).ok()
+    }
+
+    /// Applies a Set of all values of a section, `values` holds one value per
+    /// component of `part`.
+    ///
+    /// Every value is checked before the first one is applied. A storage error
+    /// rolls back the values of the part applied before it.
+    pub(crate) fn set_all(&mut self, part: u8, block: Block, values: &[u16]) -> MessageStatus {
+        let blocks = match self.set_all_blocks(part, block, values) {
+            Ok(blocks) => blocks,
+            Err(status) => {
+                self.abort_restore();
+                return status;
+            }
+        };
+        let preset = self.global.preset.current;
+        // values before and after each Set, a staged restore needs no rollback
+        let mut applied: Vec<(Block, Block), PARAMS_PER_MESSAGE> = Vec::new();
+        for block in blocks {
+            let joined = split::join(block, self.value_size, |lsb| {
+                get_block(&self.presets, &self.global, preset, lsb)
+            });
+            let previous = self.current_block(preset, joined);
+            let status = self.set_value(block);
+            if status != MessageStatus::Response {
+                // set_value rolled back the value that failed
+                self.roll_back(preset, &applied);
+                return status;
+            }
+            if let (None, Some(previous)) = (&self.restore, previous) {
+                applied.push((previous, joined)).unwrap();
+            }
+        }
+        MessageStatus::Response
+    }
+
+    /// Restores the previous values of `applied`, newest first. A value whose
+    /// previous one can't be stored keeps the new one, as it survives a reboot.
+    fn roll_back(&mut self, preset: usize, applied: &[(Block, Block)]) {
+        for &(previous, block) in applied.iter().rev() {
+            if set_block(&mut self.presets, &mut self.global, preset, previous)
+                && self.store_block(preset, previous).is_err()
+            {
+                set_block(&mut self.presets, &mut self.global, preset, block);
+            }
+        }
+    }
+
+    fn set_all_blocks(
+        &self,
+        part: u8,
+        block: Block,
+        values: &[u16],
+    ) -> Result<Vec<Block, PARAMS_PER_MESSAGE>, MessageStatus> {
+        self.validate(Wish::Set, Amount::All(part), block)?;
+        match block {
+            Block::Button(..) | Block::Encoder(..) | Block::Analog(..) => {}
+            Block::Led(_, section) if !matches!(section, LedSection::Global(_)) => {}
+            _ => return Err(MessageStatus::NotSupportedError),
+        }
+        let count = self.component_count(&block);
+        let start = part as usize * PARAMS_PER_MESSAGE;
+        if start >= count {
+            return Err(MessageStatus::PartError);
+        }
+        if values.len() != (count - start).min(PARAMS_PER_MESSAGE) {
+            return Err(MessageStatus::MessageLengthError);
+        }
+        let (_, block_id, section) = block.into_parts();
+        let block_id = block_id as u8;
+        let mut blocks = Vec::new();
+        for (i, value) in values.iter().enumerate() {
+            let section = crate::Section {
+                id: section.id,
+                value: *value,
+            };
+            let block = Block::from_parts(block_id, (start + i) as u16, section).map_err(
+                |err| match err {
+                    OpenDeckParseError::StatusError(status) => status,
+                    _ => MessageStatus::NewValueError,
+                },
+            )?;
+            blocks.push(block).unwrap();
+        }
+        Ok(blocks)
+    }
+
+    /// Leaves the part of a value that its section carries with the current value size.
+    fn mask(&self, block: Block, value: u16) -> u16 {
+        if self.value_size == ValueSize::OneByte && split::is_lsb(&block) {
+            value & 0x7F
+        } else {
+            value
+        }
+    }
+
+    /// Hands a value that was just set to the handler for persistence.
+    fn store_block(&mut self, preset: usize, block: Block) -> Result<(), StorageError> {
+        let preset = match block {
+            Block::Global(_) | Block::Led(_, LedSection::Global(_)) => 0,
+            _ => preset,
+        };
+        let (key, value) = StorageKey::from_block(preset, block);
+        self.handler.store_value(key, value).inspect_err(|_err| {
+            #[cfg(feature = "defmt")]
+            defmt::error!("storing {} failed: {}", key, _err);
+        })
+    }
 
-        (res_values, for_amount)
+    /// Applies the values persisted through [`crate::SystemHandler::store_value`],
+    /// usually once at boot.
+    pub fn load_stored(&mut self) -> Result<(), StorageError> {
+        let Config {
+            presets,
+            global,
+            handler,
+            ..
+        } = self;
+        handler.load_values(&mut |key, value| match key.to_block(value) {
+            // values that can't be set over SysEx, e.g. an active preset the
+            // board doesn't have, are skipped
+            Ok(block)
+                if (key.preset as usize) < P
+                    && Self::validate_block(Wish::Set, Amount::Single, block).is_ok() =>
+            {
+                set_block(presets, global, key.preset as usize, block);
+            }
+            _ => {
+                #[cfg(feature = "defmt")]
+                defmt::warn!("skipping stored value {}", key);
+            }
+        })
     }
 
     fn current_preset(&self) -> Option<&Preset<B, A, E, L>> {
@@ -602,9 +934,78 @@ impl<
         self.presets.get_mut(self.global.preset.current)
     }
 
+    /// Returns the Component Info message for a component used while a SysEx
+    /// session is open.
+    fn component_info(&self, block: BlockId, index: usize, count: usize) -> Option<ComponentInfo> {
+        let fits = match self.value_size {
+            ValueSize::OneByte => index <= 0x7F,
+            ValueSize::TwoBytes => true,
+        };
+        (self.enabled && index < count && fits).then_some(ComponentInfo {
+            block,
+            index: index as u16,
+            value_size: self.value_size,
+        })
+    }
+
+    /// Reports analog movement on the first of every [`ANALOG_INFO_INTERVAL`]
+    /// changes of each component, also when several are moved together.
+    fn analog_info(&mut self, index: usize) -> Option<ComponentInfo> {
+        let info = self.component_info(BlockId::Analog, index, A)?;
+        let changes = self.analog_changes.get_mut(index)?;
+        let report = *changes == 0;
+        *changes = (*changes + 1) % ANALOG_INFO_INTERVAL;
+        report.then_some(info)
+    }
+
+    /// Takes the Component Info message of the last component used while a
+    /// SysEx session is open, send it after the messages of the component.
+    pub fn next_component_info<'buf>(
+        &mut self,
+        buffer: &'buf mut [u8],
+    ) -> Result<Option<BytesMessage<&'buf mut [u8]>>, BufferOverflow> {
+        let Some(info) = &self.pending_info else {
+            return Ok(None);
+        };
+        let message = info.render(buffer)?;
+        self.pending_info = None;
+        Ok(Some(message))
+    }
+
+    /// Same as [`Config::next_component_info`], rendered as UMP for `group`.
+    pub fn next_component_info_ump<'buf>(
+        &mut self,
+        mode: UmpMode,
+        group: u4,
+        buffer: &'buf mut [u32],
+    ) -> Result<Option<UmpMessage<&'buf [u32]>>, BufferOverflow> {
+        let Some(info) = &self.pending_info else {
+            return Ok(None);
+        };
+        let message = info.render_ump(mode, group, buffer)?;
+        self.pending_info = None;
+        Ok(Some(message))
+    }
+
     pub fn handle_button(&mut self, index: usize, action: Action) -> Messages<'_> {
+        self.button(index, action, None)
+    }
+
+    /// Same as [`Config::handle_button`], `now_us` is a monotonic timestamp in
+    /// microseconds of the action, needed by tap tempo buttons.
+    pub fn handle_button_at(&mut self, index: usize, action: Action, now_us: u64) -> Messages<'_> {
+        self.button(index, action, Some(now_us))
+    }
+
+    fn button(&mut self, index: usize, action: Action, now_us: Option<u64>) -> Messages<'_> {
         use crate::button::ButtonMessageType;
 
+        if matches!(action, Action::Pressed) {
+            if let Some(info) = self.component_info(BlockId::Button, index, B) {
+                self.pending_info = Some(info);
+            }
+        }
+
         // Check for internal preset change or BPM before borrowing for MIDI handling
         if matches!(action, Action::Pressed) {
             if let Some(preset) = self.presets.get(self.global.preset.current) {
@@ -619,6 +1020,25 @@ impl<
                             self.global.preset.current = target;
                             return Messages::None;
                         }
+                        Ok(ButtonMessageType::ProgramChangeOffsetIncr) => {
+                            let step = button.get(crate::button::ButtonSection::Value(0)) as u8;
+                            self.program_offset =
+                                self.program_offset.saturating_add(step).min(0x7F);
+                            return Messages::None;
+                        }
+                        Ok(ButtonMessageType::ProgramChangeOffsetDecr) => {
+                            let step = button.get(crate::button::ButtonSection::Value(0)) as u8;
+                            self.program_offset = self.program_offset.saturating_sub(step);
+                            return Messages::None;
+                        }
+                        // the button sends the message, the generator follows
+                        Ok(ButtonMessageType::RealTimeStart) => self.clock.start(),
+                        Ok(ButtonMessageType::RealTimeContinue) => self.clock.resume(),
+                        Ok(ButtonMessageType::RealTimeStop) => self.clock.stop(),
+                        Ok(ButtonMessageType::TapTempo) => {
+                            self.tap(now_us);
+                            return Messages::None;
+                        }
                         Ok(ButtonMessageType::BPMIncr) => {
                             self.bpm.increment();
                             return Messages::None;
@@ -639,13 +1059,14 @@ impl<
             None
         };
         let standard_note_off = self.global.midi.standard_note_off();
+        let program_offset = self.program_offset;
         if let Some(preset) = self.current_preset_mut() {
             if let Some(button) = preset.button_mut(index as u16) {
-                return Messages::Button(button.handle_with_options(
-                    action,
-                    standard_note_off,
-                    channel_override,
-                ));
+                return Messages::Button(
+                    button
+                        .handle_with_options(action, standard_note_off, channel_override)
+                        .with_program_offset(program_offset),
+                );
             }
         }
         Messages::None
@@ -656,6 +1077,15 @@ impl<
         } else {
             None
         };
+        let changes = self
+            .current_preset()
+            .and_then(|preset| preset.analogs.get(index))
+            .is_some_and(|analog| analog.changes(value));
+        if changes {
+            if let Some(info) = self.analog_info(index) {
+                self.pending_info = Some(info);
+            }
+        }
         if let Some(preset) = self.current_preset_mut() {
             if let Some(analog) = preset.analog_mut(index as u16) {
                 return Messages::Analog(analog.handle_with_channel(value, channel_override));
@@ -664,8 +1094,27 @@ impl<
         Messages::None
     }
     pub fn handle_encoder(&mut self, index: usize, pulse: EncoderPulse) -> Messages<'_> {
+        self.encoder(index, pulse, None)
+    }
+
+    /// Same as [`Config::handle_encoder`], `now_us` is a monotonic timestamp in
+    /// microseconds of the pulse, needed by tap tempo encoders.
+    pub fn handle_encoder_at(
+        &mut self,
+        index: usize,
+        pulse: EncoderPulse,
+        now_us: u64,
+    ) -> Messages<'_> {
+        self.encoder(index, pulse, Some(now_us))
+    }
+
+    fn encoder(&mut self, index: usize, pulse: EncoderPulse, now_us: Option<u64>) -> Messages<'_> {
         use crate::encoder::EncoderMessageType;
 
+        if let Some(info) = self.component_info(BlockId::Encoder, index, E) {
+            self.pending_info = Some(info);
+        }
+
         // Check for internal preset change or BPM
         if let Some(preset) = self.presets.get(self.global.preset.current) {
             if let Some(encoder) = preset.encoders.get(index) {
@@ -686,6 +1135,10 @@ impl<
                     }
                     return Messages::None;
                 }
+                if matches!(msg_type, Ok(EncoderMessageType::TapTempo)) {
+                    self.tap(now_us);
+                    return Messages::None;
+                }
                 if matches!(msg_type, Ok(EncoderMessageType::BPM)) {
                     match pulse {
                         EncoderPulse::Clockwise => self.bpm.increment(),
@@ -709,8 +1162,16 @@ impl<
         Messages::None
     }
 
+    /// Sets the BPM from a tap, without a timestamp the tap is ignored.
+    fn tap(&mut self, now_us: Option<u64>) {
+        if let Some(bpm) = now_us.and_then(|now_us| self.tap_tempo.tap(now_us)) {
+            self.bpm.set(bpm);
+        }
+    }
+
     /// Notify the config that a local MIDI message was generated.
     /// This updates output states for outputs configured in Local control mode.
+    /// `channel` is 0-based, as in the status byte and [`crate::ChannelOrAll::Channel`].
     pub fn notify_local_midi(
         &mut self,
         channel: u8,
@@ -724,6 +1185,7 @@ impl<
 
     /// Notify the config that an external MIDI message was received.
     /// This updates output states for outputs configured in MIDI In control mode.
+    /// `channel` is 0-based, as in the status byte and [`crate::ChannelOrAll::Channel`].
     pub fn notify_external_midi(
         &mut self,
         channel: u8,
@@ -735,6 +1197,111 @@ impl<
         self.update_outputs(channel, id, value, is_note_on, false, is_cc)
     }
 
+    /// Handles a message decoded by a [`crate::midi_in::MidiDecoder`].
+    ///
+    /// Notes and control changes update the outputs in MIDI In control mode, a
+    /// note on with velocity 0 is a note off. A program change selects the preset
+    /// of the same index if preset change via MIDI is enabled, then updates the
+    /// outputs in program change mode of the active preset.
+    ///
+    /// `now_us` is a monotonic timestamp in microseconds of the reception. While a
+    /// MIDI clock is received the BPM follows its tempo and the internal clock is
+    /// paused; forwarding the received clock is up to the thru settings, see
+    /// [`crate::routing::Router::route_incoming`]. A received clock that stopped
+    /// is noticed here or in [`Config::poll_clock`].
+    pub fn receive_midi(&mut self, event: MidiEvent<'_>, now_us: u64) -> Received<P, B, A, E, L> {
+        self.follower.check_timeout(now_us);
+        match event {
+            MidiEvent::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => Received::Outputs(self.notify_external_midi(
+                channel.into(),
+                note,
+                velocity,
+                velocity > 0,
+                false,
+            )),
+            MidiEvent::NoteOff { channel, note, .. } => {
+                Received::Outputs(self.notify_external_midi(channel.into(), note, 0, false, false))
+            }
+            MidiEvent::ControlChange {
+                channel,
+                control,
+                value,
+            } => Received::Outputs(self.notify_external_midi(
+                channel.into(),
+                control,
+                value,
+                false,
+                true,
+            )),
+            MidiEvent::ProgramChange { channel, program } => {
+                let preset = program as usize;
+                let change =
+                    self.global.preset.get(PresetIndex::EnableMidiChange) != 0 && preset < P;
+                // switch first, so the outputs of the new preset are updated
+                if change {
+                    self.global.preset.current = preset;
+                }
+                let count = self.notify_program_change(channel.into(), program);
+                match count {
+                    _ if change => Received::PresetChange(preset),
+                    0 => Received::Ignored,
+                    count => Received::Outputs(count),
+                }
+            }
+            MidiEvent::Realtime(realtime) => {
+                match realtime {
+                    Realtime::Clock => self.blink.clock(),
+                    Realtime::Start => {
+                        self.blink.restart();
+                        self.clock.start();
+                    }
+                    Realtime::Continue => self.clock.resume(),
+                    Realtime::Stop => self.clock.stop(),
+                }
+                if let Some(bpm) = self.follower.receive(realtime, now_us) {
+                    self.bpm.set(bpm);
+                }
+                Received::Realtime(realtime)
+            }
+            MidiEvent::Sysex(message) => Received::Sysex(self.process_sysex(message)),
+        }
+    }
+
+    /// Notify the config that a program change was received or sent.
+    /// This updates output states for outputs in Program Change control mode,
+    /// taking the program change offset into account if the LEDs are set to use it.
+    pub fn notify_program_change(&mut self, channel: u8, program: u8) -> usize {
+        use crate::led::handler::OutputState;
+
+        let offset = if self.global.led.midi_program_change_offset() {
+            self.program_offset
+        } else {
+            0
+        };
+        let Some(preset) = self.presets.get_mut(self.global.preset.current) else {
+            return 0;
+        };
+        let mut count = 0;
+        for led in preset.leds.iter_mut() {
+            match led.process_program_change(channel, program, offset) {
+                OutputState::On => led.set_state(true),
+                OutputState::Off => led.set_state(false),
+                _ => continue,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Offset applied to the activation IDs of outputs in Program Change mode.
+    pub fn program_offset(&self) -> u8 {
+        self.program_offset
+    }
+
     fn update_outputs(
         &mut self,
         channel: u8,
@@ -799,14 +1366,38 @@ impl<
     }
 
     /// Get the current on/off state of an output.
+    ///
+    /// Outputs in Preset Change control mode are on while the preset of their
+    /// activation ID is active.
     pub fn output_state(&self, index: usize) -> bool {
+        let current = self.global.preset.current;
         self.presets
-            .get(self.global.preset.current)
+            .get(current)
             .and_then(|p| p.leds.get(index))
-            .map(|led| led.is_on())
+            .map(|led| match led.get_control_type() {
+                ControlType::PresetChange => led.indicates_preset(current),
+                _ => led.is_on(),
+            })
             .unwrap_or(false)
     }
 
+    /// Get how an output should be driven, with the brightness and blinking of
+    /// multi-value modes applied.
+    pub fn output_drive(&self, index: usize) -> OutputDrive {
+        let with_clock = self.global.led.blink_with_midi_clock();
+        let on = self.output_state(index);
+        self.presets
+            .get(self.global.preset.current)
+            .and_then(|p| p.leds.get(index))
+            .map(|led| self.blink.drive(led, on, with_clock))
+            .unwrap_or(OutputDrive::OFF)
+    }
+
+    /// Advances the timer of outputs blinking without the MIDI clock.
+    pub fn tick_outputs(&mut self, elapsed_ms: u32) {
+        self.blink.tick(elapsed_ms);
+    }
+
     /// Get the current level of an output (0-127, for multi-value modes).
     pub fn output_level(&self, index: usize) -> u8 {
         self.presets
@@ -878,36 +1469,186 @@ impl<
     pub fn bpm(&self) -> &crate::bpm::Bpm {
         &self.bpm
     }
+
+    /// Returns a Timing Clock message if one is due at `now_us`, a monotonic
+    /// timestamp in microseconds.
+    ///
+    /// Sending the MIDI clock must be enabled. Poll until it returns `None`, the
+    /// clock follows the current BPM and the Start, Continue and Stop buttons.
+    /// While a MIDI clock is received none is generated, the received one is
+    /// forwarded by [`crate::routing::Router::route_incoming`] as the thru
+    /// settings allow. The internal clock takes over at the received tempo once
+    /// it is lost, which is detected here even when no MIDI is received.
+    pub fn poll_clock(&mut self, now_us: u64) -> Option<Realtime> {
+        self.follower.check_timeout(now_us);
+        if !self.global.midi.send_midi_clock_enabled() || self.follower.is_following() {
+            self.clock.resync();
+            return None;
+        }
+        self.clock
+            .poll(now_us, self.bpm.get())
+            .then_some(Realtime::Clock)
+    }
+
+    pub fn clock(&self) -> &ClockGenerator {
+        &self.clock
+    }
+
+    /// Tap count and timeout of tap tempo buttons and encoders.
+    pub fn tap_tempo_mut(&mut self) -> &mut TapTempo {
+        &mut self.tap_tempo
+    }
+
+    /// Whether a received MIDI clock sets the BPM, until [`Config::poll_clock`] or
+    /// [`Config::receive_midi`] notice that it stopped.
+    pub fn follows_external_clock(&self) -> bool {
+        self.follower.is_following()
+    }
+}
+/// Requests answered without an open SysEx session: opening one and identifying
+/// the board.
+fn allowed_before_handshake(request: &OpenDeckRequest) -> bool {
+    matches!(
+        request,
+        OpenDeckRequest::Special(
+            SpecialRequest::Handshake
+                | SpecialRequest::FirmwareVersion
+                | SpecialRequest::HardwareUID
+                | SpecialRequest::FirmwareVersionAndHardwareUUID
+        )
+    )
+}
+
+/// Applies a `Wish::Set` block to `preset`, returns whether a value was changed.
+///
+/// Takes the parts of the configuration instead of `self`, so it can also be
+/// used while the handler is borrowed.
+pub(crate) fn set_block<const B: usize, const A: usize, const E: usize, const L: usize>(
+    presets: &mut [Preset<B, A, E, L>],
+    global: &mut GlobalConfig,
+    preset: usize,
+    block: Block,
+) -> bool {
+    let Some(preset) = presets.get_mut(preset) else {
+        return false;
+    };
+    match block {
+        Block::Global(GlobalSection::Midi(i, value)) => global.midi.set(i, value),
+        Block::Global(GlobalSection::Presets(pi, value)) => global.preset.set(pi, value),
+        Block::Button(index, section) => match preset.button_mut(index) {
+            Some(b) => b.set(section),
+            None => return false,
+        },
+        Block::Encoder(index, section) => match preset.encoder_mut(index) {
+            Some(b) => b.set(section),
+            None => return false,
+        },
+        Block::Analog(index, section) => match preset.analog_mut(index) {
+            Some(b) => b.set(section),
+            None => return false,
+        },
+        Block::Led(index, LedSection::Global(value)) => {
+            match crate::led::LedIndex::try_from(index) {
+                Ok(led_index) => global.led.set(led_index, &value),
+                Err(_) => return false,
+            }
+        }
+        Block::Led(index, section) => match preset.led_mut(index) {
+            Some(b) => b.set(section),
+            None => return false,
+        },
+        Block::Global(_) | Block::Display | Block::Touchscreen => return false,
+    }
+    true
+}
+
+/// Reads the value addressed by `block` from `preset`, the counterpart of [`set_block`].
+pub(crate) fn get_block<const B: usize, const A: usize, const E: usize, const L: usize>(
+    presets: &[Preset<B, A, E, L>],
+    global: &GlobalConfig,
+    preset: usize,
+    block: Block,
+) -> Option<u16> {
+    let preset = presets.get(preset)?;
+    match block {
+        Block::Global(GlobalSection::Midi(i, _)) => Some(global.midi.get(i)),
+        Block::Global(GlobalSection::Presets(pi, _)) => Some(global.preset.get(pi)),
+        Block::Button(index, section) => preset.buttons.get(index as usize).map(|b| b.get(section)),
+        Block::Encoder(index, section) => {
+            preset.encoders.get(index as usize).map(|b| b.get(section))
+        }
+        Block::Analog(index, section) => preset.analogs.get(index as usize).map(|b| b.get(section)),
+        Block::Led(index, LedSection::Global(_)) => crate::led::LedIndex::try_from(index)
+            .ok()
+            .map(|led_index| global.led.get(&led_index)),
+        Block::Led(index, section) => preset.leds.get(index as usize).map(|b| b.get(section)),
+        Block::Global(_) | Block::Display | Block::Touchscreen => None,
+    }
 }
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use crate::MAX_MESSAGE_SIZE;
 
     use super::*;
     use midi2::Data;
 
-    struct NoopHandler;
+    pub(crate) struct NoopHandler;
     impl crate::SystemHandler for NoopHandler {
         fn reboot(&self) {}
         fn bootloader(&self) {}
         fn factory_reset(&self) {}
     }
 
-    #[test]
-    fn test_process_sysex_handshake() {
-        let version = FirmwareVersion {
-            major: 1,
-            minor: 0,
-            revision: 0,
-        };
-        let uid = 12345;
-        let mut config = Config::<1, 1, 1, 1, 1, _>::new(version, uid, NoopHandler);
-
-        let request = [0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0xF7]; // Example SysEx request for handshake
-        let mut responses = config.process_sysex(&request);
+    /// Firmware version of the configurations made by [`test_config`].
+    pub(crate) const VERSION: FirmwareVersion = FirmwareVersion {
+        major: 1,
+        minor: 0,
+        revision: 0,
+    };
 
-        let exp = &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x01, 0xF7][..];
-        let buf = &mut [0; MAX_MESSAGE_SIZE];
+    /// Configuration with default values and UID 0.
+    pub(crate) fn test_config<
+        const P: usize,
+        const B: usize,
+        const A: usize,
+        const E: usize,
+        const L: usize,
+    >() -> Config<P, B, A, E, L, NoopHandler> {
+        Config::new(VERSION, 0, NoopHandler)
+    }
+
+    fn open_session<
+        const P: usize,
+        const B: usize,
+        const A: usize,
+        const E: usize,
+        const L: usize,
+        H: crate::SystemHandler,
+    >(
+        config: &mut Config<P, B, A, E, L, H>,
+    ) {
+        let buf = &mut [0; MAX_MESSAGE_SIZE];
+        let mut responses = config.process_sysex(&[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0xF7]);
+        responses.next(buf, config).unwrap();
+        assert!(config.sysex_enabled());
+    }
+
+    #[test]
+    fn test_process_sysex_handshake() {
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let uid = 12345;
+        let mut config = Config::<1, 1, 1, 1, 1, _>::new(version, uid, NoopHandler);
+
+        let request = [0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0xF7]; // Example SysEx request for handshake
+        let mut responses = config.process_sysex(&request);
+
+        let exp = &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x01, 0xF7][..];
+        let buf = &mut [0; MAX_MESSAGE_SIZE];
         assert_eq!(
             responses.next(buf, &mut config).unwrap().unwrap().data(),
             exp
@@ -923,6 +1664,7 @@ mod tests {
         };
         let uid = 12345;
         let mut config = Config::<1, 1, 1, 1, 1, _>::new(version, uid, NoopHandler);
+        open_session(&mut config);
 
         let request = [
             0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x00, 0x00, 0x03, 0x03, 0x00, 0x00, 0x00, 0x00,
@@ -949,6 +1691,7 @@ mod tests {
         };
         let uid = 12345;
         let mut config = Config::<1, 20, 1, 1, 1, _>::new(version, uid, NoopHandler);
+        open_session(&mut config);
 
         let request = [
             0xF0, 0x00, 0x53, 0x43, 0x00, 0x7E, 0x00, 0x01, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00,
@@ -1137,6 +1880,7 @@ mod tests {
 
     /// Multi-part GET ALL for encoders (48 encoders = 2 parts)
     #[test]
+    #[allow(unused_imports)]
     fn test_get_all_multipart_encoders() {
         use crate::encoder::EncoderSection;
         let version = FirmwareVersion {
@@ -1176,6 +1920,7 @@ mod tests {
 
     /// Multi-part GET ALL for analog (40 analog = 2 parts)
     #[test]
+    #[allow(unused_imports)]
     fn test_get_all_multipart_analog() {
         use crate::analog::AnalogSection;
         let version = FirmwareVersion {
@@ -1212,6 +1957,7 @@ mod tests {
 
     /// Multi-part GET ALL for LEDs (96 LEDs = 3 parts)
     #[test]
+    #[allow(unused_imports)]
     fn test_get_all_multipart_leds() {
         use crate::led::LedSection;
         let version = FirmwareVersion {
@@ -1286,6 +2032,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::get_first)]
     fn test_preset_default_with_different_analog_and_encoder_counts() {
         // Config<P=1, B=6, A=2, E=4, L=8> — A != E exposes the generic param swap
         let version = FirmwareVersion {
@@ -1324,9 +2071,9 @@ mod tests {
         b.set(ButtonSection::Channel(ChannelOrAll::Channel(0))); // channel 1 (0-based)
         b.set(ButtonSection::Value(127));
 
-        // Enable global MIDI channel and set it to channel 5 (wire format 1-based)
+        // Enable global MIDI channel (wire format 1-based)
         config.global.midi.set(MidiIndex::UseGlobalMIDIchannel, 1);
-        config.global.midi.set(MidiIndex::GlobalMIDIchannel, 5); // wire 5 → Channel(5) → MIDI ch 5
+        config.global.midi.set(MidiIndex::GlobalMIDIchannel, 6); // wire 6 → Channel(5) → status nibble 5
 
         // Button press should use global channel (5) not per-component channel (0)
         let mut buf = [0u8; 8];
@@ -1378,6 +2125,142 @@ mod tests {
         assert_eq!(msg.data()[1], 60); // same note
     }
 
+    #[test]
+    fn test_component_info_while_session_is_open() {
+        use crate::analog::{AnalogMessageType, AnalogSection};
+        use crate::button::{ButtonMessageType, ButtonSection};
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config: Config<1, 3, 2, 1, 1, _> = Config::new(version, 0, NoopHandler);
+        let preset = config.current_preset_mut().unwrap();
+        let b = preset.button_mut(2).unwrap();
+        b.set(ButtonSection::MessageType(ButtonMessageType::Notes));
+        b.set(ButtonSection::MidiId(60));
+        let a = preset.analog_mut(1).unwrap();
+        a.set(AnalogSection::Enabled(true));
+        a.set(AnalogSection::MessageType(
+            AnalogMessageType::PotentiometerWithCCMessage7Bit,
+        ));
+        let buf = &mut [0u8; 16];
+
+        // without a session only MIDI is sent
+        let mut messages = config.handle_button(2, Action::Pressed);
+        assert_eq!(messages.next(buf).unwrap().unwrap().data()[0], 0x90);
+        assert_eq!(config.next_component_info(buf).unwrap(), None);
+        config.handle_button(2, Action::Released);
+
+        open_session(&mut config);
+        let mut messages = config.handle_button(2, Action::Pressed);
+        assert_eq!(messages.next(buf).unwrap().unwrap().data()[0], 0x90);
+        assert_eq!(messages.next(buf).unwrap(), None);
+        assert_eq!(
+            config.next_component_info(buf).unwrap().unwrap().data(),
+            &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x49, 0x01, 0x00, 0x02, 0xF7]
+        );
+        assert_eq!(config.next_component_info(buf).unwrap(), None);
+        // releasing the button doesn't report it again
+        let mut messages = config.handle_button(2, Action::Released);
+        assert_eq!(messages.next(buf).unwrap().unwrap().data()[0], 0x90);
+        assert_eq!(config.next_component_info(buf).unwrap(), None);
+
+        config.handle_encoder(0, EncoderPulse::Clockwise);
+        assert_eq!(
+            config.next_component_info(buf).unwrap().unwrap().data(),
+            &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x49, 0x02, 0x00, 0x00, 0xF7]
+        );
+
+        // analog movement is reported once every ANALOG_INFO_INTERVAL changes
+        let mut reports = 0;
+        for value in 0..2 * ANALOG_INFO_INTERVAL as u16 {
+            let mut messages = config.handle_analog(1, value * 100);
+            assert!(messages.next(buf).unwrap().is_some());
+            if config.next_component_info(buf).unwrap().is_some() {
+                reports += 1;
+            }
+        }
+        assert_eq!(reports, 2);
+        // unchanged values send nothing
+        let mut messages = config.handle_analog(1, (2 * ANALOG_INFO_INTERVAL as u16 - 1) * 100);
+        assert_eq!(messages.next(buf).unwrap(), None);
+        assert_eq!(config.next_component_info(buf).unwrap(), None);
+        // disabled components aren't reported
+        config.handle_analog(0, 1000);
+        assert_eq!(config.next_component_info(buf).unwrap(), None);
+    }
+
+    #[test]
+    fn test_analog_info_is_limited_per_component() {
+        use crate::analog::{AnalogMessageType, AnalogSection};
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config: Config<1, 1, 2, 1, 1, _> = Config::new(version, 0, NoopHandler);
+        for index in 0..2 {
+            let a = config
+                .current_preset_mut()
+                .unwrap()
+                .analog_mut(index)
+                .unwrap();
+            a.set(AnalogSection::Enabled(true));
+            a.set(AnalogSection::MessageType(
+                AnalogMessageType::PotentiometerWithCCMessage7Bit,
+            ));
+        }
+        open_session(&mut config);
+        let buf = &mut [0u8; 16];
+
+        // two pots moved together
+        let mut reports = [0; 2];
+        for value in 0..2 * ANALOG_INFO_INTERVAL as u16 {
+            for (index, reports) in reports.iter_mut().enumerate() {
+                config.handle_analog(index, value * 100);
+                if config.next_component_info(buf).unwrap().is_some() {
+                    *reports += 1;
+                }
+            }
+        }
+        assert_eq!(reports, [2, 2]);
+    }
+
+    #[test]
+    fn test_component_info_as_ump() {
+        use crate::button::{ButtonMessageType, ButtonSection};
+        use crate::ump::UmpMode;
+        use midi2::{ux::u4, Data};
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config: Config<1, 3, 1, 1, 1, _> = Config::new(version, 0, NoopHandler);
+        let b = config.current_preset_mut().unwrap().button_mut(2).unwrap();
+        b.set(ButtonSection::MessageType(ButtonMessageType::Notes));
+        b.set(ButtonSection::MidiId(60));
+        open_session(&mut config);
+        let buf = &mut [0u32; 8];
+
+        let mut messages = config.handle_button(2, Action::Pressed);
+        let note = messages.next_ump(UmpMode::Midi1, u4::new(0), buf);
+        assert_eq!(note.unwrap().unwrap().data()[0] >> 16, 0x2090);
+        assert_eq!(messages.next_ump(UmpMode::Midi1, u4::new(0), buf), Ok(None));
+        assert_eq!(
+            config
+                .next_component_info_ump(UmpMode::Midi1, u4::new(0), buf)
+                .unwrap()
+                .unwrap()
+                .data(),
+            &[0x3016_0053, 0x4301_0049, 0x3033_0100, 0x0200_0000]
+        );
+    }
+
     /// Verify that the global channel override also works for encoders
     #[test]
     fn test_global_midi_channel_overrides_encoder_channel() {
@@ -1402,9 +2285,9 @@ mod tests {
         e.set(EncoderSection::Channel(ChannelOrAll::Channel(1))); // ch 2
         e.set(EncoderSection::PulsesPerStep(1));
 
-        // Enable global channel = 10 (wire format)
+        // Enable global channel, wire format is 1-based
         config.global.midi.set(MidiIndex::UseGlobalMIDIchannel, 1);
-        config.global.midi.set(MidiIndex::GlobalMIDIchannel, 10); // wire 10 → Channel(10) → MIDI ch 10
+        config.global.midi.set(MidiIndex::GlobalMIDIchannel, 11); // wire 11 → Channel(10) → status nibble 10
 
         let mut buf = [0u8; 8];
         let mut messages = config.handle_encoder(0, EncoderPulse::Clockwise);
@@ -1436,9 +2319,9 @@ mod tests {
         ));
         a.set(AnalogSection::Channel(ChannelOrAll::Channel(2)));
 
-        // Enable global channel = 7 (wire format)
+        // Enable global channel, wire format is 1-based
         config.global.midi.set(MidiIndex::UseGlobalMIDIchannel, 1);
-        config.global.midi.set(MidiIndex::GlobalMIDIchannel, 7); // wire 7 → Channel(7) → MIDI ch 7
+        config.global.midi.set(MidiIndex::GlobalMIDIchannel, 8); // wire 8 → Channel(7) → status nibble 7
 
         let mut buf = [0u8; 8];
         let mut messages = config.handle_analog(0, 2048); // mid-range ADC value
@@ -1470,7 +2353,7 @@ mod tests {
 
         // Enable global channel, use it, then disable it
         config.global.midi.set(MidiIndex::UseGlobalMIDIchannel, 1);
-        config.global.midi.set(MidiIndex::GlobalMIDIchannel, 10);
+        config.global.midi.set(MidiIndex::GlobalMIDIchannel, 11);
 
         let mut buf = [0u8; 8];
         let mut messages = config.handle_button(0, Action::Pressed);
@@ -1497,6 +2380,7 @@ mod tests {
         };
         let mut config = Config::<1, 1, 1, 1, 1, _>::new(version, 0, NoopHandler);
         config.set_serial_number(&[0xAB, 0xCD, 0xEF, 0x12]);
+        open_session(&mut config);
 
         let request = [0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x53, 0xF7];
         let mut responses = config.process_sysex(&request);
@@ -1537,7 +2421,7 @@ mod tests {
         let mut config = Config::<1, 1, 1, 1, 1, _>::new(version, 0, TrackingHandler);
 
         // Handshake first (required to enable SysEx)
-        config.process_sysex(&[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0xF7]);
+        open_session(&mut config);
 
         CALLED.store(false, Ordering::Relaxed);
         let request = [0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x44, 0xF7];
@@ -1733,6 +2617,168 @@ mod tests {
         assert_eq!(config.bpm().get(), 119);
     }
 
+    #[test]
+    fn test_midi_clock_follows_bpm_and_transport() {
+        use crate::button::handler::Action;
+        use crate::button::{ButtonMessageType, ButtonSection};
+        use crate::global::MidiIndex;
+        use crate::midi_in::Realtime;
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config: Config<1, 2, 1, 1, 1, _> = Config::new(version, 0, NoopHandler);
+        for (index, message_type) in [
+            (0, ButtonMessageType::RealTimeStart),
+            (1, ButtonMessageType::BPMDecr),
+        ] {
+            config.process_req(OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                Block::Button(index, ButtonSection::MessageType(message_type)),
+            ));
+        }
+
+        assert_eq!(config.poll_clock(0), None);
+        config.process_req(OpenDeckRequest::Configuration(
+            Wish::Set,
+            Amount::Single,
+            Block::Global(GlobalSection::Midi(MidiIndex::SendMIDIclock, 1)),
+        ));
+        assert_eq!(config.poll_clock(0), Some(Realtime::Clock));
+        assert_eq!(config.poll_clock(20_832), None);
+        assert_eq!(config.poll_clock(20_833), Some(Realtime::Clock));
+
+        let mut buf = [0u8; 8];
+        let mut m = config.handle_button(0, Action::Pressed);
+        assert_eq!(m.next(&mut buf).unwrap().unwrap().data(), [0xFA]);
+        assert!(config.clock().is_running());
+        // the clock restarts with the transport
+        assert_eq!(config.poll_clock(30_000), Some(Realtime::Clock));
+        assert_eq!(config.poll_clock(30_000), None);
+
+        // at 119 BPM the pulses are 21008.4 µs apart
+        config.handle_button(1, Action::Pressed);
+        assert_eq!(config.poll_clock(51_007), None);
+        assert_eq!(config.poll_clock(51_008), Some(Realtime::Clock));
+    }
+
+    #[test]
+    fn test_bpm_follows_received_clock() {
+        use crate::clock::CLOCK_TIMEOUT_US;
+        use crate::global::MidiIndex;
+        use crate::midi_in::{MidiEvent, Realtime};
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config: Config<1, 1, 1, 1, 1, _> = Config::new(version, 0, NoopHandler);
+        config.process_req(OpenDeckRequest::Configuration(
+            Wish::Set,
+            Amount::Single,
+            Block::Global(GlobalSection::Midi(MidiIndex::SendMIDIclock, 1)),
+        ));
+        assert_eq!(config.poll_clock(0), Some(Realtime::Clock));
+
+        // 100 BPM, 25000 µs per pulse
+        let mut now = 0;
+        for _ in 0..24 {
+            now += 25_000;
+            let received = config.receive_midi(MidiEvent::Realtime(Realtime::Clock), now);
+            assert!(matches!(received, Received::Realtime(Realtime::Clock)));
+            // no clock is generated while one is received
+            assert_eq!(config.poll_clock(now), None);
+        }
+        assert!(config.follows_external_clock());
+        assert_eq!(config.bpm().get(), 100);
+
+        // the internal clock takes over once the received one is lost
+        assert_eq!(config.poll_clock(now + CLOCK_TIMEOUT_US - 1), None);
+        assert_eq!(
+            config.poll_clock(now + CLOCK_TIMEOUT_US),
+            Some(Realtime::Clock)
+        );
+        assert_eq!(config.poll_clock(now + CLOCK_TIMEOUT_US), None);
+        assert!(!config.follows_external_clock());
+        assert_eq!(config.bpm().get(), 100);
+
+        // also when the received clock stops after a transport jump
+        now += 1_000_000;
+        for _ in 0..10 {
+            now += 25_000;
+            config.receive_midi(MidiEvent::Realtime(Realtime::Clock), now);
+        }
+        config.receive_midi(MidiEvent::Realtime(Realtime::Stop), now + 1_000);
+        config.receive_midi(MidiEvent::Realtime(Realtime::Start), now + 2_000);
+        assert!(config.follows_external_clock());
+        let pulses = (0..10_000)
+            .filter(|ms| config.poll_clock(now + ms * 1_000).is_some())
+            .count();
+        assert!(pulses > 0);
+        assert!(!config.follows_external_clock());
+
+        // a lost clock is noticed on reception too, without polling
+        now += 1_000_000;
+        config.receive_midi(MidiEvent::Realtime(Realtime::Clock), now);
+        assert!(config.follows_external_clock());
+        config.receive_midi(
+            MidiEvent::NoteOn {
+                channel: u4::new(0),
+                note: 1,
+                velocity: 1,
+            },
+            now + CLOCK_TIMEOUT_US,
+        );
+        assert!(!config.follows_external_clock());
+    }
+
+    #[test]
+    fn test_tap_tempo() {
+        use crate::button::handler::Action;
+        use crate::button::{ButtonMessageType, ButtonSection};
+        use crate::encoder::{EncoderMessageType, EncoderSection};
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config: Config<1, 1, 1, 1, 1, _> = Config::new(version, 0, NoopHandler);
+        config.process_req(OpenDeckRequest::Configuration(
+            Wish::Set,
+            Amount::Single,
+            Block::Button(0, ButtonSection::MessageType(ButtonMessageType::TapTempo)),
+        ));
+        config.process_req(OpenDeckRequest::Configuration(
+            Wish::Set,
+            Amount::Single,
+            Block::Encoder(0, EncoderSection::MessageType(EncoderMessageType::TapTempo)),
+        ));
+
+        let mut buf = [0u8; 8];
+        for (now, action) in [
+            (0, Action::Pressed),
+            (100_000, Action::Released),
+            (600_000, Action::Pressed),
+        ] {
+            let mut m = config.handle_button_at(0, action, now);
+            assert_eq!(m.next(&mut buf), Ok(None));
+        }
+        assert_eq!(config.bpm().get(), 100);
+        // without a timestamp a tap is ignored
+        config.handle_button(0, Action::Pressed);
+        assert_eq!(config.bpm().get(), 100);
+
+        config.tap_tempo_mut().set_taps(2);
+        config.handle_encoder_at(0, EncoderPulse::Clockwise, 1_000_000);
+        config.handle_encoder_at(0, EncoderPulse::CounterClockwise, 1_400_000);
+        assert_eq!(config.bpm().get(), 150);
+    }
+
     /// Encoder BPM mode (0xA) should adjust BPM via rotation, no MIDI output
     #[test]
     fn test_encoder_bpm_mode() {
@@ -1864,46 +2910,1013 @@ mod tests {
         assert_eq!(config.output_level(0), 0);
     }
 
-    /// Regression test for issue #45: buttons configured as ProgramChange produce
-    /// 2-byte MIDI messages. Consumers must not require data.len() >= 3.
     #[test]
-    fn test_program_change_button_produces_2_byte_message() {
-        use crate::button::{ButtonMessageType, ButtonSection};
-        use midi2::Data;
+    fn test_receive_midi_bytes() {
+        use crate::led::{ControlType, LedSection};
+        use crate::midi_in::{MidiDecoder, Realtime};
+        use crate::ChannelOrAll;
 
         let version = FirmwareVersion {
             major: 1,
             minor: 0,
             revision: 0,
         };
-        let mut config = Config::<1, 10, 2, 2, 10, _>::new(version, 0, NoopHandler);
+        let mut config: Config<2, 1, 1, 1, 2, _> = Config::new(version, 0, NoopHandler);
+        for section in [
+            LedSection::ControlType(ControlType::MidiInNoteSingleValue),
+            LedSection::ActivationId(60),
+            LedSection::ActivationValue(127),
+            LedSection::Channel(ChannelOrAll::Channel(9)),
+        ] {
+            config.process_req(OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                Block::Led(0, section),
+            ));
+        }
 
-        // Configure button 2 as ProgramChange with program=5, channel=1
-        config.process_req(OpenDeckRequest::Configuration(
-            Wish::Set,
-            Amount::Single,
-            Block::Button(
-                2,
-                ButtonSection::MessageType(ButtonMessageType::ProgramChange),
-            ),
+        let mut decoder: MidiDecoder = MidiDecoder::new();
+        let mut receive = |config: &mut Config<2, 1, 1, 1, 2, _>, bytes: &[u8]| {
+            let mut last = None;
+            for &byte in bytes {
+                if let Some(event) = decoder.push(byte) {
+                    last = Some(config.receive_midi(event, 0));
+                }
+            }
+            last
+        };
+
+        // note on channel 10, the status byte carries 9
+        assert!(matches!(
+            receive(&mut config, &[0x99, 60, 0xF8, 127]),
+            Some(Received::Outputs(1))
+        ));
+        assert!(config.output_state(0));
+        // running status note on with velocity 0 turns it off
+        assert!(matches!(
+            receive(&mut config, &[60, 0]),
+            Some(Received::Outputs(1))
+        ));
+        assert!(!config.output_state(0));
+        assert!(matches!(
+            receive(&mut config, &[0x98, 60, 127]),
+            Some(Received::Outputs(0))
+        ));
+        assert!(matches!(
+            receive(&mut config, &[0xFA]),
+            Some(Received::Realtime(Realtime::Start))
+        ));
+
+        // program changes select presets only when enabled
+        assert!(matches!(
+            receive(&mut config, &[0xC0, 1]),
+            Some(Received::Ignored)
         ));
         config.process_req(OpenDeckRequest::Configuration(
             Wish::Set,
             Amount::Single,
-            Block::Button(2, ButtonSection::MidiId(5)),
+            Block::Global(GlobalSection::Presets(PresetIndex::EnableMidiChange, 1)),
+        ));
+        assert!(matches!(
+            receive(&mut config, &[1]),
+            Some(Received::PresetChange(1))
+        ));
+        assert_eq!(config.active_preset(), 1);
+        assert!(matches!(
+            receive(&mut config, &[2]),
+            Some(Received::Ignored)
         ));
+        assert_eq!(config.active_preset(), 1);
 
-        // Press button → should produce a ProgramChange message
-        let mut buf = [0u8; 6];
-        let mut messages = config.handle_button(2, crate::button::handler::Action::Pressed);
-        let msg = messages.next(&mut buf).unwrap();
-        assert!(msg.is_some(), "ProgramChange button must produce a message");
+        // SysEx is answered
+        let Some(Received::Sysex(mut responses)) = receive(
+            &mut config,
+            &[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0xF7],
+        ) else {
+            panic!("expected a SysEx response");
+        };
+        let buf = &mut [0; MAX_MESSAGE_SIZE];
+        let response = responses.next(buf, &mut config).unwrap().unwrap();
+        assert_eq!(
+            response.data(),
+            &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x01, 0xF7]
+        );
+    }
 
-        let m = msg.unwrap();
-        let data = m.data();
-        // ProgramChange is 2 bytes: [0xC0 | channel, program]
-        assert_eq!(data.len(), 2, "ProgramChange is a 2-byte MIDI message");
-        assert_eq!(data[0] & 0xF0, 0xC0);
-        assert_eq!(data[1], 5); // program number
+    #[test]
+    fn test_program_and_preset_change_outputs() {
+        use crate::button::{ButtonMessageType, ButtonSection};
+        use crate::led::{ControlType, LedIndex, LedSection};
+        use crate::midi_in::MidiEvent;
+        use crate::ChannelOrAll;
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config: Config<2, 2, 1, 1, 2, _> = Config::new(version, 0, NoopHandler);
+        let set = |config: &mut Config<2, 2, 1, 1, 2, _>, block| {
+            config.process_req(OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                block,
+            ));
+        };
+        for preset in [1, 0] {
+            set(
+                &mut config,
+                Block::Global(GlobalSection::Presets(PresetIndex::Active, preset)),
+            );
+            for section in [
+                LedSection::ControlType(ControlType::PresetChange),
+                LedSection::ActivationId(1),
+            ] {
+                set(&mut config, Block::Led(0, section));
+            }
+        }
+        for section in [
+            LedSection::ControlType(ControlType::ProgramChange),
+            LedSection::ActivationId(3),
+            LedSection::Channel(ChannelOrAll::Channel(0)),
+        ] {
+            set(&mut config, Block::Led(1, section));
+        }
+        for section in [
+            ButtonSection::MessageType(ButtonMessageType::ProgramChangeOffsetIncr),
+            ButtonSection::Value(2),
+        ] {
+            set(&mut config, Block::Button(0, section));
+        }
+        for section in [
+            ButtonSection::MessageType(ButtonMessageType::ProgramChange),
+            ButtonSection::MidiId(3),
+        ] {
+            set(&mut config, Block::Button(1, section));
+        }
+
+        assert!(!config.output_state(0));
+        assert_eq!(config.notify_program_change(0, 3), 1);
+        assert!(config.output_state(1));
+        assert_eq!(config.notify_program_change(0, 4), 1);
+        assert!(!config.output_state(1));
+        assert_eq!(config.notify_program_change(1, 3), 0);
+
+        // the offset only applies once the LEDs are set to use it
+        config.handle_button(0, Action::Pressed);
+        assert_eq!(config.program_offset(), 2);
+        let mut buf = [0u8; 8];
+        let Messages::Button(mut messages) = config.handle_button(1, Action::Pressed) else {
+            panic!("expected button messages");
+        };
+        assert_eq!(messages.next(&mut buf).unwrap().unwrap().data(), [0xC0, 5]);
+        config.notify_program_change(0, 3);
+        assert!(config.output_state(1));
+        set(
+            &mut config,
+            Block::Led(
+                LedIndex::UseMidiProgramChangeOffset as u16,
+                LedSection::Global(1),
+            ),
+        );
+        config.notify_program_change(0, 3);
+        assert!(!config.output_state(1));
+        config.notify_program_change(0, 5);
+        assert!(config.output_state(1));
+
+        // switching presets lights the preset indicator of the new preset, and the
+        // program change outputs of the new preset follow the program change
+        set(
+            &mut config,
+            Block::Led(
+                LedIndex::UseMidiProgramChangeOffset as u16,
+                LedSection::Global(0),
+            ),
+        );
+        set(
+            &mut config,
+            Block::Global(GlobalSection::Presets(PresetIndex::Active, 1)),
+        );
+        for section in [
+            LedSection::ControlType(ControlType::ProgramChange),
+            LedSection::ActivationId(1),
+            LedSection::Channel(ChannelOrAll::Channel(0)),
+        ] {
+            set(&mut config, Block::Led(1, section));
+        }
+        set(
+            &mut config,
+            Block::Global(GlobalSection::Presets(PresetIndex::Active, 0)),
+        );
+        set(
+            &mut config,
+            Block::Global(GlobalSection::Presets(PresetIndex::EnableMidiChange, 1)),
+        );
+        assert!(matches!(
+            config.receive_midi(
+                MidiEvent::ProgramChange {
+                    channel: midi2::ux::u4::new(0),
+                    program: 1,
+                },
+                0
+            ),
+            Received::PresetChange(1)
+        ));
+        assert!(config.output_state(0));
+        assert!(config.output_state(1));
+        config.set_active_preset(0);
+        assert!(!config.output_state(0));
+    }
+
+    #[test]
+    fn test_output_blinks_with_midi_clock() {
+        use crate::led::{blink::OutputDrive, ControlType, LedIndex, LedSection};
+        use crate::midi_in::{MidiEvent, Realtime};
+        use crate::ChannelOrAll;
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config: Config<1, 1, 1, 1, 1, _> = Config::new(version, 0, NoopHandler);
+        for section in [
+            LedSection::ControlType(ControlType::MidiInCcMultiValue),
+            LedSection::ActivationId(7),
+            LedSection::Channel(ChannelOrAll::Channel(0)),
+        ] {
+            config.process_req(OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                Block::Led(0, section),
+            ));
+        }
+        // 500 ms or 24 pulses, full brightness
+        config.receive_midi(
+            MidiEvent::ControlChange {
+                channel: midi2::ux::u4::new(0),
+                control: 7,
+                value: 79,
+            },
+            0,
+        );
+        let on = OutputDrive {
+            on: true,
+            brightness: 100,
+        };
+        assert_eq!(config.output_drive(0), on);
+        config.tick_outputs(500);
+        assert_eq!(config.output_drive(0), OutputDrive::OFF);
+        config.tick_outputs(500);
+
+        config.process_req(OpenDeckRequest::Configuration(
+            Wish::Set,
+            Amount::Single,
+            Block::Led(LedIndex::BlinkWithMIDIClock as u16, LedSection::Global(1)),
+        ));
+        for _ in 0..24 {
+            assert_eq!(config.output_drive(0), on);
+            config.receive_midi(MidiEvent::Realtime(Realtime::Clock), 0);
+        }
+        assert_eq!(config.output_drive(0), OutputDrive::OFF);
+        config.receive_midi(MidiEvent::Realtime(Realtime::Start), 0);
+        assert_eq!(config.output_drive(0), on);
+        assert_eq!(config.output_drive(1), OutputDrive::OFF);
+    }
+
+    /// Regression test for issue #45: buttons configured as ProgramChange produce
+    /// 2-byte MIDI messages. Consumers must not require data.len() >= 3.
+    #[test]
+    fn test_program_change_button_produces_2_byte_message() {
+        use crate::button::{ButtonMessageType, ButtonSection};
+        use midi2::Data;
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config = Config::<1, 10, 2, 2, 10, _>::new(version, 0, NoopHandler);
+
+        // Configure button 2 as ProgramChange with program=5, channel=1
+        config.process_req(OpenDeckRequest::Configuration(
+            Wish::Set,
+            Amount::Single,
+            Block::Button(
+                2,
+                ButtonSection::MessageType(ButtonMessageType::ProgramChange),
+            ),
+        ));
+        config.process_req(OpenDeckRequest::Configuration(
+            Wish::Set,
+            Amount::Single,
+            Block::Button(2, ButtonSection::MidiId(5)),
+        ));
+
+        // Press button → should produce a ProgramChange message
+        let mut buf = [0u8; 6];
+        let mut messages = config.handle_button(2, crate::button::handler::Action::Pressed);
+        let msg = messages.next(&mut buf).unwrap();
+        assert!(msg.is_some(), "ProgramChange button must produce a message");
+
+        let m = msg.unwrap();
+        let data = m.data();
+        // ProgramChange is 2 bytes: [0xC0 | channel, program]
+        assert_eq!(data.len(), 2, "ProgramChange is a 2-byte MIDI message");
+        assert_eq!(data[0] & 0xF0, 0xC0);
+        assert_eq!(data[1], 5); // program number
+    }
+
+    struct LogHandler {
+        log: crate::storage::log::LogStore<crate::storage::MemoryStorage<512>>,
+    }
+
+    impl crate::SystemHandler for LogHandler {
+        fn reboot(&self) {}
+        fn bootloader(&self) {}
+        fn factory_reset(&self) {}
+        fn store_value(&mut self, key: StorageKey, value: u16) -> Result<(), StorageError> {
+            self.log.write(key, value)
+        }
+        fn load_values(
+            &mut self,
+            apply: &mut dyn FnMut(StorageKey, u16),
+        ) -> Result<(), StorageError> {
+            self.log.replay(apply)
+        }
+    }
+
+    #[test]
+    fn test_set_values_are_replayed_from_log() {
+        use crate::button::ButtonSection;
+        use crate::global::PresetIndex;
+        use crate::led::{LedIndex, LedSection};
+        use crate::storage::{log::LogStore, MemoryStorage};
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let log = LogStore::mount(MemoryStorage::<512>::new(), 128, 4).unwrap();
+        let mut config = Config::<2, 2, 1, 1, 1, _>::new(version, 1, LogHandler { log });
+
+        for block in [
+            Block::Button(1, ButtonSection::MidiId(42)),
+            Block::Led(LedIndex::BlinkWithMIDIClock as u16, LedSection::Global(1)),
+            Block::Global(GlobalSection::Presets(PresetIndex::Active, 1)),
+            Block::Button(0, ButtonSection::MidiId(7)),
+            // out of range, must not be stored
+            Block::Button(5, ButtonSection::MidiId(9)),
+        ] {
+            config.process_req(OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                block,
+            ));
+        }
+
+        let storage = config.handler.log.release();
+        let log = LogStore::mount(storage, 128, 4).unwrap();
+        let mut config = Config::<2, 2, 1, 1, 1, _>::new(version, 1, LogHandler { log });
+        config.load_stored().unwrap();
+
+        assert_eq!(config.active_preset(), 1);
+        assert_eq!(
+            config.presets[1].buttons[0].get(ButtonSection::MidiId(0)),
+            7
+        );
+        assert_eq!(
+            config.presets[0].buttons[1].get(ButtonSection::MidiId(0)),
+            42
+        );
+        assert_eq!(
+            config.presets[0].buttons[0].get(ButtonSection::MidiId(0)),
+            0
+        );
+        assert_eq!(config.global.led.get(&LedIndex::BlinkWithMIDIClock), 1);
+    }
+
+    #[test]
+    fn test_invalid_stored_values_are_skipped() {
+        use crate::button::ButtonSection;
+        use crate::global::PresetIndex;
+        use crate::storage::{log::LogStore, MemoryStorage};
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut log = LogStore::mount(MemoryStorage::<512>::new(), 128, 4).unwrap();
+        for (preset, block) in [
+            (
+                0,
+                Block::Global(GlobalSection::Presets(PresetIndex::Active, 2)),
+            ),
+            (2, Block::Button(0, ButtonSection::MidiId(9))),
+            (0, Block::Button(5, ButtonSection::MidiId(9))),
+            (1, Block::Button(1, ButtonSection::MidiId(8))),
+        ] {
+            let (key, value) = StorageKey::from_block(preset, block);
+            log.write(key, value).unwrap();
+        }
+        let mut config = Config::<2, 2, 1, 1, 1, _>::new(version, 1, LogHandler { log });
+        config.load_stored().unwrap();
+
+        assert_eq!(config.active_preset(), 0);
+        assert_eq!(
+            config.presets[1].buttons[1].get(ButtonSection::MidiId(0)),
+            8
+        );
+    }
+
+    struct FailingHandler(StorageError);
+
+    impl crate::SystemHandler for FailingHandler {
+        fn reboot(&self) {}
+        fn bootloader(&self) {}
+        fn factory_reset(&self) {}
+        fn store_value(&mut self, _key: StorageKey, _value: u16) -> Result<(), StorageError> {
+            Err(self.0)
+        }
+    }
+
+    #[test]
+    fn test_failed_store_returns_write_error_and_rolls_back() {
+        use crate::button::ButtonSection;
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let handler = FailingHandler(StorageError::WriteFailed);
+        let mut config = Config::<1, 2, 1, 1, 1, _>::new(version, 1, handler);
+        open_session(&mut config);
+        let before = config.presets[0].buttons[1].get(ButtonSection::MidiId(0));
+
+        // SET button 1 MidiId = 5
+        let request = [
+            0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x01, 0x02, 0x00, 0x01, 0x00, 0x05,
+            0xF7,
+        ];
+        let mut responses = config.process_sysex(&request);
+        let exp = &[
+            0xF0, 0x00, 0x53, 0x43, 0x0C, 0x00, 0x01, 0x00, 0x01, 0x02, 0x00, 0x01, 0x00, 0x05,
+            0xF7,
+        ];
+        let buf = &mut [0; MAX_MESSAGE_SIZE];
+        assert_eq!(
+            responses.next(buf, &mut config).unwrap().unwrap().data(),
+            exp
+        );
+        assert!(responses.next(buf, &mut config).unwrap().is_none());
+        assert_eq!(
+            config.presets[0].buttons[1].get(ButtonSection::MidiId(0)),
+            before
+        );
+    }
+
+    #[test]
+    fn test_failed_store_rolls_back_channel_and_global() {
+        use crate::button::ButtonSection;
+        use crate::global::PresetIndex;
+        use crate::ChannelOrAll;
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let handler = FailingHandler(StorageError::ReadFailed);
+        let mut config = Config::<2, 1, 1, 1, 1, _>::new(version, 1, handler);
+        config.presets[0].buttons[0].set(ButtonSection::Channel(ChannelOrAll::Channel(3)));
+
+        let res = config.process_req_with_status(OpenDeckRequest::Configuration(
+            Wish::Set,
+            Amount::Single,
+            Block::Button(0, ButtonSection::Channel(ChannelOrAll::Channel(9))),
+        ));
+        assert_eq!(
+            res.map(|(_, status)| status),
+            Some(MessageStatus::ReadError)
+        );
+        assert_eq!(
+            config.presets[0].buttons[0].get(ButtonSection::Channel(ChannelOrAll::None)),
+            u16::from(ChannelOrAll::Channel(3))
+        );
+
+        let res = config.process_req_with_status(OpenDeckRequest::Configuration(
+            Wish::Set,
+            Amount::Single,
+            Block::Global(GlobalSection::Presets(PresetIndex::Active, 1)),
+        ));
+        assert_eq!(
+            res.map(|(_, status)| status),
+            Some(MessageStatus::ReadError)
+        );
+        assert_eq!(config.active_preset(), 0);
+    }
+
+    /// Stores every value except the `fail_at`th one.
+    struct FlakyHandler {
+        stored: heapless::LinearMap<StorageKey, u16, 8>,
+        calls: usize,
+        fail_at: usize,
+    }
+
+    impl crate::SystemHandler for FlakyHandler {
+        fn reboot(&self) {}
+        fn bootloader(&self) {}
+        fn factory_reset(&self) {}
+        fn store_value(&mut self, key: StorageKey, value: u16) -> Result<(), StorageError> {
+            self.calls += 1;
+            if self.calls == self.fail_at {
+                return Err(StorageError::WriteFailed);
+            }
+            self.stored.insert(key, value).unwrap();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_failed_store_rolls_back_set_all() {
+        use crate::button::ButtonSection;
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let handler = FlakyHandler {
+            stored: heapless::LinearMap::new(),
+            calls: 0,
+            fail_at: 3,
+        };
+        let mut config = Config::<1, 4, 1, 1, 1, _>::new(version, 1, handler);
+        let block = Block::Button(0, ButtonSection::MidiId(0));
+        assert_eq!(
+            config.set_all(0, block, &[10, 11, 12, 13]),
+            MessageStatus::WriteError
+        );
+        // the first two values are set back in RAM and storage
+        for index in 0..4 {
+            assert_eq!(
+                config.presets[0].buttons[index].get(ButtonSection::MidiId(0)),
+                index as u16
+            );
+        }
+        for index in 0..2 {
+            let (key, _) =
+                StorageKey::from_block(0, Block::Button(index, ButtonSection::MidiId(0)));
+            assert_eq!(config.handler.stored.get(&key), Some(&index));
+        }
+        assert_eq!(config.handler.stored.len(), 2);
+    }
+
+    /// One-byte protocol: 14-bit values are set and read through their LSB and MSB
+    /// sections.
+    #[test]
+    fn test_one_byte_protocol_split_sections() {
+        use crate::encoder::EncoderSection;
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config = Config::<1, 1, 1, 2, 1, _>::new(version, 0, NoopHandler);
+        config.set_value_size(ValueSize::OneByte);
+        open_session(&mut config);
+        let buf = &mut [0; MAX_MESSAGE_SIZE];
+
+        let request = [0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x02, 0xF7];
+        let mut responses = config.process_sysex(&request);
+        assert_eq!(
+            responses.next(buf, &mut config).unwrap().unwrap().data(),
+            &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x02, 0x01, 0xF7]
+        );
+
+        // encoder 1 MIDI ID 200 = LSB 0x48, MSB 0x01
+        for request in [
+            [
+                0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x02, 0x03, 0x01, 0x48, 0xF7,
+            ],
+            [
+                0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x02, 0x07, 0x01, 0x01, 0xF7,
+            ],
+        ] {
+            let mut responses = config.process_sysex(&request);
+            let res = responses.next(buf, &mut config).unwrap().unwrap();
+            assert_eq!(res.data()[4], 0x01);
+        }
+        assert_eq!(
+            config.presets[0].encoders[1].get(EncoderSection::MidiIdLSB(0)),
+            200
+        );
+
+        // a later LSB keeps the upper bits
+        let request = [
+            0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x02, 0x03, 0x01, 0x05, 0xF7,
+        ];
+        let mut responses = config.process_sysex(&request);
+        responses.next(buf, &mut config).unwrap();
+        assert_eq!(
+            config.presets[0].encoders[1].get(EncoderSection::MidiIdLSB(0)),
+            0x85
+        );
+
+        let request = [
+            0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x01, 0x00, 0xF7,
+        ];
+        let mut responses = config.process_sysex(&request);
+        assert_eq!(
+            responses.next(buf, &mut config).unwrap().unwrap().data(),
+            &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x00, 0x00, 0x02, 0x03, 0x01, 0x00, 0x05, 0xF7]
+        );
+        let request = [
+            0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x00, 0x00, 0x02, 0x07, 0x01, 0x00, 0xF7,
+        ];
+        let mut responses = config.process_sysex(&request);
+        assert_eq!(
+            responses.next(buf, &mut config).unwrap().unwrap().data(),
+            &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x00, 0x00, 0x02, 0x07, 0x01, 0x00, 0x01, 0xF7]
+        );
+    }
+
+    /// With two-byte values the LSB section carries the whole value, an MSB
+    /// section still only replaces the upper bits.
+    #[test]
+    fn test_two_byte_protocol_msb_section() {
+        use crate::analog::AnalogSection;
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config = Config::<1, 1, 1, 1, 1, _>::new(version, 0, NoopHandler);
+        for block in [
+            Block::Analog(0, AnalogSection::UpperCCLimit(0x1234)),
+            Block::Analog(0, AnalogSection::UpperCCLimitMSB(0x02)),
+        ] {
+            config.process_req(OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                block,
+            ));
+        }
+        assert_eq!(
+            config.presets[0].analogs[0].get(AnalogSection::UpperCCLimit(0)),
+            0x0134
+        );
+        assert_eq!(
+            config.process_req(OpenDeckRequest::Configuration(
+                Wish::Get,
+                Amount::Single,
+                Block::Analog(0, AnalogSection::UpperCCLimit(0)),
+            )),
+            Some(OpenDeckResponse::Configuration(
+                Wish::Get,
+                Amount::Single,
+                Block::Analog(0, AnalogSection::UpperCCLimit(0)),
+                NewValues::from_slice(&[0x0134]).unwrap(),
+            ))
+        );
+    }
+
+    /// Wiki: MESSAGE_STATUS > HANDSHAKE_ERROR (3)
+    /// Before the handshake only the handshake itself and the identification requests
+    /// are answered.
+    #[test]
+    fn test_special_requests_allowed_before_handshake() {
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 2,
+            revision: 3,
+        };
+        let buf = &mut [0; MAX_MESSAGE_SIZE];
+        for (id, response) in [
+            (0x56, &[0x00, 0x01, 0x00, 0x02, 0x00, 0x03][..]),
+            (0x42, &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2A]),
+            (
+                0x43,
+                &[
+                    0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x2A,
+                ],
+            ),
+            (0x01, &[]),
+        ] {
+            let mut config = Config::<1, 1, 1, 1, 1, _>::new(version, 42, NoopHandler);
+            let mut responses =
+                config.process_sysex(&[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, id, 0xF7]);
+            let res = responses.next(buf, &mut config).unwrap().unwrap();
+            let data = res.data();
+            assert_eq!(data[4], 0x01, "request {id:#x}");
+            assert_eq!(data[6], id);
+            assert_eq!(&data[7..data.len() - 1], response, "request {id:#x}");
+        }
+    }
+
+    #[test]
+    fn test_requests_rejected_before_handshake() {
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        static CALLED: AtomicBool = AtomicBool::new(false);
+        struct TrackingHandler;
+        impl crate::SystemHandler for TrackingHandler {
+            fn reboot(&self) {
+                CALLED.store(true, Ordering::Relaxed);
+            }
+            fn bootloader(&self) {
+                CALLED.store(true, Ordering::Relaxed);
+            }
+            fn factory_reset(&self) {
+                CALLED.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config = Config::<1, 1, 1, 1, 1, _>::new(version, 0, TrackingHandler);
+        let handshake_error = &[0xF0, 0x00, 0x53, 0x43, 0x03, 0x00, 0x01, 0xF7][..];
+        let buf = &mut [0; MAX_MESSAGE_SIZE];
+
+        let specials = [
+            0x00, 0x02, 0x03, 0x4D, 0x7F, 0x55, 0x44, 0x50, 0x51, 0x1B, 0x53, 0x1C, 0x1D,
+        ];
+        for id in specials {
+            let mut responses =
+                config.process_sysex(&[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, id, 0xF7]);
+            assert_eq!(
+                responses.next(buf, &mut config).unwrap().unwrap().data(),
+                handshake_error,
+                "request {id:#x}"
+            );
+            assert!(responses.next(buf, &mut config).unwrap().is_none());
+        }
+        assert!(!CALLED.load(Ordering::Relaxed));
+
+        // SET button 0 MidiId = 5
+        let set = [
+            0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x05,
+            0xF7,
+        ];
+        let mut responses = config.process_sysex(&set);
+        assert_eq!(
+            responses.next(buf, &mut config).unwrap().unwrap().data(),
+            handshake_error
+        );
+        assert_eq!(
+            config.presets[0].buttons[0].get(crate::button::ButtonSection::MidiId(0)),
+            0
+        );
+
+        // accepted once the session is open, rejected again after it is closed
+        open_session(&mut config);
+        let mut responses = config.process_sysex(&set);
+        assert_eq!(
+            responses.next(buf, &mut config).unwrap().unwrap().data()[4],
+            0x01
+        );
+        let mut responses = config.process_sysex(&[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x00, 0xF7]);
+        responses.next(buf, &mut config).unwrap();
+        assert!(!config.sysex_enabled());
+        let mut responses = config.process_sysex(&set);
+        assert_eq!(
+            responses.next(buf, &mut config).unwrap().unwrap().data(),
+            handshake_error
+        );
+    }
+
+    #[test]
+    fn test_invalid_requests_report_their_status() {
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config = Config::<1, 2, 1, 1, 1, _>::new(version, 0, NoopHandler);
+        open_session(&mut config);
+        let buf = &mut [0; MAX_MESSAGE_SIZE];
+
+        for (request, status) in [
+            // GET button 2 MidiId, the board has 2 buttons
+            (
+                &[
+                    0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x02, 0x00,
+                    0x00, 0xF7,
+                ][..],
+                MessageStatus::IndexError,
+            ),
+            // SET button 0 MidiId = 128
+            (
+                &[
+                    0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x01, 0x02, 0x00, 0x00, 0x01,
+                    0x00, 0xF7,
+                ],
+                MessageStatus::NewValueError,
+            ),
+            // GET global OSC
+            (
+                &[
+                    0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+                    0x00, 0xF7,
+                ],
+                MessageStatus::NotSupportedError,
+            ),
+            // GET ALL button MidiIds, part 1 of a single part
+            (
+                &[
+                    0xF0, 0x00, 0x53, 0x43, 0x00, 0x01, 0x00, 0x01, 0x01, 0x02, 0x00, 0x00, 0x00,
+                    0x00, 0xF7,
+                ],
+                MessageStatus::PartError,
+            ),
+            // GET button 0 MidiId without a value
+            (
+                &[
+                    0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0xF7,
+                ],
+                MessageStatus::MessageLengthError,
+            ),
+        ] {
+            let mut responses = config.process_sysex(request);
+            let res = responses.next(buf, &mut config).unwrap().unwrap();
+            assert_eq!(res.data()[4], status as u8, "{request:x?}");
+            assert!(responses.next(buf, &mut config).unwrap().is_none());
+        }
+        assert_eq!(
+            config.presets[0].buttons[0].get(crate::button::ButtonSection::MidiId(0)),
+            0
+        );
+    }
+
+    fn set_all<
+        const P: usize,
+        const B: usize,
+        const A: usize,
+        const E: usize,
+        const L: usize,
+        H: crate::SystemHandler,
+    >(
+        config: &mut Config<P, B, A, E, L, H>,
+        part: u8,
+        block: Block,
+        values: &[u16],
+    ) -> Vec<u8, MAX_MESSAGE_SIZE> {
+        let request = &mut [0; MAX_MESSAGE_SIZE];
+        let request = crate::client::RequestRenderer::new(config.value_size(), request)
+            .render_set_all(part, block, values)
+            .unwrap();
+        let buf = &mut [0; MAX_MESSAGE_SIZE];
+        let mut responses = config.process_sysex(request.data());
+        let res = Vec::from_slice(responses.next(buf, config).unwrap().unwrap().data()).unwrap();
+        assert!(responses.next(buf, config).unwrap().is_none());
+        res
+    }
+
+    #[test]
+    fn test_set_all_multipart() {
+        use crate::{button::ButtonSection, led::LedSection, ChannelOrAll};
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config = Config::<1, 40, 1, 1, 3, _>::new(version, 0, NoopHandler);
+        open_session(&mut config);
+        let block = Block::Button(0, ButtonSection::MidiId(0));
+
+        let part0: Vec<u16, 32> = (0..32).map(|i| 50 + i).collect();
+        let res = set_all(&mut config, 0, block, &part0);
+        assert_eq!(&res[..6], &[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00]);
+        // the response echoes the values
+        assert_eq!(res.len(), 14 + 32 * 2 + 1);
+        assert_eq!(&res[14..16], &[0x00, 50]);
+
+        let res = set_all(&mut config, 1, block, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(&res[4..6], &[0x01, 0x01]);
+        for (i, button) in config.presets[0].buttons.iter().enumerate() {
+            let expected = if i < 32 { 50 + i as u16 } else { i as u16 - 31 };
+            assert_eq!(button.get(ButtonSection::MidiId(0)), expected);
+        }
+
+        // there is no third part
+        let res = set_all(&mut config, 2, block, &[1]);
+        assert_eq!(res[4], MessageStatus::PartError as u8);
+        // the second part holds 8 buttons
+        let res = set_all(&mut config, 1, block, &[0; 3]);
+        assert_eq!(res[4], MessageStatus::MessageLengthError as u8);
+        // values are checked before any of them is set
+        let res = set_all(&mut config, 1, block, &[0, 0, 0, 0, 0, 0, 0, 200]);
+        assert_eq!(res[4], MessageStatus::NewValueError as u8);
+        assert_eq!(
+            config.presets[0].buttons[32].get(ButtonSection::MidiId(0)),
+            1
+        );
+
+        let res = set_all(
+            &mut config,
+            0,
+            Block::Led(0, LedSection::Channel(ChannelOrAll::None)),
+            &[2, 3, 4],
+        );
+        assert_eq!(res[4], 0x01);
+        assert_eq!(
+            config.presets[0].leds[2].get(LedSection::Channel(ChannelOrAll::None)),
+            4
+        );
+        let res = set_all(
+            &mut config,
+            0,
+            Block::Led(0, LedSection::Global(0)),
+            &[0; 4],
+        );
+        assert_eq!(res[4], MessageStatus::NotSupportedError as u8);
+    }
+
+    #[test]
+    fn test_set_all_one_byte_keeps_upper_bits() {
+        use crate::analog::AnalogSection;
+
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        let mut config = Config::<1, 1, 2, 1, 1, _>::new(version, 0, NoopHandler);
+        config.set_value_size(ValueSize::OneByte);
+        open_session(&mut config);
+
+        let msb = Block::Analog(0, AnalogSection::MidiIdMSB(0));
+        assert_eq!(set_all(&mut config, 0, msb, &[1, 2])[4], 0x01);
+        let lsb = Block::Analog(0, AnalogSection::MidiId(0));
+        assert_eq!(set_all(&mut config, 0, lsb, &[5, 6])[4], 0x01);
+        assert_eq!(
+            config.presets[0].analogs[0].get(AnalogSection::MidiId(0)),
+            0x85
+        );
+        assert_eq!(
+            config.presets[0].analogs[1].get(AnalogSection::MidiId(0)),
+            0x106
+        );
+    }
+
+    mod proptests {
+        use super::*;
+        use proptest::prelude::*;
+
+        fn process(value_size: ValueSize, request: &[u8]) {
+            let version = FirmwareVersion {
+                major: 1,
+                minor: 0,
+                revision: 0,
+            };
+            let mut config = Config::<2, 40, 2, 2, 2, _>::new(version, 0, NoopHandler);
+            config.set_value_size(value_size);
+            open_session(&mut config);
+            let buf = &mut [0; MAX_MESSAGE_SIZE];
+            let mut responses = config.process_sysex(request);
+            // a backup is the longest response
+            for _ in 0..2048 {
+                if responses.next(buf, &mut config).unwrap().is_none() {
+                    return;
+                }
+            }
+            panic!("response doesn't end");
+        }
+
+        fn value_size() -> impl Strategy<Value = ValueSize> {
+            prop_oneof![Just(ValueSize::OneByte), Just(ValueSize::TwoBytes)]
+        }
+
+        proptest! {
+            #[test]
+            fn process_sysex_never_panics(
+                value_size in value_size(),
+                request in proptest::collection::vec(any::<u8>(), 0..96),
+            ) {
+                process(value_size, &request);
+            }
+
+            #[test]
+            fn process_sysex_never_panics_on_opendeck_frames(
+                value_size in value_size(),
+                payload in proptest::collection::vec(0u8..0x80, 0..80),
+            ) {
+                let mut request = Vec::<u8, 96>::new();
+                request.extend_from_slice(&[0xF0, 0x00, 0x53, 0x43, 0x00]).unwrap();
+                request.extend_from_slice(&payload).unwrap();
+                request.push(0xF7).unwrap();
+                process(value_size, &request);
+            }
+        }
     }
 }
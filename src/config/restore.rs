@@ -0,0 +1,442 @@
+//! Restore of a backup sent by the configurator.
+//!
+//! Between `RestoreStart` and `RestoreEnd` every `Wish::Set` is applied to a
+//! copy of the configuration. The copy replaces the live configuration on
+//! `RestoreEnd`; an invalid value, a malformed OpenDeck message or
+//! `ConnectionClose` drops it, so an interrupted restore leaves the board as it
+//! was.
+
+use crate::{
+    config::{
+        backup::ConfigBackupIterator, get_block, set_block, split, Config, GlobalConfig, Preset,
+    },
+    global::{GlobalSection, PresetIndex},
+    storage::{StorageError, StorageKey},
+    Block, MessageStatus, OpenDeckResponse, Wish,
+};
+use heapless::Vec;
+
+pub struct RestoreSession<
+    const P: usize,
+    const B: usize,
+    const A: usize,
+    const E: usize,
+    const L: usize,
+> {
+    presets: Vec<Preset<B, A, E, L>, P>,
+    global: GlobalConfig,
+}
+
+impl<
+        const P: usize,
+        const B: usize,
+        const A: usize,
+        const E: usize,
+        const L: usize,
+        H: crate::SystemHandler,
+    > Config<P, B, A, E, L, H>
+{
+    /// Starts staging from the current configuration, dropping any earlier session.
+    pub(crate) fn start_restore(&mut self) {
+        self.restore = Some(RestoreSession {
+            presets: self.presets.clone(),
+            global: self.global.clone(),
+        });
+    }
+
+    pub(crate) fn abort_restore(&mut self) {
+        #[cfg(feature = "defmt")]
+        if self.restore.is_some() {
+            defmt::warn!("restore aborted");
+        }
+        self.restore = None;
+    }
+
+    /// Applies `block` to the staged configuration.
+    pub(crate) fn stage_restore(&mut self, block: Block) -> MessageStatus {
+        let Some(session) = self.restore.as_mut() else {
+            return MessageStatus::Response;
+        };
+        let preset = session.global.preset.current;
+        let block = split::join(block, self.value_size, |lsb| {
+            get_block(&session.presets, &session.global, preset, lsb)
+        });
+        if set_block(&mut session.presets, &mut session.global, preset, block) {
+            return MessageStatus::Response;
+        }
+        self.abort_restore();
+        MessageStatus::IndexError
+    }
+
+    /// Replaces the configuration with the staged one and persists the values
+    /// that changed.
+    ///
+    /// When storing fails, the values stored so far are put back and the
+    /// previous configuration stays in RAM, except for values that can't be
+    /// put back, so RAM matches what survives a reboot.
+    pub(crate) fn finish_restore(&mut self) -> MessageStatus {
+        let Some(mut previous) = self.restore.take() else {
+            return MessageStatus::Response;
+        };
+        core::mem::swap(&mut self.presets, &mut previous.presets);
+        core::mem::swap(&mut self.global, &mut previous.global);
+
+        let mut stored = 0;
+        let result = self.for_each_change(&mut previous, |config, _, preset, block| {
+            config.store_block(preset, block)?;
+            stored += 1;
+            Ok(())
+        });
+        let Err(err) = result else {
+            return MessageStatus::Response;
+        };
+
+        let mut rolled_back = 0;
+        let _ = self.for_each_change(&mut previous, |config, previous, preset, block| {
+            if rolled_back == stored {
+                return Ok(());
+            }
+            rolled_back += 1;
+            let (key, _) = StorageKey::from_block(preset, block);
+            let old = get_block(&previous.presets, &previous.global, preset, block)
+                .and_then(|value| key.to_block(value).ok());
+            let restored = old.is_some_and(|old| config.store_block(preset, old).is_ok());
+            if !restored {
+                set_block(&mut previous.presets, &mut previous.global, preset, block);
+            }
+            Ok(())
+        });
+        self.presets = previous.presets;
+        self.global = previous.global;
+        err.into()
+    }
+
+    /// Calls `f` for every value that differs from `previous`, with the preset
+    /// it belongs to, until `f` fails. The active preset comes last.
+    fn for_each_change<F>(
+        &mut self,
+        previous: &mut RestoreSession<P, B, A, E, L>,
+        mut f: F,
+    ) -> Result<(), StorageError>
+    where
+        F: FnMut(
+            &mut Self,
+            &mut RestoreSession<P, B, A, E, L>,
+            usize,
+            Block,
+        ) -> Result<(), StorageError>,
+    {
+        // the backup stream covers everything that can be set
+        let mut backup = ConfigBackupIterator::new();
+        let mut preset = 0;
+        while let Some(res) = backup.next(self) {
+            let OpenDeckResponse::Configuration(Wish::Set, _, block, _) = res else {
+                continue;
+            };
+            // the stream selects each preset before its values
+            if let Block::Global(GlobalSection::Presets(PresetIndex::Active, value)) = block {
+                preset = value as usize;
+                continue;
+            }
+            let (_, value) = StorageKey::from_block(preset, block);
+            if get_block(&previous.presets, &previous.global, preset, block) != Some(value) {
+                f(self, previous, preset, block)?;
+            }
+        }
+        let active = self.global.preset.current;
+        if active != previous.global.preset.current {
+            let block = Block::Global(GlobalSection::Presets(PresetIndex::Active, active as u16));
+            f(self, previous, active, block)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        button::ButtonSection,
+        config::FirmwareVersion,
+        encoder::EncoderSection,
+        storage::{log::LogStore, MemoryStorage},
+        Amount, OpenDeckRequest, SpecialRequest,
+    };
+
+    #[derive(Default)]
+    struct CountingHandler {
+        stored: usize,
+        fail: bool,
+    }
+    impl crate::SystemHandler for CountingHandler {
+        fn reboot(&self) {}
+        fn bootloader(&self) {}
+        fn factory_reset(&self) {}
+        fn store_value(&mut self, _key: StorageKey, _value: u16) -> Result<(), StorageError> {
+            if self.fail {
+                return Err(StorageError::WriteFailed);
+            }
+            self.stored += 1;
+            Ok(())
+        }
+    }
+
+    type TestConfig = Config<2, 2, 1, 1, 1, CountingHandler>;
+
+    fn config() -> TestConfig {
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        Config::new(version, 0, CountingHandler::default())
+    }
+
+    fn special(config: &mut TestConfig, special: SpecialRequest) -> Option<MessageStatus> {
+        config
+            .process_req_with_status(OpenDeckRequest::Special(special))
+            .map(|(_, status)| status)
+    }
+
+    fn set(config: &mut TestConfig, block: Block) -> MessageStatus {
+        config
+            .process_req_with_status(OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                block,
+            ))
+            .unwrap()
+            .1
+    }
+
+    fn midi_id(config: &TestConfig, preset: usize, button: usize) -> u16 {
+        config.presets[preset].buttons[button].get(ButtonSection::MidiId(0))
+    }
+
+    #[test]
+    fn test_restore_is_applied_on_restore_end() {
+        let mut config = config();
+        special(&mut config, SpecialRequest::RestoreStart);
+        assert_eq!(
+            set(&mut config, Block::Button(1, ButtonSection::MidiId(50))),
+            MessageStatus::Response
+        );
+        assert_eq!(midi_id(&config, 0, 1), 1);
+        assert_eq!(config.handler.stored, 0);
+
+        assert_eq!(
+            special(&mut config, SpecialRequest::RestoreEnd),
+            Some(MessageStatus::Response)
+        );
+        assert_eq!(midi_id(&config, 0, 1), 50);
+        assert!(config.handler.stored > 0);
+    }
+
+    #[test]
+    fn test_connection_close_discards_restore() {
+        let mut config = config();
+        special(&mut config, SpecialRequest::RestoreStart);
+        set(&mut config, Block::Button(1, ButtonSection::MidiId(50)));
+        special(&mut config, SpecialRequest::ConnectionClose);
+        special(&mut config, SpecialRequest::RestoreEnd);
+        assert_eq!(midi_id(&config, 0, 1), 1);
+        assert_eq!(config.handler.stored, 0);
+    }
+
+    #[test]
+    fn test_invalid_value_discards_restore() {
+        let mut config = config();
+        special(&mut config, SpecialRequest::RestoreStart);
+        set(&mut config, Block::Button(1, ButtonSection::MidiId(50)));
+        assert_eq!(
+            set(&mut config, Block::Button(7, ButtonSection::MidiId(1))),
+            MessageStatus::IndexError
+        );
+        special(&mut config, SpecialRequest::RestoreEnd);
+        assert_eq!(midi_id(&config, 0, 1), 1);
+
+        special(&mut config, SpecialRequest::RestoreStart);
+        assert_eq!(
+            set(
+                &mut config,
+                Block::Global(GlobalSection::Presets(PresetIndex::Active, 2))
+            ),
+            MessageStatus::NewValueError
+        );
+        special(&mut config, SpecialRequest::RestoreEnd);
+        assert_eq!(config.active_preset(), 0);
+    }
+
+    #[test]
+    fn test_malformed_message_discards_restore() {
+        let mut config = config();
+        special(&mut config, SpecialRequest::RestoreStart);
+        set(&mut config, Block::Button(1, ButtonSection::MidiId(50)));
+        config.process_sysex(&[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0xF7]);
+        special(&mut config, SpecialRequest::RestoreEnd);
+        assert_eq!(midi_id(&config, 0, 1), 1);
+    }
+
+    #[test]
+    fn test_foreign_sysex_keeps_restore() {
+        let mut config = config();
+        special(&mut config, SpecialRequest::RestoreStart);
+        set(&mut config, Block::Button(1, ButtonSection::MidiId(50)));
+        // an identity reply and SysEx of another manufacturer
+        config.process_sysex(&[0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0xF7]);
+        config.process_sysex(&[0xF0, 0x00, 0x20, 0x32, 0x01, 0xF7]);
+        special(&mut config, SpecialRequest::RestoreEnd);
+        assert_eq!(midi_id(&config, 0, 1), 50);
+    }
+
+    #[test]
+    fn test_restore_backup_stream() {
+        let mut source = config();
+        source.presets[0].buttons[0].set(ButtonSection::MidiId(10));
+        source.presets[1].buttons[1].set(ButtonSection::MidiId(20));
+        source.presets[1].encoders[0].set(EncoderSection::SecondMidiId(30));
+
+        let mut target = config();
+        special(&mut target, SpecialRequest::RestoreStart);
+        let mut backup = ConfigBackupIterator::new();
+        while let Some(res) = backup.next(&mut source) {
+            if let OpenDeckResponse::Configuration(Wish::Set, _, block, _) = res {
+                assert_eq!(set(&mut target, block), MessageStatus::Response);
+                // nothing is visible before the end of the restore
+                assert_eq!(midi_id(&target, 1, 1), 1);
+            }
+        }
+        special(&mut target, SpecialRequest::RestoreEnd);
+
+        assert_eq!(midi_id(&target, 0, 0), 10);
+        assert_eq!(midi_id(&target, 1, 1), 20);
+        assert_eq!(
+            target.presets[1].encoders[0].get(EncoderSection::SecondMidiId(0)),
+            30
+        );
+    }
+
+    #[test]
+    fn test_restore_reports_write_error() {
+        let mut config = config();
+        config.handler.fail = true;
+        special(&mut config, SpecialRequest::RestoreStart);
+        set(&mut config, Block::Button(1, ButtonSection::MidiId(50)));
+        assert_eq!(
+            special(&mut config, SpecialRequest::RestoreEnd),
+            Some(MessageStatus::WriteError)
+        );
+        assert_eq!(midi_id(&config, 0, 1), 1);
+    }
+
+    /// Keeps values in a [`LogStore`] with room for 15 keys.
+    struct LogHandler(LogStore<MemoryStorage<512>>);
+
+    impl LogHandler {
+        fn new() -> Self {
+            LogHandler(LogStore::mount(MemoryStorage::new(), 128, 15).unwrap())
+        }
+        fn keys(&mut self) -> usize {
+            let mut keys: Vec<StorageKey, 32> = Vec::new();
+            self.0
+                .replay(&mut |key, _| {
+                    if !keys.contains(&key) {
+                        keys.push(key).unwrap();
+                    }
+                })
+                .unwrap();
+            keys.len()
+        }
+    }
+
+    impl crate::SystemHandler for LogHandler {
+        fn reboot(&self) {}
+        fn bootloader(&self) {}
+        fn factory_reset(&self) {}
+        fn store_value(&mut self, key: StorageKey, value: u16) -> Result<(), StorageError> {
+            self.0.write(key, value)
+        }
+        fn load_values(
+            &mut self,
+            apply: &mut dyn FnMut(StorageKey, u16),
+        ) -> Result<(), StorageError> {
+            self.0.replay(apply)
+        }
+    }
+
+    type LogConfig = Config<2, 8, 1, 1, 1, LogHandler>;
+
+    fn log_config() -> LogConfig {
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 0,
+            revision: 0,
+        };
+        Config::new(version, 0, LogHandler::new())
+    }
+
+    /// Restores the backup of a configuration with `buttons` changed buttons in
+    /// each preset.
+    fn restore_log(target: &mut LogConfig, buttons: usize) -> Option<MessageStatus> {
+        let mut source = log_config();
+        for preset in 0..2 {
+            for button in 0..buttons {
+                let id = (10 * preset + button) as u8 + 20;
+                source.presets[preset].buttons[button].set(ButtonSection::MidiId(id));
+            }
+        }
+        let req = |block| OpenDeckRequest::Configuration(Wish::Set, Amount::Single, block);
+        target.process_req(OpenDeckRequest::Special(SpecialRequest::RestoreStart));
+        let mut backup = ConfigBackupIterator::new();
+        while let Some(res) = backup.next(&mut source) {
+            if let OpenDeckResponse::Configuration(Wish::Set, _, block, _) = res {
+                target.process_req(req(block));
+            }
+        }
+        target
+            .process_req_with_status(OpenDeckRequest::Special(SpecialRequest::RestoreEnd))
+            .map(|(_, status)| status)
+    }
+
+    fn assert_same_config(a: &mut LogConfig, b: &mut LogConfig) {
+        let (mut backup_a, mut backup_b) =
+            (ConfigBackupIterator::new(), ConfigBackupIterator::new());
+        loop {
+            let res = backup_a.next(a);
+            assert_eq!(res, backup_b.next(b));
+            if res.is_none() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_restore_stores_changed_values() {
+        let mut config = log_config();
+        assert_eq!(restore_log(&mut config, 3), Some(MessageStatus::Response));
+        assert_eq!(
+            config.presets[1].buttons[2].get(ButtonSection::MidiId(0)),
+            32
+        );
+        assert_eq!(config.handler.keys(), 6);
+        // restoring the same backup again stores nothing
+        assert_eq!(restore_log(&mut config, 3), Some(MessageStatus::Response));
+        assert_eq!(config.handler.keys(), 6);
+    }
+
+    #[test]
+    fn test_failed_restore_keeps_ram_and_storage_consistent() {
+        let mut config = log_config();
+        // 16 keys don't fit
+        assert_eq!(restore_log(&mut config, 8), Some(MessageStatus::WriteError));
+        assert_eq!(
+            config.presets[0].buttons[0].get(ButtonSection::MidiId(0)),
+            0
+        );
+
+        let handler = core::mem::replace(&mut config.handler, LogHandler::new());
+        let mut rebooted = Config::new(config.version, 0, handler);
+        rebooted.load_stored().unwrap();
+        assert_same_config(&mut config, &mut rebooted);
+    }
+}
@@ -0,0 +1,137 @@
+//! 14-bit values in the one-byte protocol.
+//!
+//! With one-byte values a 14-bit value doesn't fit into a single section. Its
+//! LSB section then carries the lower 7 bits and a separate MSB section the
+//! upper 7 bits. With two-byte values the LSB section carries the whole value
+//! and the MSB sections aren't used.
+
+use crate::{analog::AnalogSection, encoder::EncoderSection, Block, ValueSize};
+
+/// Returns the LSB section of the 14-bit value addressed by `block`, carrying `value`.
+fn lsb(block: Block, value: u16) -> Option<Block> {
+    match block {
+        Block::Encoder(i, EncoderSection::MidiIdLSB(_) | EncoderSection::MidiIdMSB(_)) => {
+            Some(Block::Encoder(i, EncoderSection::MidiIdLSB(value)))
+        }
+        Block::Analog(i, AnalogSection::MidiId(_) | AnalogSection::MidiIdMSB(_)) => {
+            Some(Block::Analog(i, AnalogSection::MidiId(value)))
+        }
+        Block::Analog(i, AnalogSection::LowerCCLimit(_) | AnalogSection::LowerCCLimitMSB(_)) => {
+            Some(Block::Analog(i, AnalogSection::LowerCCLimit(value)))
+        }
+        Block::Analog(i, AnalogSection::UpperCCLimit(_) | AnalogSection::UpperCCLimitMSB(_)) => {
+            Some(Block::Analog(i, AnalogSection::UpperCCLimit(value)))
+        }
+        _ => None,
+    }
+}
+
+fn is_msb(block: &Block) -> bool {
+    matches!(
+        block,
+        Block::Encoder(_, EncoderSection::MidiIdMSB(_))
+            | Block::Analog(
+                _,
+                AnalogSection::MidiIdMSB(_)
+                    | AnalogSection::LowerCCLimitMSB(_)
+                    | AnalogSection::UpperCCLimitMSB(_)
+            )
+    )
+}
+
+/// Whether `block` is the lower half of a 14-bit value.
+pub(crate) fn is_lsb(block: &Block) -> bool {
+    !is_msb(block) && lsb(*block, 0).is_some()
+}
+
+/// Turns a Set of either half of a 14-bit value into a Set of the whole value
+/// in its LSB section. `current` reads the value of that section.
+pub(crate) fn join(
+    block: Block,
+    value_size: ValueSize,
+    current: impl FnOnce(Block) -> Option<u16>,
+) -> Block {
+    let msb = is_msb(&block);
+    if !msb && value_size == ValueSize::TwoBytes {
+        return block;
+    }
+    let Some(current) = lsb(block, 0).and_then(current) else {
+        return block;
+    };
+    let value = block.into_parts().2.value & 0x7F;
+    let joined = if msb {
+        crate::with_msb(current, value as u8)
+    } else {
+        (current & !0x7F) | value
+    };
+    lsb(block, joined).unwrap_or(block)
+}
+
+/// Splits the Set of a whole 14-bit value into the Sets of its LSB and MSB
+/// sections.
+pub(crate) fn split(block: Block) -> Option<(Block, Block)> {
+    if !is_lsb(&block) {
+        return None;
+    }
+    let value = block.into_parts().2.value;
+    let msb = (value >> 7) as u8 & 0x7F;
+    let msb = match block {
+        Block::Encoder(i, _) => Block::Encoder(i, EncoderSection::MidiIdMSB(msb)),
+        Block::Analog(i, AnalogSection::MidiId(_)) => {
+            Block::Analog(i, AnalogSection::MidiIdMSB(msb))
+        }
+        Block::Analog(i, AnalogSection::LowerCCLimit(_)) => {
+            Block::Analog(i, AnalogSection::LowerCCLimitMSB(msb))
+        }
+        Block::Analog(i, _) => Block::Analog(i, AnalogSection::UpperCCLimitMSB(msb)),
+        _ => return None,
+    };
+    Some((lsb(block, value & 0x7F)?, msb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::button::ButtonSection;
+
+    #[test]
+    fn test_join_lsb_keeps_upper_bits_in_one_byte_mode() {
+        let block = Block::Analog(1, AnalogSection::MidiId(0x05));
+        assert_eq!(
+            join(block, ValueSize::OneByte, |_| Some(0x0280)),
+            Block::Analog(1, AnalogSection::MidiId(0x0285))
+        );
+        // with two bytes the LSB section carries the whole value
+        assert_eq!(join(block, ValueSize::TwoBytes, |_| Some(0x0280)), block);
+    }
+
+    #[test]
+    fn test_join_msb_keeps_lower_bits() {
+        let block = Block::Encoder(2, EncoderSection::MidiIdMSB(0x03));
+        assert_eq!(
+            join(block, ValueSize::TwoBytes, |lsb| {
+                assert_eq!(lsb, Block::Encoder(2, EncoderSection::MidiIdLSB(0)));
+                Some(0x0105)
+            }),
+            Block::Encoder(2, EncoderSection::MidiIdLSB(0x0185))
+        );
+        let other = Block::Button(0, ButtonSection::MidiId(0x10));
+        assert_eq!(join(other, ValueSize::OneByte, |_| Some(0)), other);
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            split(Block::Analog(0, AnalogSection::UpperCCLimit(0x3FFF))),
+            Some((
+                Block::Analog(0, AnalogSection::UpperCCLimit(0x7F)),
+                Block::Analog(0, AnalogSection::UpperCCLimitMSB(0x7F))
+            ))
+        );
+        assert_eq!(
+            split(Block::Analog(0, AnalogSection::LowerCCLimitMSB(1))),
+            None
+        );
+        assert_eq!(split(Block::Button(0, ButtonSection::MidiId(0x10))), None);
+    }
+}
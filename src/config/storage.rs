@@ -0,0 +1,787 @@
+//! Binary image of the configuration in persistent storage.
+//!
+//! All values are stored little endian:
+//!
+//! | offset | size | content                                             |
+//! |--------|------|-----------------------------------------------------|
+//! | 0      | 4    | magic `ODCF`                                        |
+//! | 4      | 2    | layout version                                      |
+//! | 6      | 10   | number of presets, buttons, encoders, analogs, LEDs |
+//! | 16     | 3    | firmware major, minor, revision                     |
+//! | 19     | 1    | reserved                                            |
+//! | 20     | 2    | CRC-16 of bytes 4..20 and the payload               |
+//! | 22     | ..   | presets, followed by global data                    |
+//!
+//! Each preset stores its buttons, encoders, analogs and LEDs in index order,
+//! every component as one `u16` per section in the order of its [`Layout`].
+//! The global MIDI, preset and LED settings follow in the same way, layout 2
+//! appends the tempo.
+//!
+//! Images of older layouts are still loaded: values are read with the tables
+//! of the stored layout and whatever it lacks keeps the value of `new()`. A
+//! change to the stored data therefore needs a new [`Layout`] in [`LAYOUTS`]
+//! instead of an edit of an existing one, and a fixture of the previous layout
+//! in `fixtures/`.
+//!
+//! The header is written after the payload, so an interrupted save leaves no
+//! valid image behind. An image that fails any check is not applied and the
+//! configuration falls back to its defaults.
+
+use crate::{
+    analog::{AnalogMessageType, AnalogSection},
+    bpm::Bpm,
+    button::{ButtonMessageType, ButtonSection, ButtonType},
+    config::{Config, FirmwareVersion, GlobalConfig, Preset},
+    encoder::{Accelleration, EncoderMessageType, EncoderSection},
+    global::{MidiIndex, PresetIndex},
+    led::{Color, ControlType, LedIndex, LedSection},
+    storage::{Storage, StorageError},
+    ChannelOrAll, Section,
+};
+
+const MAGIC: [u8; 4] = *b"ODCF";
+const HEADER_SIZE: usize = 22;
+const CRC_OFFSET: usize = 20;
+const VALUE_SIZE: usize = 2;
+
+/// Version of the layout images are written with.
+pub const LAYOUT_VERSION: u16 = CURRENT.version;
+
+const BUTTON_SECTIONS: [ButtonSection; 5] = [
+    ButtonSection::Type(ButtonType::Momentary),
+    ButtonSection::MessageType(ButtonMessageType::Notes),
+    ButtonSection::MidiId(0),
+    ButtonSection::Value(0),
+    ButtonSection::Channel(ChannelOrAll::None),
+];
+
+const ENCODER_SECTIONS: [EncoderSection; 12] = [
+    EncoderSection::Enabled(false),
+    EncoderSection::Inverted(false),
+    EncoderSection::MessageType(EncoderMessageType::ControlChange7Fh01h),
+    EncoderSection::MidiIdLSB(0),
+    EncoderSection::Channel(ChannelOrAll::None),
+    EncoderSection::PulsesPerStep(0),
+    EncoderSection::Accelleration(Accelleration::None),
+    EncoderSection::RemoteSync(false),
+    EncoderSection::LowerLimit(0),
+    EncoderSection::UpperLimit(0),
+    EncoderSection::RepeatedValue(0),
+    EncoderSection::SecondMidiId(0),
+];
+
+const ANALOG_SECTIONS: [AnalogSection; 9] = [
+    AnalogSection::Enabled(false),
+    AnalogSection::Inverted(false),
+    AnalogSection::MessageType(AnalogMessageType::PotentiometerWithCCMessage7Bit),
+    AnalogSection::MidiId(0),
+    AnalogSection::LowerCCLimit(0),
+    AnalogSection::UpperCCLimit(0),
+    AnalogSection::Channel(ChannelOrAll::None),
+    AnalogSection::LowerADCOffset(0),
+    AnalogSection::UpperADCOffset(0),
+];
+
+const LED_SECTIONS: [LedSection; 6] = [
+    LedSection::State(false),
+    LedSection::ColorTesting(Color::Off),
+    LedSection::ActivationId(0),
+    LedSection::ControlType(ControlType::MidiInNoteSingleValue),
+    LedSection::ActivationValue(0),
+    LedSection::Channel(ChannelOrAll::None),
+];
+
+const MIDI_INDICES: [MidiIndex; 16] = [
+    MidiIndex::StandardNoteOff,
+    MidiIndex::RunningStatus,
+    MidiIndex::DINtoUSBthru,
+    MidiIndex::DINMIDIstate,
+    MidiIndex::USBtoDINthru,
+    MidiIndex::USBtoUSBthru,
+    MidiIndex::USBtoBLEthru,
+    MidiIndex::DINtoDINthru,
+    MidiIndex::DINtoBLEthru,
+    MidiIndex::BLEMIDIstate,
+    MidiIndex::BLEtoDINthru,
+    MidiIndex::BLEtoUSBthru,
+    MidiIndex::BLEtoBLEthru,
+    MidiIndex::UseGlobalMIDIchannel,
+    MidiIndex::GlobalMIDIchannel,
+    MidiIndex::SendMIDIclock,
+];
+
+const PRESET_INDICES: [PresetIndex; 4] = [
+    PresetIndex::Active,
+    PresetIndex::Preservation,
+    PresetIndex::ForceValueRefresh,
+    PresetIndex::EnableMidiChange,
+];
+
+const LED_INDICES: [LedIndex; 3] = [
+    LedIndex::BlinkWithMIDIClock,
+    LedIndex::EnableStartupAnimation,
+    LedIndex::UseMidiProgramChangeOffset,
+];
+
+/// The sections stored by one layout version, in storage order.
+struct Layout {
+    version: u16,
+    buttons: &'static [ButtonSection],
+    encoders: &'static [EncoderSection],
+    analogs: &'static [AnalogSection],
+    leds: &'static [LedSection],
+    midi: &'static [MidiIndex],
+    presets: &'static [PresetIndex],
+    led_indices: &'static [LedIndex],
+    bpm: bool,
+}
+
+impl Layout {
+    /// Number of values in an image for the given component counts.
+    const fn values(&self, [p, b, e, a, l]: [usize; 5]) -> usize {
+        p * (b * self.buttons.len()
+            + e * self.encoders.len()
+            + a * self.analogs.len()
+            + l * self.leds.len())
+            + self.midi.len()
+            + self.presets.len()
+            + self.led_indices.len()
+            + self.bpm as usize
+    }
+}
+
+const LAYOUT_V1: Layout = Layout {
+    version: 1,
+    buttons: &BUTTON_SECTIONS,
+    encoders: &ENCODER_SECTIONS,
+    analogs: &ANALOG_SECTIONS,
+    leds: &LED_SECTIONS,
+    midi: &MIDI_INDICES,
+    presets: &PRESET_INDICES,
+    led_indices: &LED_INDICES,
+    bpm: false,
+};
+
+/// Adds the tempo.
+const LAYOUT_V2: Layout = Layout {
+    version: 2,
+    bpm: true,
+    ..LAYOUT_V1
+};
+
+const LAYOUTS: [&Layout; 2] = [&LAYOUT_V1, &LAYOUT_V2];
+const CURRENT: &Layout = LAYOUTS[LAYOUTS.len() - 1];
+
+/// CRC-16/CCITT-FALSE, continued from `crc`.
+fn crc16(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 > 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+const CRC_INIT: u16 = 0xFFFF;
+
+/// Header of an image written by this build, without the checksum.
+fn header(counts: [usize; 5], version: FirmwareVersion) -> [u8; CRC_OFFSET] {
+    let mut header = [0; CRC_OFFSET];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4..6].copy_from_slice(&CURRENT.version.to_le_bytes());
+    for (i, count) in counts.iter().enumerate() {
+        header[6 + 2 * i..8 + 2 * i].copy_from_slice(&(*count as u16).to_le_bytes());
+    }
+    header[16] = version.major;
+    header[17] = version.minor;
+    header[18] = version.revision;
+    header
+}
+
+/// Rebuilds a section of the same kind as `template` carrying `value`.
+fn decode<T>(template: T, value: u16) -> Result<T, StorageError>
+where
+    T: Into<Section> + TryFrom<Section>,
+{
+    let id = template.into().id;
+    T::try_from(Section { id, value }).map_err(|_| StorageError::InvalidValue)
+}
+
+/// Like `ChannelOrAll::from`, but rejects values the renderer never produces.
+fn decode_channel(value: u16) -> Result<ChannelOrAll, StorageError> {
+    match value {
+        0 => Ok(ChannelOrAll::None),
+        1..=16 => Ok(ChannelOrAll::Channel(value as u8 - 1)),
+        17 => Ok(ChannelOrAll::All),
+        _ => Err(StorageError::InvalidValue),
+    }
+}
+
+struct ImageWriter<'s, S: Storage> {
+    storage: &'s mut S,
+    offset: usize,
+    crc: u16,
+}
+
+impl<S: Storage> ImageWriter<'_, S> {
+    fn write(&mut self, value: u16) -> Result<(), StorageError> {
+        let bytes = value.to_le_bytes();
+        self.storage.write(self.offset, &bytes)?;
+        self.crc = crc16(self.crc, &bytes);
+        self.offset += VALUE_SIZE;
+        Ok(())
+    }
+}
+
+struct ImageReader<'s, S: Storage> {
+    storage: &'s mut S,
+    offset: usize,
+}
+
+impl<S: Storage> ImageReader<'_, S> {
+    fn read(&mut self) -> Result<u16, StorageError> {
+        let mut bytes = [0; VALUE_SIZE];
+        self.storage.read(self.offset, &mut bytes)?;
+        self.offset += VALUE_SIZE;
+        Ok(u16::from_le_bytes(bytes))
+    }
+}
+
+impl<
+        const P: usize,
+        const B: usize,
+        const A: usize,
+        const E: usize,
+        const L: usize,
+        H: crate::SystemHandler,
+    > Config<P, B, A, E, L, H>
+{
+    const COUNTS: [usize; 5] = [P, B, E, A, L];
+
+    /// Number of bytes needed to store the configuration.
+    pub const IMAGE_SIZE: usize = Self::image_size(CURRENT);
+
+    const fn image_size(layout: &Layout) -> usize {
+        HEADER_SIZE + VALUE_SIZE * layout.values(Self::COUNTS)
+    }
+
+    /// Writes the complete configuration to `storage`, starting at offset 0.
+    pub fn save_to<S: Storage>(&self, storage: &mut S) -> Result<(), StorageError> {
+        if storage.capacity() < Self::IMAGE_SIZE {
+            return Err(StorageError::OutOfBounds);
+        }
+        storage.erase(0, Self::IMAGE_SIZE)?;
+
+        let header = header(Self::COUNTS, self.version);
+        let mut w = ImageWriter {
+            storage,
+            offset: HEADER_SIZE,
+            crc: crc16(CRC_INIT, &header[MAGIC.len()..]),
+        };
+        for preset in self.presets.iter() {
+            for button in preset.buttons.iter() {
+                for section in CURRENT.buttons {
+                    w.write(button.get(*section))?;
+                }
+            }
+            for encoder in preset.encoders.iter() {
+                for section in CURRENT.encoders {
+                    w.write(encoder.get(*section))?;
+                }
+            }
+            for analog in preset.analogs.iter() {
+                for section in CURRENT.analogs {
+                    w.write(analog.get(*section))?;
+                }
+            }
+            for led in preset.leds.iter() {
+                for section in CURRENT.leds {
+                    w.write(led.get(*section))?;
+                }
+            }
+        }
+        for index in CURRENT.midi {
+            w.write(self.global.midi.get(*index))?;
+        }
+        for index in CURRENT.presets {
+            w.write(self.global.preset.get(*index))?;
+        }
+        for index in CURRENT.led_indices {
+            w.write(self.global.led.get(index))?;
+        }
+        if CURRENT.bpm {
+            w.write(self.bpm.get())?;
+        }
+
+        let crc = w.crc;
+        storage.write(CRC_OFFSET, &crc.to_le_bytes())?;
+        storage.write(0, &header)
+    }
+
+    /// Replaces the configuration with the image stored in `storage`.
+    ///
+    /// If the image is missing, incompatible or corrupt, the configuration is
+    /// reset to its defaults and the reason is returned.
+    pub fn load_from<S: Storage>(&mut self, storage: &mut S) -> Result<(), StorageError> {
+        let result = Self::check_image(storage, self.version).and_then(|layout| {
+            // fields the stored layout lacks keep their defaults
+            self.reset_to_defaults();
+            self.apply_image(storage, layout)
+        });
+        if let Err(_err) = result {
+            #[cfg(feature = "defmt")]
+            defmt::warn!("stored configuration not loaded: {}", _err);
+            self.reset_to_defaults();
+        }
+        result
+    }
+
+    /// Validates header and checksum without touching the configuration.
+    fn check_image<S: Storage>(
+        storage: &mut S,
+        version: FirmwareVersion,
+    ) -> Result<&'static Layout, StorageError> {
+        let mut header = [0; HEADER_SIZE];
+        storage.read(0, &mut header)?;
+        if header[..MAGIC.len()] != MAGIC {
+            return Err(StorageError::NoImage);
+        }
+        let layout = u16::from_le_bytes([header[4], header[5]]);
+        let Some(layout) = LAYOUTS.into_iter().find(|l| l.version == layout) else {
+            return Err(StorageError::UnsupportedLayout(layout));
+        };
+        let expected = self::header(Self::COUNTS, version);
+        if header[6..16] != expected[6..16] {
+            return Err(StorageError::ComponentMismatch);
+        }
+        // older firmware is fine, layout changes are covered by the layout version
+        let stored = FirmwareVersion {
+            major: header[16],
+            minor: header[17],
+            revision: header[18],
+        };
+        if stored > version {
+            return Err(StorageError::FirmwareMismatch);
+        }
+
+        let mut crc = crc16(CRC_INIT, &header[MAGIC.len()..CRC_OFFSET]);
+        let mut offset = HEADER_SIZE;
+        let mut chunk = [0; 32];
+        let size = Self::image_size(layout);
+        while offset < size {
+            let len = chunk.len().min(size - offset);
+            storage.read(offset, &mut chunk[..len])?;
+            crc = crc16(crc, &chunk[..len]);
+            offset += len;
+        }
+        if crc.to_le_bytes() != header[CRC_OFFSET..] {
+            return Err(StorageError::ChecksumMismatch);
+        }
+        Ok(layout)
+    }
+
+    /// Restores the configuration a fresh [`Config`] starts with.
+    fn reset_to_defaults(&mut self) {
+        for preset in self.presets.iter_mut() {
+            let adc_max = preset.analogs.first().map(|a| a.adc_max());
+            *preset = Preset::default();
+            if let Some(adc_max) = adc_max {
+                for analog in preset.analogs.iter_mut() {
+                    analog.set_adc_max(adc_max);
+                }
+            }
+        }
+        self.global = GlobalConfig::default();
+        self.bpm = Bpm::default();
+    }
+
+    fn apply_image<S: Storage>(
+        &mut self,
+        storage: &mut S,
+        layout: &Layout,
+    ) -> Result<(), StorageError> {
+        let mut r = ImageReader {
+            storage,
+            offset: HEADER_SIZE,
+        };
+        for preset in self.presets.iter_mut() {
+            for button in preset.buttons.iter_mut() {
+                for section in layout.buttons.iter().copied() {
+                    let value = r.read()?;
+                    button.set(match section {
+                        ButtonSection::Channel(_) => ButtonSection::Channel(decode_channel(value)?),
+                        _ => decode(section, value)?,
+                    });
+                }
+            }
+            for encoder in preset.encoders.iter_mut() {
+                for section in layout.encoders.iter().copied() {
+                    let value = r.read()?;
+                    encoder.set(match section {
+                        EncoderSection::Channel(_) => {
+                            EncoderSection::Channel(decode_channel(value)?)
+                        }
+                        _ => decode(section, value)?,
+                    });
+                }
+            }
+            for analog in preset.analogs.iter_mut() {
+                for section in layout.analogs.iter().copied() {
+                    let value = r.read()?;
+                    analog.set(match section {
+                        AnalogSection::Channel(_) => AnalogSection::Channel(decode_channel(value)?),
+                        _ => decode(section, value)?,
+                    });
+                }
+            }
+            for led in preset.leds.iter_mut() {
+                for section in layout.leds.iter().copied() {
+                    let value = r.read()?;
+                    led.set(match section {
+                        LedSection::Channel(_) => LedSection::Channel(decode_channel(value)?),
+                        _ => decode(section, value)?,
+                    });
+                }
+            }
+        }
+        for index in layout.midi.iter().copied() {
+            let value = r.read()?;
+            match index {
+                MidiIndex::GlobalMIDIchannel => {
+                    self.global.midi.set_global_channel(decode_channel(value)?)
+                }
+                _ => self.global.midi.set(index, value),
+            }
+        }
+        for index in layout.presets.iter().copied() {
+            let value = r.read()?;
+            if index == PresetIndex::Active && value as usize >= P {
+                return Err(StorageError::InvalidValue);
+            }
+            self.global.preset.set(index, value);
+        }
+        for index in layout.led_indices.iter().cloned() {
+            self.global.led.set(index, &r.read()?);
+        }
+        if layout.bpm {
+            self.bpm.set(r.read()?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{
+            tests::{test_config, NoopHandler, VERSION},
+            FirmwareVersion,
+        },
+        storage::{MemoryStorage, ERASED_BYTE},
+    };
+
+    type TestConfig = Config<2, 3, 2, 2, 4, NoopHandler>;
+
+    fn config() -> TestConfig {
+        test_config()
+    }
+
+    fn config_with_adc_max(adc_max: u16) -> TestConfig {
+        Config::new_with_adc_max(VERSION, 0, NoopHandler, adc_max)
+    }
+
+    fn customize(config: &mut TestConfig) {
+        let preset = &mut config.presets[1];
+        preset.buttons[2].set(ButtonSection::MessageType(ButtonMessageType::ControlChange));
+        preset.buttons[2].set(ButtonSection::Channel(ChannelOrAll::Channel(5)));
+        preset.encoders[1].set(EncoderSection::SecondMidiId(42));
+        preset.encoders[1].set(EncoderSection::Accelleration(Accelleration::Fast));
+        preset.analogs[0].set(AnalogSection::UpperCCLimit(16383));
+        preset.leds[3].set(LedSection::ControlType(ControlType::Static));
+        preset.leds[3].set(LedSection::ColorTesting(Color::Cyan));
+        config.global.midi.set(MidiIndex::DINtoUSBthru, 1);
+        config.global.midi.set(MidiIndex::GlobalMIDIchannel, 17);
+        config.global.preset.set(PresetIndex::Active, 1);
+        config.global.led.set(LedIndex::EnableStartupAnimation, &1);
+        config.bpm.set(140);
+    }
+
+    #[test]
+    fn test_image_size() {
+        // header + 2 presets * (3*5 + 2*12 + 2*9 + 4*6) values + 23 global values + bpm
+        assert_eq!(TestConfig::IMAGE_SIZE, 22 + 2 * (2 * 81 + 23 + 1));
+    }
+
+    #[test]
+    fn test_save_writes_header() {
+        let mut storage = MemoryStorage::<512>::new();
+        config().save_to(&mut storage).unwrap();
+        assert_eq!(
+            &storage.as_slice()[..20],
+            b"ODCF\x02\x00\x02\x00\x03\x00\x02\x00\x02\x00\x04\x00\x01\x00\x00\x00"
+        );
+        assert_eq!(storage.as_slice()[TestConfig::IMAGE_SIZE], ERASED_BYTE);
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let mut original = config();
+        customize(&mut original);
+        let mut storage = MemoryStorage::<512>::new();
+        original.save_to(&mut storage).unwrap();
+
+        let mut loaded = config();
+        loaded.load_from(&mut storage).unwrap();
+
+        let preset = &loaded.presets[1];
+        assert_eq!(
+            preset.buttons[2].get(ButtonSection::MessageType(ButtonMessageType::Notes)),
+            ButtonMessageType::ControlChange as u16
+        );
+        assert_eq!(preset.encoders[1].get(EncoderSection::SecondMidiId(0)), 42);
+        assert_eq!(preset.analogs[0].get(AnalogSection::UpperCCLimit(0)), 16383);
+        assert_eq!(preset.leds[3].get_control_type(), ControlType::Static);
+        assert_eq!(preset.leds[3].get_color(), Color::Cyan);
+        assert!(loaded.global_midi().din_to_usb_thru());
+        assert_eq!(loaded.global_midi().global_channel(), ChannelOrAll::All);
+        assert_eq!(
+            preset.buttons[2].get(ButtonSection::Channel(ChannelOrAll::None)),
+            6
+        );
+        assert_eq!(loaded.active_preset(), 1);
+        assert!(loaded.global_led().startup_animation());
+        assert_eq!(loaded.bpm().get(), 140);
+
+        let mut copy = MemoryStorage::<512>::new();
+        loaded.save_to(&mut copy).unwrap();
+        assert_eq!(storage.as_slice(), copy.as_slice());
+    }
+
+    #[test]
+    fn test_load_from_blank_storage() {
+        let mut storage = MemoryStorage::<512>::new();
+        assert_eq!(config().load_from(&mut storage), Err(StorageError::NoImage));
+    }
+
+    #[test]
+    fn test_load_rejects_unknown_layout() {
+        let mut storage = MemoryStorage::<512>::new();
+        config().save_to(&mut storage).unwrap();
+        storage.as_mut_slice()[4] = 0x09;
+        assert_eq!(
+            config().load_from(&mut storage),
+            Err(StorageError::UnsupportedLayout(9))
+        );
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_value() {
+        let mut storage = MemoryStorage::<512>::new();
+        config().save_to(&mut storage).unwrap();
+        // message type of the first button
+        storage.as_mut_slice()[HEADER_SIZE + 2] = 0x7F;
+        update_crc(&mut storage);
+        assert_eq!(
+            config().load_from(&mut storage),
+            Err(StorageError::InvalidValue)
+        );
+    }
+
+    fn layout(image: &[u8]) -> &'static Layout {
+        let version = u16::from_le_bytes([image[4], image[5]]);
+        LAYOUTS.into_iter().find(|l| l.version == version).unwrap()
+    }
+
+    fn update_crc(storage: &mut MemoryStorage<512>) {
+        let data = storage.as_mut_slice();
+        let crc = crc16(CRC_INIT, &data[4..CRC_OFFSET]);
+        let size = TestConfig::image_size(layout(data));
+        let crc = crc16(crc, &data[HEADER_SIZE..size]);
+        data[CRC_OFFSET..HEADER_SIZE].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    fn assert_defaults(config: &TestConfig) {
+        let preset = &config.presets[1];
+        assert_eq!(
+            preset.buttons[2].get(ButtonSection::MessageType(ButtonMessageType::Notes)),
+            ButtonMessageType::Notes as u16
+        );
+        assert_eq!(preset.encoders[1].get(EncoderSection::SecondMidiId(0)), 0);
+        assert_eq!(config.active_preset(), 0);
+        assert!(!config.global_midi().din_to_usb_thru());
+    }
+
+    #[test]
+    fn test_corrupt_image_falls_back_to_defaults() {
+        let mut original = config();
+        customize(&mut original);
+        let mut storage = MemoryStorage::<512>::new();
+        original.save_to(&mut storage).unwrap();
+        storage.as_mut_slice()[TestConfig::IMAGE_SIZE - 1] ^= 0x01;
+
+        let mut loaded = config_with_adc_max(1023);
+        customize(&mut loaded);
+        assert_eq!(
+            loaded.load_from(&mut storage),
+            Err(StorageError::ChecksumMismatch)
+        );
+        assert_defaults(&loaded);
+        assert_eq!(loaded.presets[0].analogs[1].adc_max(), 1023);
+    }
+
+    #[test]
+    fn test_invalid_value_falls_back_to_defaults() {
+        let mut original = config();
+        customize(&mut original);
+        let mut storage = MemoryStorage::<512>::new();
+        original.save_to(&mut storage).unwrap();
+        // active preset, read after all presets were applied
+        let offset = HEADER_SIZE + 2 * (2 * 81 + 16);
+        storage.as_mut_slice()[offset] = 5;
+        update_crc(&mut storage);
+
+        let mut loaded = config();
+        assert_eq!(
+            loaded.load_from(&mut storage),
+            Err(StorageError::InvalidValue)
+        );
+        assert_defaults(&loaded);
+    }
+
+    #[test]
+    fn test_load_rejects_other_component_counts() {
+        let mut storage = MemoryStorage::<512>::new();
+        config().save_to(&mut storage).unwrap();
+
+        let mut other: Config<2, 3, 2, 2, 3, _> = test_config();
+        assert_eq!(
+            other.load_from(&mut storage),
+            Err(StorageError::ComponentMismatch)
+        );
+    }
+
+    #[test]
+    fn test_load_rejects_newer_firmware() {
+        let mut storage = MemoryStorage::<512>::new();
+        let newer = FirmwareVersion {
+            major: 1,
+            minor: 2,
+            revision: 0,
+        };
+        TestConfig::new(newer, 0, NoopHandler)
+            .save_to(&mut storage)
+            .unwrap();
+        assert_eq!(
+            config().load_from(&mut storage),
+            Err(StorageError::FirmwareMismatch)
+        );
+
+        // going forward is fine
+        config().save_to(&mut storage).unwrap();
+        let mut loaded = TestConfig::new(newer, 0, NoopHandler);
+        assert_eq!(loaded.load_from(&mut storage), Ok(()));
+    }
+
+    #[test]
+    fn test_interrupted_save_leaves_no_image() {
+        let mut original = config();
+        customize(&mut original);
+        let mut storage = MemoryStorage::<512>::new();
+        original.save_to(&mut storage).unwrap();
+        // power lost before the header was written
+        storage.as_mut_slice()[..HEADER_SIZE].fill(ERASED_BYTE);
+        assert_eq!(config().load_from(&mut storage), Err(StorageError::NoImage));
+    }
+
+    #[test]
+    fn test_save_rejects_too_small_storage() {
+        let mut storage = MemoryStorage::<64>::new();
+        assert_eq!(
+            config().save_to(&mut storage),
+            Err(StorageError::OutOfBounds)
+        );
+    }
+
+    type V1Config = Config<1, 2, 1, 1, 2, NoopHandler>;
+
+    /// Image written by layout 1 for a `V1Config` with some customized values.
+    const LAYOUT_V1_IMAGE: &[u8] = include_bytes!("fixtures/layout_v1.bin");
+
+    fn v1_storage() -> MemoryStorage<256> {
+        let mut storage = MemoryStorage::<256>::new();
+        storage.write(0, LAYOUT_V1_IMAGE).unwrap();
+        storage
+    }
+
+    fn v1_config() -> V1Config {
+        let version = FirmwareVersion {
+            major: 1,
+            minor: 1,
+            revision: 0,
+        };
+        Config::new(version, 0, NoopHandler)
+    }
+
+    #[test]
+    fn test_layout_sizes() {
+        assert_eq!(LAYOUT_V1_IMAGE.len(), V1Config::image_size(&LAYOUT_V1));
+        assert_eq!(V1Config::IMAGE_SIZE, LAYOUT_V1_IMAGE.len() + VALUE_SIZE);
+        assert!(LAYOUTS.windows(2).all(|w| w[0].version < w[1].version));
+    }
+
+    #[test]
+    fn test_load_layout_v1_fixture() {
+        let mut storage = v1_storage();
+        let mut config = v1_config();
+        config.bpm.set(90);
+        config.load_from(&mut storage).unwrap();
+
+        let preset = &config.presets[0];
+        assert_eq!(
+            preset.buttons[1].get(ButtonSection::MessageType(ButtonMessageType::Notes)),
+            ButtonMessageType::ControlChange as u16
+        );
+        assert_eq!(preset.buttons[1].get(ButtonSection::MidiId(0)), 20);
+        assert_eq!(preset.encoders[0].get(EncoderSection::SecondMidiId(0)), 42);
+        assert_eq!(preset.analogs[0].get(AnalogSection::UpperCCLimit(0)), 16383);
+        assert_eq!(preset.leds[1].get_control_type(), ControlType::Static);
+        assert!(config.global_midi().din_to_usb_thru());
+        assert!(config.global_led().startup_animation());
+        // not part of layout 1
+        assert_eq!(config.bpm(), &Bpm::default());
+    }
+
+    #[test]
+    fn test_layout_v1_fixture_is_upgraded_on_save() {
+        let mut storage = v1_storage();
+        let mut config = v1_config();
+        config.load_from(&mut storage).unwrap();
+        config.bpm.set(100);
+        config.save_to(&mut storage).unwrap();
+        assert_eq!(&storage.as_slice()[4..6], &LAYOUT_VERSION.to_le_bytes());
+
+        let mut loaded = v1_config();
+        loaded.load_from(&mut storage).unwrap();
+        assert_eq!(loaded.bpm().get(), 100);
+        assert_eq!(
+            loaded.presets[0].buttons[1].get(ButtonSection::MidiId(0)),
+            20
+        );
+    }
+
+    #[test]
+    fn test_corrupt_layout_v1_fixture_is_rejected() {
+        let mut storage = v1_storage();
+        storage.as_mut_slice()[HEADER_SIZE] ^= 0x01;
+        assert_eq!(
+            v1_config().load_from(&mut storage),
+            Err(StorageError::ChecksumMismatch)
+        );
+    }
+}
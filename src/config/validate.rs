@@ -0,0 +1,171 @@
+//! Checks of configuration requests against the board.
+//!
+//! The parser only knows the protocol, whether an index or a value exists
+//! depends on the number of components and presets of the configuration.
+
+use crate::{
+    config::Config,
+    global::{GlobalSection, PresetIndex},
+    led::{LedIndex, LedSection},
+    Amount, Block, MessageStatus, Wish,
+};
+
+impl<
+        const P: usize,
+        const B: usize,
+        const A: usize,
+        const E: usize,
+        const L: usize,
+        H: crate::SystemHandler,
+    > Config<P, B, A, E, L, H>
+{
+    /// Returns the error status to answer a request with, if the board has no
+    /// such value.
+    pub(crate) fn validate(
+        &self,
+        wish: Wish,
+        amount: Amount,
+        block: Block,
+    ) -> Result<(), MessageStatus> {
+        Self::validate_block(wish, amount, block)
+    }
+
+    /// Same as [`Config::validate`], for use while parts of the configuration
+    /// are borrowed.
+    pub(crate) fn validate_block(
+        wish: Wish,
+        amount: Amount,
+        block: Block,
+    ) -> Result<(), MessageStatus> {
+        // requests for all values don't address a single index
+        let single = amount == Amount::Single;
+        let index_valid = match block {
+            Block::Button(index, _) => !single || (index as usize) < B,
+            Block::Encoder(index, _) => !single || (index as usize) < E,
+            Block::Analog(index, _) => !single || (index as usize) < A,
+            Block::Led(index, LedSection::Global(_)) => {
+                !single || LedIndex::try_from(index).is_ok()
+            }
+            Block::Led(index, _) => !single || (index as usize) < L,
+            Block::Global(GlobalSection::Midi(..) | GlobalSection::Presets(..)) => true,
+            Block::Global(_) | Block::Display | Block::Touchscreen => {
+                return Err(MessageStatus::NotSupportedError)
+            }
+        };
+        if !index_valid {
+            return Err(MessageStatus::IndexError);
+        }
+        match (wish, block) {
+            (Wish::Set, Block::Global(GlobalSection::Presets(PresetIndex::Active, value)))
+                if value as usize >= P =>
+            {
+                Err(MessageStatus::NewValueError)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        button::ButtonSection,
+        config::{
+            tests::{test_config, NoopHandler},
+            Config,
+        },
+        global::{GlobalSection, MidiIndex, PresetIndex},
+        led::LedSection,
+        Amount, Block, MessageStatus, Wish,
+    };
+
+    fn config() -> Config<2, 3, 1, 1, 2, NoopHandler> {
+        test_config()
+    }
+
+    #[test]
+    fn test_index_out_of_range() {
+        let config = config();
+        let button = |index| Block::Button(index, ButtonSection::MidiId(0));
+        assert_eq!(
+            config.validate(Wish::Get, Amount::Single, button(2)),
+            Ok(())
+        );
+        assert_eq!(
+            config.validate(Wish::Get, Amount::Single, button(3)),
+            Err(MessageStatus::IndexError)
+        );
+        assert_eq!(
+            config.validate(Wish::Get, Amount::All(0), button(3)),
+            Ok(())
+        );
+        assert_eq!(
+            config.validate(
+                Wish::Set,
+                Amount::Single,
+                Block::Led(2, LedSection::State(true))
+            ),
+            Err(MessageStatus::IndexError)
+        );
+        // global LED settings are addressed by their LedIndex
+        assert_eq!(
+            config.validate(
+                Wish::Get,
+                Amount::Single,
+                Block::Led(3, LedSection::Global(0))
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            config.validate(
+                Wish::Get,
+                Amount::Single,
+                Block::Led(1, LedSection::Global(0))
+            ),
+            Err(MessageStatus::IndexError)
+        );
+    }
+
+    #[test]
+    fn test_unsupported_blocks() {
+        let config = config();
+        for block in [
+            Block::Display,
+            Block::Touchscreen,
+            Block::Global(GlobalSection::OSC(0, 0)),
+            Block::Global(GlobalSection::MDNS(0, 0)),
+            Block::Global(GlobalSection::ConfigurationUnlock(0, 0)),
+        ] {
+            assert_eq!(
+                config.validate(Wish::Get, Amount::Single, block),
+                Err(MessageStatus::NotSupportedError)
+            );
+        }
+        assert_eq!(
+            config.validate(
+                Wish::Set,
+                Amount::Single,
+                Block::Global(GlobalSection::Midi(MidiIndex::RunningStatus, 1))
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_active_preset_out_of_range() {
+        let config = config();
+        let active = |value| Block::Global(GlobalSection::Presets(PresetIndex::Active, value));
+        assert_eq!(
+            config.validate(Wish::Set, Amount::Single, active(1)),
+            Ok(())
+        );
+        assert_eq!(
+            config.validate(Wish::Set, Amount::Single, active(2)),
+            Err(MessageStatus::NewValueError)
+        );
+        assert_eq!(
+            config.validate(Wish::Get, Amount::Single, active(0)),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Byte streams of the DIN MIDI interface.
+//!
+//! On the 31250 baud DIN link a channel message can omit its status byte when
+//! it is the same as the one of the previous channel message, the running
+//! status. A stream of CCs from an expression pedal then takes two bytes per
+//! message instead of three.
+//!
+//! [`DinWriter`] applies the running status to the messages sent on DIN when
+//! [`crate::global::GlobalMidi::running_status`] is enabled. [`DinReader`]
+//! restores the complete messages of a received stream, for the thru routing
+//! of [`crate::routing::Router`] and for [`crate::midi_in::MidiEvent::parse`].
+//!
+//! SysEx and system common messages cancel the running status, the next
+//! channel message is sent with its status. Realtime messages don't affect it.
+
+use crate::{midi_in::data_length, routing::MidiSink, MAX_MESSAGE_SIZE, SYSEX_END, SYSEX_START};
+
+/// Tune Request, the only system common message without data bytes.
+const TUNE_REQUEST: u8 = 0xF6;
+
+/// Writes complete messages to the DIN output, dropping repeated status bytes
+/// when the running status is enabled.
+///
+/// The sink receives the bytes to transmit, which may start with a data byte.
+/// Use a writer as the DIN sink of a [`crate::routing::Router`].
+pub struct DinWriter<S: MidiSink> {
+    pub sink: S,
+    running_status: bool,
+    /// Status of the last channel message sent.
+    status: Option<u8>,
+}
+
+impl<S: MidiSink> DinWriter<S> {
+    pub fn new(sink: S, running_status: bool) -> Self {
+        DinWriter {
+            sink,
+            running_status,
+            status: None,
+        }
+    }
+
+    /// Follows a change of the running status setting, the next message is sent
+    /// with its status.
+    pub fn set_running_status(&mut self, enabled: bool) {
+        self.running_status = enabled;
+        self.status = None;
+    }
+
+    /// Sends the status of the next channel message, e.g. after the receiver
+    /// was reconnected.
+    pub fn reset(&mut self) {
+        self.status = None;
+    }
+}
+
+impl<S: MidiSink> MidiSink for DinWriter<S> {
+    fn send(&mut self, message: &[u8]) {
+        let Some(&status) = message.first() else {
+            return;
+        };
+        match status {
+            0xF8..=0xFF => {}
+            0x80..=0xEF if self.running_status => {
+                if self.status.replace(status) == Some(status) {
+                    self.sink.send(&message[1..]);
+                    return;
+                }
+            }
+            _ => self.status = None,
+        }
+        self.sink.send(message);
+    }
+}
+
+/// Restores complete messages from a received DIN stream, SysEx of up to `N`
+/// bytes is collected.
+///
+/// Realtime messages are passed on right away, also in the middle of another
+/// message. Incomplete messages and SysEx that doesn't fit are dropped.
+pub struct DinReader<const N: usize = MAX_MESSAGE_SIZE> {
+    /// Running status, kept for channel messages.
+    status: Option<u8>,
+    message: [u8; N],
+    len: usize,
+    in_sysex: bool,
+    realtime: u8,
+}
+
+impl<const N: usize> Default for DinReader<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> DinReader<N> {
+    pub fn new() -> Self {
+        DinReader {
+            status: None,
+            message: [0; N],
+            len: 0,
+            in_sysex: false,
+            realtime: 0,
+        }
+    }
+
+    /// Drops the running status and a partially received message.
+    pub fn reset(&mut self) {
+        self.status = None;
+        self.len = 0;
+        self.in_sysex = false;
+    }
+
+    /// Feeds a received byte, returns the message it completes, with its status.
+    pub fn push(&mut self, byte: u8) -> Option<&[u8]> {
+        match byte {
+            0xF8..=0xFF => {
+                self.realtime = byte;
+                Some(core::slice::from_ref(&self.realtime))
+            }
+            SYSEX_END => {
+                self.status = None;
+                let complete = self.in_sysex && self.append(byte);
+                self.in_sysex = false;
+                self.complete(complete)
+            }
+            0x80..=0xF6 => {
+                self.len = 0;
+                self.in_sysex = byte == SYSEX_START;
+                self.status = (byte < 0xF0).then_some(byte);
+                match byte {
+                    // undefined
+                    0xF4 | 0xF5 => None,
+                    TUNE_REQUEST => {
+                        self.append(byte);
+                        self.complete(true)
+                    }
+                    _ => {
+                        self.append(byte);
+                        None
+                    }
+                }
+            }
+            _ if self.in_sysex => {
+                if !self.append(byte) {
+                    self.reset();
+                }
+                None
+            }
+            _ => {
+                if self.len == 0 {
+                    let status = self.status?;
+                    self.append(status);
+                }
+                self.append(byte);
+                if self.len <= data_length(self.message[0]) {
+                    return None;
+                }
+                self.complete(true)
+            }
+        }
+    }
+
+    fn append(&mut self, byte: u8) -> bool {
+        let Some(slot) = self.message.get_mut(self.len) else {
+            return false;
+        };
+        *slot = byte;
+        self.len += 1;
+        true
+    }
+
+    /// Starts the next message, returns the current one when it is complete.
+    fn complete(&mut self, complete: bool) -> Option<&[u8]> {
+        let len = core::mem::take(&mut self.len);
+        complete.then_some(&self.message[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::Vec;
+
+    #[derive(Default)]
+    struct Wire(Vec<u8, 32>);
+
+    impl MidiSink for &mut Wire {
+        fn send(&mut self, bytes: &[u8]) {
+            self.0.extend_from_slice(bytes).unwrap();
+        }
+    }
+
+    fn write(running_status: bool, messages: &[&[u8]]) -> Vec<u8, 32> {
+        let mut wire = Wire::default();
+        let mut writer = DinWriter::new(&mut wire, running_status);
+        for message in messages {
+            writer.send(message);
+        }
+        wire.0
+    }
+
+    fn read<const N: usize>(reader: &mut DinReader<N>, bytes: &[u8]) -> Vec<Vec<u8, 16>, 8> {
+        let mut messages = Vec::new();
+        for &byte in bytes {
+            if let Some(message) = reader.push(byte) {
+                messages.push(Vec::from_slice(message).unwrap()).unwrap();
+            }
+        }
+        messages
+    }
+
+    const CC: [&[u8]; 3] = [
+        &[0xB0, 0x07, 0x10],
+        &[0xB0, 0x07, 0x11],
+        &[0xB0, 0x07, 0x12],
+    ];
+
+    #[test]
+    fn test_writer_running_status() {
+        assert_eq!(write(true, &CC), [0xB0, 0x07, 0x10, 0x07, 0x11, 0x07, 0x12]);
+        // disabled
+        assert_eq!(
+            write(false, &CC),
+            [0xB0, 0x07, 0x10, 0xB0, 0x07, 0x11, 0xB0, 0x07, 0x12]
+        );
+        // another channel
+        assert_eq!(
+            write(true, &[CC[0], &[0xB1, 0x07, 0x10], CC[1]]),
+            [0xB0, 0x07, 0x10, 0xB1, 0x07, 0x10, 0xB0, 0x07, 0x11]
+        );
+    }
+
+    #[test]
+    fn test_writer_system_messages() {
+        // realtime keeps the running status
+        assert_eq!(
+            write(true, &[CC[0], &[0xF8], CC[1]]),
+            [0xB0, 0x07, 0x10, 0xF8, 0x07, 0x11]
+        );
+        // SysEx and system common cancel it
+        assert_eq!(
+            write(
+                true,
+                &[CC[0], &[0xF0, 0x7E, 0xF7], CC[1], &[0xF3, 0x01], CC[2]]
+            ),
+            [0xB0, 0x07, 0x10, 0xF0, 0x7E, 0xF7, 0xB0, 0x07, 0x11, 0xF3, 0x01, 0xB0, 0x07, 0x12]
+        );
+        let mut wire = Wire::default();
+        let mut writer = DinWriter::new(&mut wire, true);
+        writer.send(CC[0]);
+        writer.set_running_status(true);
+        writer.send(CC[1]);
+        writer.send(CC[2]);
+        assert_eq!(wire.0, [0xB0, 0x07, 0x10, 0xB0, 0x07, 0x11, 0x07, 0x12]);
+    }
+
+    #[test]
+    fn test_reader_running_status() {
+        let mut reader = DinReader::<16>::new();
+        let messages = read(
+            &mut reader,
+            &[0xB0, 0x07, 0x10, 0x07, 0xF8, 0x11, 0xC2, 0x05, 0x06],
+        );
+        assert_eq!(
+            messages,
+            [
+                &[0xB0, 0x07, 0x10][..],
+                &[0xF8],
+                &[0xB0, 0x07, 0x11],
+                &[0xC2, 0x05],
+                &[0xC2, 0x06],
+            ]
+        );
+        // a system common message cancels the running status
+        let messages = read(&mut reader, &[0xF2, 0x01, 0x02, 0x07, 0x12, 0xF6]);
+        assert_eq!(messages, [&[0xF2, 0x01, 0x02][..], &[0xF6]]);
+        // so does SysEx
+        let messages = read(
+            &mut reader,
+            &[0x90, 0x40, 0x7F, 0xF0, 0x7E, 0xF8, 0xF7, 0x40, 0x00],
+        );
+        assert_eq!(
+            messages,
+            [&[0x90, 0x40, 0x7F][..], &[0xF8], &[0xF0, 0x7E, 0xF7]]
+        );
+    }
+
+    #[test]
+    fn test_reader_drops_long_sysex() {
+        let mut reader = DinReader::<4>::new();
+        let messages = read(&mut reader, &[0xF0, 0x01, 0x02, 0x03, 0x04, 0xF7, 0x05]);
+        assert!(messages.is_empty());
+        let messages = read(&mut reader, &[0xF0, 0x01, 0x02, 0xF7]);
+        assert_eq!(messages, [&[0xF0, 0x01, 0x02, 0xF7][..]]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let messages: [&[u8]; 6] = [CC[0], CC[1], &[0xF8], CC[2], &[0xF0, 0x7E, 0xF7], CC[0]];
+        let mut reader: DinReader = DinReader::new();
+        assert_eq!(read(&mut reader, &write(true, &messages)), messages);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::encoder::{Encoder, EncoderMessageType};
 use crate::handler::{ChannelMessages, HiRes};
+use crate::ump::{self, UmpMode};
 use crate::ChannelOrAll;
 
 use channel_voice1::ProgramChange;
@@ -7,7 +8,7 @@ use midi2::{
     channel_voice1::{ControlChange, NoteOn, PitchBend},
     error::BufferOverflow,
     prelude::*,
-    BytesMessage,
+    BytesMessage, UmpMessage,
 };
 
 pub enum EncoderPulse {
@@ -50,6 +51,7 @@ impl<'a> EncoderMessages<'a> {
             EncoderMessageType::NRPN14 => 4,
             EncoderMessageType::PresetChange => 0,
             EncoderMessageType::BPM => 0,
+            EncoderMessageType::TapTempo => 0,
         };
         let ch = channel_override.unwrap_or(encoder.channel);
         let channel_messages = ChannelMessages::new_with_multiple_messages(ch, nr_of_messages);
@@ -186,8 +188,54 @@ impl<'a> EncoderMessages<'a> {
             }
             EncoderMessageType::PresetChange => Ok(None),
             EncoderMessageType::BPM => Ok(None),
+            EncoderMessageType::TapTempo => Ok(None),
         }
     }
+    /// Same as [`EncoderMessages::next`], rendered as UMP for `group`.
+    ///
+    /// With MIDI 2.0 a 14-bit control change is a single control change and NRPN
+    /// an assignable controller, both with a 32-bit value.
+    pub fn next_ump<'buf>(
+        &mut self,
+        mode: UmpMode,
+        group: u4,
+        buffer: &'buf mut [u32],
+    ) -> Result<Option<UmpMessage<&'buf [u32]>>, BufferOverflow> {
+        let (status, index, bits) = match (mode, self.encoder.message_type) {
+            (UmpMode::Midi2, EncoderMessageType::ControlChange14bit) => {
+                (ump::CONTROL_CHANGE, (self.encoder.midi_id as u8, 0), 14)
+            }
+            (UmpMode::Midi2, EncoderMessageType::NRPN7 | EncoderMessageType::NRPN14) => {
+                let id = self.encoder.midi_id;
+                let bits = match self.encoder.message_type {
+                    EncoderMessageType::NRPN7 => 7,
+                    _ => 14,
+                };
+                (
+                    ump::ASSIGNABLE_CONTROLLER,
+                    (HiRes::new(id).msb().into(), HiRes::new(id).lsb().into()),
+                    bits,
+                )
+            }
+            _ => {
+                let bytes = &mut [0; ump::MAX_BYTES];
+                return match self.next(bytes)? {
+                    Some(m) => ump::from_bytes(mode, group, m.data(), buffer).map(Some),
+                    None => Ok(None),
+                };
+            }
+        };
+        if !self.encoder.enabled {
+            return Ok(None);
+        }
+        let Some((channel, incr)) = self.channel_messages.next_first() else {
+            return Ok(None);
+        };
+        self.encoder.increment(&self.pulse, incr);
+        // like the data bytes of MIDI 1.0, only the low `bits` of the value are sent
+        let data = ump::scale_up(self.encoder.value as u32 & ((1 << bits) - 1), bits, 32);
+        ump::midi2(group, status, channel, index, data, buffer).map(Some)
+    }
 }
 
 impl Encoder {
@@ -560,6 +608,40 @@ mod tests {
         assert_eq!(Ok(None), it.next(&mut buf));
     }
     #[test]
+    fn test_cc_14bit_ump() {
+        let buffer = &mut [0u32; 4];
+        let mut encoder = Encoder {
+            enabled: true,
+            message_type: EncoderMessageType::ControlChange14bit,
+            value: 999,
+            upper_limit: 0x3FFF,
+            pulses_per_step: 1,
+            midi_id: 0x03,
+            channel: ChannelOrAll::Channel(1),
+            ..Encoder::default()
+        };
+        let mut it = encoder.handle(EncoderPulse::Clockwise);
+        let m = it.next_ump(UmpMode::Midi2, u4::new(0), buffer).unwrap();
+        assert_eq!(m.unwrap().data(), [0x40B1_0300, 1000 << 18]);
+        assert_eq!(Ok(None), it.next_ump(UmpMode::Midi2, u4::new(0), buffer));
+
+        encoder.message_type = EncoderMessageType::ProgramChange;
+        encoder.value = 5;
+        encoder.upper_limit = 127;
+        let mut it = encoder.handle(EncoderPulse::CounterClockwise);
+        let m = it.next_ump(UmpMode::Midi2, u4::new(0), buffer).unwrap();
+        assert_eq!(m.unwrap().data(), [0x40C1_0000, 4 << 24]);
+
+        // a 7-bit NRPN sends the low 7 bits of a value above 127, as with MIDI 1.0
+        encoder.message_type = EncoderMessageType::NRPN7;
+        encoder.value = 159;
+        encoder.upper_limit = 0x3FFF;
+        encoder.midi_id = 1624;
+        let mut it = encoder.handle(EncoderPulse::Clockwise);
+        let m = it.next_ump(UmpMode::Midi2, u4::new(0), buffer).unwrap();
+        assert_eq!(m.unwrap().data(), [0x4031_0C58, 32 << 25]);
+    }
+    #[test]
     fn test_control_change_7fh01h() {
         let mut buf = [0x00u8; 8];
         let mut encoder = Encoder {
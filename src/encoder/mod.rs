@@ -64,7 +64,7 @@ impl Encoder {
             EncoderSection::UpperLimit(v) => self.upper_limit = v,
             EncoderSection::SecondMidiId(v) => self.second_midi_id = v,
             EncoderSection::RepeatedValue(v) => self.value = v,
-            EncoderSection::MidiIdMSB(_) => {}
+            EncoderSection::MidiIdMSB(v) => self.midi_id = crate::with_msb(self.midi_id, v),
         }
     }
     pub fn override_channel(&mut self, ch: ChannelOrAll) {
@@ -84,7 +84,7 @@ impl Encoder {
             EncoderSection::UpperLimit(_) => self.upper_limit,
             EncoderSection::SecondMidiId(_) => self.second_midi_id,
             EncoderSection::RepeatedValue(_) => self.value,
-            EncoderSection::MidiIdMSB(_) => 0x00,
+            EncoderSection::MidiIdMSB(_) => self.midi_id >> 7,
         }
     }
 }
@@ -115,6 +115,8 @@ pub enum EncoderMessageType {
     SingleNoteWithFixedValueBothDirections = 0xC,
     SingleNoteWithFixedValueOneDirection0OtherDirection = 0xD,
     TwoNoteWithFixedValueBothDirections = 0xE,
+    /// Sets the BPM from the intervals between pulses in either direction.
+    TapTempo = 0xF,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, IntEnum, Default)]
@@ -1,7 +1,7 @@
 use crate::{
     encoder::{Accelleration, EncoderMessageType, EncoderSection, EncoderSectionId},
     parser::OpenDeckParseError,
-    ChannelOrAll, MessageStatus, Section,
+    MessageStatus, Section,
 };
 
 impl TryFrom<Section> for EncoderSection {
@@ -9,21 +9,19 @@ impl TryFrom<Section> for EncoderSection {
     fn try_from(x: Section) -> Result<Self, Self::Error> {
         if let Ok(id) = EncoderSectionId::try_from(x.id) {
             match id {
-                EncoderSectionId::InvertState => Ok(EncoderSection::Inverted(x.value > 0)),
-                EncoderSectionId::RemoteSync => Ok(EncoderSection::RemoteSync(x.value > 0)),
-                EncoderSectionId::Enabled => Ok(EncoderSection::Enabled(x.value > 0)),
+                EncoderSectionId::InvertState => x.flag().map(EncoderSection::Inverted),
+                EncoderSectionId::RemoteSync => x.flag().map(EncoderSection::RemoteSync),
+                EncoderSectionId::Enabled => x.flag().map(EncoderSection::Enabled),
                 EncoderSectionId::MessageType => EncoderMessageType::try_from(x.value)
                     .map(EncoderSection::MessageType)
                     .map_err(OpenDeckParseError::new_value_err),
-                EncoderSectionId::Channel => {
-                    Ok(EncoderSection::Channel(ChannelOrAll::from(x.value)))
-                }
+                EncoderSectionId::Channel => x.channel().map(EncoderSection::Channel),
                 EncoderSectionId::Accelleration => Accelleration::try_from(x.value)
                     .map(EncoderSection::Accelleration)
                     .map_err(OpenDeckParseError::new_value_err),
-                EncoderSectionId::PulsesPerStep => Ok(EncoderSection::PulsesPerStep(x.value as u8)),
+                EncoderSectionId::PulsesPerStep => x.data_byte().map(EncoderSection::PulsesPerStep),
                 EncoderSectionId::MidiIdLSB => Ok(EncoderSection::MidiIdLSB(x.value)),
-                EncoderSectionId::MidiIdMSB => Ok(EncoderSection::MidiIdMSB(x.value as u8)),
+                EncoderSectionId::MidiIdMSB => x.data_byte().map(EncoderSection::MidiIdMSB),
                 EncoderSectionId::LowerLimit => Ok(EncoderSection::LowerLimit(x.value)),
                 EncoderSectionId::UpperLimit => Ok(EncoderSection::UpperLimit(x.value)),
                 EncoderSectionId::RepeatedValue => Ok(EncoderSection::RepeatedValue(x.value)),
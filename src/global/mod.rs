@@ -6,7 +6,7 @@ pub mod backup;
 pub mod parser;
 pub mod renderer;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct GlobalPreset {
     pub current: usize,
     force_value_refresh: bool,
@@ -23,7 +23,7 @@ impl GlobalPreset {
             PresetIndex::Preservation => self.preserve_preset = value > 0,
         }
     }
-    pub fn get(&mut self, index: PresetIndex) -> u16 {
+    pub fn get(&self, index: PresetIndex) -> u16 {
         match index {
             PresetIndex::Active => self.current as u16,
             PresetIndex::ForceValueRefresh => self.force_value_refresh.into(),
@@ -33,7 +33,7 @@ impl GlobalPreset {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct GlobalMidi {
     standard_note_off: bool,
     running_status: bool,
@@ -57,6 +57,9 @@ impl GlobalMidi {
     pub fn standard_note_off(&self) -> bool {
         self.standard_note_off
     }
+    pub fn running_status(&self) -> bool {
+        self.running_status
+    }
     pub fn use_global_channel(&self) -> bool {
         self.use_global_midi_channel
     }
@@ -78,6 +81,9 @@ impl GlobalMidi {
     pub fn send_midi_clock_enabled(&self) -> bool {
         self.send_midi_clock
     }
+    pub fn set_global_channel(&mut self, channel: ChannelOrAll) {
+        self.global_midi_channel = channel;
+    }
     pub fn set(&mut self, index: MidiIndex, value: u16) {
         match index {
             MidiIndex::StandardNoteOff => self.standard_note_off = value > 0,
@@ -100,7 +106,7 @@ impl GlobalMidi {
             }
         }
     }
-    pub fn get(&mut self, index: MidiIndex) -> u16 {
+    pub fn get(&self, index: MidiIndex) -> u16 {
         match index {
             MidiIndex::StandardNoteOff => self.standard_note_off.into(),
             MidiIndex::RunningStatus => self.running_status.into(),
@@ -11,6 +11,11 @@ impl TryFrom<(u16, Section)> for GlobalSection {
             match id {
                 GlobalSectionId::Midi => {
                     if let Ok(mi) = MidiIndex::try_from(v.0) {
+                        if mi == MidiIndex::GlobalMIDIchannel {
+                            v.1.channel()?;
+                        } else {
+                            v.1.flag()?;
+                        }
                         Ok(GlobalSection::Midi(mi, v.1.value))
                     } else {
                         Err(OpenDeckParseError::StatusError(MessageStatus::IndexError))
@@ -18,6 +23,10 @@ impl TryFrom<(u16, Section)> for GlobalSection {
                 }
                 GlobalSectionId::Presets => {
                     if let Ok(pi) = PresetIndex::try_from(v.0) {
+                        // the active preset is checked against the presets of the board
+                        if pi != PresetIndex::Active {
+                            v.1.flag()?;
+                        }
                         Ok(GlobalSection::Presets(pi, v.1.value))
                     } else {
                         Err(OpenDeckParseError::StatusError(MessageStatus::IndexError))
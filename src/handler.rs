@@ -2,9 +2,11 @@ use crate::analog::handler::AnalogMessages;
 use crate::button::handler::ButtonMessages;
 use crate::encoder::handler::EncoderMessages;
 
-use crate::ChannelOrAll;
+use crate::renderer::OpenDeckRenderer;
+use crate::ump::{self, UmpMode};
+use crate::{BlockId, ChannelOrAll, ValueSize, MAX_MESSAGE_SIZE};
 use midi2::ux::{u4, u7};
-use midi2::{error::BufferOverflow, BytesMessage};
+use midi2::{error::BufferOverflow, BytesMessage, Data, UmpMessage};
 
 pub enum Messages<'a> {
     Button(ButtonMessages<'a>),
@@ -25,6 +27,52 @@ impl Messages<'_> {
             Messages::None => Ok(None),
         }
     }
+    /// Same as [`Messages::next`], rendered as UMP for `group`.
+    pub fn next_ump<'buf>(
+        &mut self,
+        mode: UmpMode,
+        group: u4,
+        buffer: &'buf mut [u32],
+    ) -> Result<Option<UmpMessage<&'buf [u32]>>, BufferOverflow> {
+        match self {
+            Messages::Button(m) => m.next_ump(mode, group, buffer),
+            Messages::Analog(m) => m.next_ump(mode, group, buffer),
+            Messages::Encoder(m) => m.next_ump(mode, group, buffer),
+            Messages::None => Ok(None),
+        }
+    }
+}
+
+/// Tells the configurator which component was used, the index has to fit into
+/// the value size.
+pub(crate) struct ComponentInfo {
+    pub(crate) block: BlockId,
+    pub(crate) index: u16,
+    pub(crate) value_size: ValueSize,
+}
+
+impl ComponentInfo {
+    pub(crate) fn render<'buf>(
+        &self,
+        buffer: &'buf mut [u8],
+    ) -> Result<BytesMessage<&'buf mut [u8]>, BufferOverflow> {
+        match OpenDeckRenderer::new(self.value_size, buffer)
+            .render_component_info(self.block.clone(), self.index)
+        {
+            Ok(Some(m)) => Ok(m.into()),
+            _ => Err(BufferOverflow),
+        }
+    }
+    pub(crate) fn render_ump<'buf>(
+        &self,
+        mode: UmpMode,
+        group: u4,
+        buffer: &'buf mut [u32],
+    ) -> Result<UmpMessage<&'buf [u32]>, BufferOverflow> {
+        let bytes = &mut [0; MAX_MESSAGE_SIZE];
+        let m = self.render(bytes)?;
+        ump::from_bytes(mode, group, m.data(), buffer)
+    }
 }
 
 pub struct ChannelMessages {
@@ -59,6 +107,12 @@ impl ChannelMessages {
             index: 0,
         }
     }
+    /// Skips to the first message of the next channel, for messages that carry
+    /// in one what takes several in MIDI 1.0.
+    pub fn next_first(&mut self) -> Option<(u4, bool)> {
+        self.find(|(_, index, _)| *index == 0)
+            .map(|(channel, _, first)| (channel, first))
+    }
 }
 
 impl Iterator for ChannelMessages {
@@ -1,4 +1,4 @@
-use crate::led::{Led, LedSection, LedSectionId};
+use crate::led::{GlobalLed, Led, LedIndex, LedSection, LedSectionId};
 use crate::{Amount, Block, NewValues, OpenDeckResponse, Wish};
 
 pub struct LedBackupIterator {
@@ -63,3 +63,51 @@ impl LedBackupIterator {
         ))
     }
 }
+
+pub struct GlobalLedBackupIterator {
+    led_index: LedIndex,
+    done: bool,
+}
+
+impl GlobalLedBackupIterator {
+    pub fn new() -> Self {
+        Self {
+            led_index: LedIndex::BlinkWithMIDIClock,
+            done: false,
+        }
+    }
+    pub fn next(&mut self, global_led: &GlobalLed) -> Option<OpenDeckResponse> {
+        if self.done {
+            return None;
+        }
+        let new_values = NewValues::new();
+        let key = self.led_index.clone();
+        let value = match self.led_index {
+            LedIndex::BlinkWithMIDIClock => {
+                self.led_index = LedIndex::EnableStartupAnimation;
+                global_led.blink_with_midi_clock as u16
+            }
+            LedIndex::EnableStartupAnimation => {
+                self.led_index = LedIndex::UseMidiProgramChangeOffset;
+                global_led.startup_animtation as u16
+            }
+            LedIndex::UseMidiProgramChangeOffset => {
+                self.done = true;
+                global_led.midi_program_change_offset as u16
+            }
+        };
+
+        Some(OpenDeckResponse::Configuration(
+            Wish::Set,
+            Amount::Single,
+            Block::Led(key as u16, LedSection::Global(value)),
+            new_values,
+        ))
+    }
+}
+
+impl Default for GlobalLedBackupIterator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,225 @@
+//! Brightness and blinking of outputs in multi-value mode.
+//!
+//! The received velocity or CC value selects both: values below 16 turn the
+//! output off, the lower four bits of each block of 16 select one of four
+//! brightness levels and the block selects the blink speed.
+//!
+//! | value   | blink half period | in MIDI clock pulses |
+//! |---------|-------------------|----------------------|
+//! | 16-31   | no blink          | no blink             |
+//! | 32-47   | 2000 ms           | 96                   |
+//! | 48-63   | 1000 ms           | 48                   |
+//! | 64-79   | 500 ms            | 24                   |
+//! | 80-95   | 250 ms            | 12                   |
+//! | 96-111  | 125 ms            | 6                    |
+//! | 112-127 | 62 ms             | 3                    |
+//!
+//! At 120 BPM both columns are the same. Outputs of the same speed blink in
+//! phase, the firmware advances the timer with [`BlinkEngine::tick`] while
+//! received `F8` clock pulses advance the clock.
+
+use crate::led::{ControlType, Led};
+
+const BLINK_SPEEDS: usize = 6;
+const HALF_PERIOD_MS: [u32; BLINK_SPEEDS] = [2000, 1000, 500, 250, 125, 62];
+const HALF_PERIOD_PULSES: [u32; BLINK_SPEEDS] = [96, 48, 24, 12, 6, 3];
+/// A full period of the slowest speed, every speed completes its periods in it.
+const PULSE_CYCLE: u32 = 2 * HALF_PERIOD_PULSES[0];
+
+const VALUES_PER_BLOCK: u8 = 16;
+
+/// How an output should be driven, brightness is a PWM duty cycle in percent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OutputDrive {
+    pub on: bool,
+    pub brightness: u8,
+}
+
+impl OutputDrive {
+    pub const OFF: OutputDrive = OutputDrive {
+        on: false,
+        brightness: 0,
+    };
+}
+
+/// Brightness in percent for a multi-value output.
+pub fn brightness(value: u8) -> u8 {
+    if value < VALUES_PER_BLOCK {
+        return 0;
+    }
+    (value % VALUES_PER_BLOCK / 4 + 1) * 25
+}
+
+/// Blink speed for a multi-value output, `None` for a steady output.
+///
+/// The speed indexes the table of the module, the slowest is 0.
+pub fn blink_speed(value: u8) -> Option<usize> {
+    (value / VALUES_PER_BLOCK).checked_sub(2).map(usize::from)
+}
+
+/// Tracks the blink phase of every speed, by timer and by MIDI clock.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BlinkEngine {
+    ms: u32,
+    pulses: u32,
+}
+
+impl BlinkEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances the timer by `elapsed_ms`.
+    pub fn tick(&mut self, elapsed_ms: u32) {
+        self.ms = self.ms.wrapping_add(elapsed_ms);
+    }
+
+    /// Advances the clock by a received `F8` pulse.
+    pub fn clock(&mut self) {
+        self.pulses = (self.pulses + 1) % PULSE_CYCLE;
+    }
+
+    /// Restarts the clock phase, on a received Start.
+    pub fn restart(&mut self) {
+        self.pulses = 0;
+    }
+
+    /// Whether outputs blinking at `speed` are in the on half of their period.
+    pub fn phase(&self, speed: usize, with_clock: bool) -> bool {
+        let half_periods = if with_clock {
+            self.pulses / HALF_PERIOD_PULSES[speed]
+        } else {
+            self.ms / HALF_PERIOD_MS[speed]
+        };
+        half_periods % 2 == 0
+    }
+
+    /// How `led` should be driven now, `on` is its state.
+    ///
+    /// Outputs in multi-value mode take brightness and blinking from their level,
+    /// any other output is fully on or off.
+    pub fn drive(&self, led: &Led, on: bool, with_clock: bool) -> OutputDrive {
+        if !on {
+            return OutputDrive::OFF;
+        }
+        if !is_multi_value(led.get_control_type()) {
+            return OutputDrive {
+                on: true,
+                brightness: 100,
+            };
+        }
+        let value = led.get_level();
+        let brightness = brightness(value);
+        let blinking_off = blink_speed(value).is_some_and(|speed| !self.phase(speed, with_clock));
+        if brightness == 0 || blinking_off {
+            return OutputDrive::OFF;
+        }
+        OutputDrive {
+            on: true,
+            brightness,
+        }
+    }
+}
+
+fn is_multi_value(control_type: ControlType) -> bool {
+    matches!(
+        control_type,
+        ControlType::MidiInNoteMultiValue
+            | ControlType::LocalNoteMultiValue
+            | ControlType::MidiInCcMultiValue
+            | ControlType::LocalCcMultiValue
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::led::LedSection;
+
+    fn multi_value_led(level: u8) -> Led {
+        let mut led = Led::new(0);
+        led.set(LedSection::ControlType(ControlType::MidiInCcMultiValue));
+        led.set_level(level);
+        led.set_state(level > 0);
+        led
+    }
+
+    #[test]
+    fn test_value_mapping() {
+        assert_eq!(brightness(15), 0);
+        assert_eq!(brightness(16), 25);
+        assert_eq!(brightness(23), 50);
+        assert_eq!(brightness(31), 100);
+        assert_eq!(brightness(127), 100);
+        assert_eq!(blink_speed(0), None);
+        assert_eq!(blink_speed(31), None);
+        assert_eq!(blink_speed(32), Some(0));
+        assert_eq!(blink_speed(127), Some(BLINK_SPEEDS - 1));
+    }
+
+    #[test]
+    fn test_timer_blink() {
+        let mut engine = BlinkEngine::new();
+        // 500 ms at 50%
+        let led = multi_value_led(64 + 5);
+        let on = OutputDrive {
+            on: true,
+            brightness: 50,
+        };
+        assert_eq!(engine.drive(&led, true, false), on);
+        engine.tick(499);
+        assert_eq!(engine.drive(&led, true, false), on);
+        engine.tick(1);
+        assert_eq!(engine.drive(&led, true, false), OutputDrive::OFF);
+        engine.tick(500);
+        assert_eq!(engine.drive(&led, true, false), on);
+        // clock pulses don't advance the timer
+        engine.clock();
+        assert_eq!(engine.drive(&led, true, false), on);
+
+        assert_eq!(
+            engine.drive(&multi_value_led(10), true, false),
+            OutputDrive::OFF
+        );
+        assert_eq!(engine.drive(&led, false, false), OutputDrive::OFF);
+    }
+
+    #[test]
+    fn test_clock_blink() {
+        let mut engine = BlinkEngine::new();
+        // 12 pulses
+        let led = multi_value_led(80 + 15);
+        for _ in 0..12 {
+            assert!(engine.drive(&led, true, true).on);
+            engine.clock();
+        }
+        assert!(!engine.drive(&led, true, true).on);
+        engine.tick(250);
+        assert!(!engine.drive(&led, true, true).on);
+        engine.restart();
+        assert!(engine.drive(&led, true, true).on);
+        // the slowest speed wraps around with the clock
+        let slow = multi_value_led(32 + 15);
+        for _ in 0..PULSE_CYCLE {
+            engine.clock();
+        }
+        assert!(engine.drive(&slow, true, true).on);
+    }
+
+    #[test]
+    fn test_single_value_is_full_brightness() {
+        let mut led = Led::new(0);
+        led.set(LedSection::ControlType(ControlType::MidiInNoteSingleValue));
+        led.set_level(40);
+        let engine = BlinkEngine::new();
+        assert_eq!(
+            engine.drive(&led, true, false),
+            OutputDrive {
+                on: true,
+                brightness: 100,
+            }
+        );
+    }
+}
@@ -12,7 +12,7 @@ pub enum OutputState {
 
 impl Led {
     /// Process an incoming MIDI message and return the new output state.
-    /// `channel` is 1-based MIDI channel, `id` is note/CC number, `value` is velocity/CC value.
+    /// `channel` is the 0-based MIDI channel of the status byte, `id` is note/CC number, `value` is velocity/CC value.
     pub fn process_midi(&self, channel: u8, id: u8, value: u8, is_note_on: bool) -> OutputState {
         if self.get_control_type() == ControlType::Static {
             return OutputState::On;
@@ -53,6 +53,25 @@ impl Led {
         }
     }
 
+    /// Process a program change, the output is on while `program` equals the
+    /// activation ID plus `offset`.
+    pub fn process_program_change(&self, channel: u8, program: u8, offset: u8) -> OutputState {
+        if self.get_control_type() != ControlType::ProgramChange || !self.channel_matches(channel) {
+            return OutputState::NoChange;
+        }
+        if self.get_activation_id().wrapping_add(offset) & 0x7F == program {
+            OutputState::On
+        } else {
+            OutputState::Off
+        }
+    }
+
+    /// Whether an output in preset change mode is on while `preset` is active.
+    pub fn indicates_preset(&self, preset: usize) -> bool {
+        self.get_control_type() == ControlType::PresetChange
+            && self.get_activation_id() as usize == preset
+    }
+
     fn channel_matches(&self, channel: u8) -> bool {
         match self.get_channel() {
             ChannelOrAll::All => true,
@@ -212,6 +231,29 @@ mod tests {
         let led = make_led(60, 0, 1, ControlType::LocalNoteMultiValue);
         assert_eq!(led.process_midi(1, 60, 0, false), OutputState::Level(0));
     }
+
+    #[test]
+    fn test_program_change() {
+        let led = make_led(5, 0, 1, ControlType::ProgramChange);
+        assert_eq!(led.process_program_change(1, 5, 0), OutputState::On);
+        assert_eq!(led.process_program_change(1, 6, 0), OutputState::Off);
+        assert_eq!(led.process_program_change(2, 5, 0), OutputState::NoChange);
+        assert_eq!(led.process_program_change(1, 7, 2), OutputState::On);
+        assert_eq!(led.process_program_change(1, 5, 2), OutputState::Off);
+        // notes don't affect it
+        assert_eq!(led.process_midi(1, 5, 127, true), OutputState::NoChange);
+
+        let led = make_led(5, 0, 1, ControlType::MidiInNoteSingleValue);
+        assert_eq!(led.process_program_change(1, 5, 0), OutputState::NoChange);
+    }
+
+    #[test]
+    fn test_preset_change() {
+        let led = make_led(1, 0, 1, ControlType::PresetChange);
+        assert!(led.indicates_preset(1));
+        assert!(!led.indicates_preset(0));
+        assert!(!make_led(1, 0, 1, ControlType::ProgramChange).indicates_preset(1));
+    }
 }
 
 #[cfg(test)]
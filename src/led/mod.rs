@@ -2,6 +2,7 @@ use crate::ChannelOrAll;
 use int_enum::IntEnum;
 
 pub mod backup;
+pub mod blink;
 pub mod handler;
 pub mod parser;
 pub mod renderer;
@@ -18,7 +19,7 @@ pub struct Led {
     level: u8,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct GlobalLed {
     blink_with_midi_clock: bool,
     startup_animtation: bool,
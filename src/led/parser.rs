@@ -1,7 +1,7 @@
 use crate::{
     led::{Color, LedSection, LedSectionId},
     parser::OpenDeckParseError,
-    ChannelOrAll, MessageStatus, Section,
+    MessageStatus, Section,
 };
 
 use super::ControlType;
@@ -11,18 +11,18 @@ impl TryFrom<Section> for LedSection {
     fn try_from(v: Section) -> Result<Self, Self::Error> {
         if let Ok(id) = LedSectionId::try_from(v.id) {
             match id {
-                LedSectionId::ActivationId => Ok(LedSection::ActivationId(v.value as u8)),
-                LedSectionId::ActivationValue => Ok(LedSection::ActivationValue(v.value as u8)),
-                LedSectionId::State => Ok(LedSection::State(v.value > 0)),
+                LedSectionId::ActivationId => v.data_byte().map(LedSection::ActivationId),
+                LedSectionId::ActivationValue => v.data_byte().map(LedSection::ActivationValue),
+                LedSectionId::State => v.flag().map(LedSection::State),
                 LedSectionId::ControlType => ControlType::try_from(v.value)
                     .map(LedSection::ControlType)
                     .map_err(OpenDeckParseError::new_value_err),
                 LedSectionId::Reserved => Ok(LedSection::Reserved(v.value)),
-                LedSectionId::BlinkTesting => Ok(LedSection::ColorTesting(
-                    Color::try_from(v.value).unwrap_or_default(),
-                )),
-                LedSectionId::Channel => Ok(LedSection::Channel(ChannelOrAll::from(v.value))),
-                LedSectionId::Global => Ok(LedSection::Global(v.value)),
+                LedSectionId::BlinkTesting => Color::try_from(v.value)
+                    .map(LedSection::ColorTesting)
+                    .map_err(OpenDeckParseError::new_value_err),
+                LedSectionId::Channel => v.channel().map(LedSection::Channel),
+                LedSectionId::Global => v.flag().map(|on| LedSection::Global(on.into())),
             }
         } else {
             Err(OpenDeckParseError::StatusError(MessageStatus::SectionError))
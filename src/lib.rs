@@ -10,19 +10,43 @@ use heapless::Vec;
 pub mod analog;
 pub mod bpm;
 pub mod button;
+pub mod client;
+pub mod clock;
 pub mod config;
+pub mod din;
 pub mod encoder;
 pub mod global;
 pub mod handler;
 pub mod led;
+pub mod midi_in;
 pub mod parser;
 pub mod renderer;
+pub mod routing;
+pub mod storage;
+pub mod sysex;
+pub mod ump;
+pub mod usb;
 
 /// Hardware-specific operations that the library delegates to the firmware.
 pub trait SystemHandler {
     fn reboot(&self);
     fn bootloader(&self);
     fn factory_reset(&self);
+    /// Persists a value changed over SysEx, e.g. in a [`storage::log::LogStore`].
+    fn store_value(
+        &mut self,
+        _key: storage::StorageKey,
+        _value: u16,
+    ) -> Result<(), storage::StorageError> {
+        Ok(())
+    }
+    /// Feeds every persisted value to `apply`, oldest first.
+    fn load_values(
+        &mut self,
+        _apply: &mut dyn FnMut(storage::StorageKey, u16),
+    ) -> Result<(), storage::StorageError> {
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -34,6 +58,9 @@ const M_ID_0: u8 = 0x00;
 const M_ID_1: u8 = 0x53;
 const M_ID_2: u8 = 0x43;
 
+// SPECIAL_MESSAGE_ID of component info messages, see renderer::render_component_info.
+const COMPONENT_INFO_ID: u8 = 0x49;
+
 const BYTES_PER_VALUE: usize = 2;
 const SPECIAL_REQ_MSG_SIZE: usize = 6 + 1 + 1; // extra byte for end
 const STD_REQ_MIN_MSG_SIZE: usize = 10 + BYTES_PER_VALUE * 2 + 1;
@@ -242,3 +269,9 @@ struct Section {
     id: u8,
     value: u16,
 }
+
+/// Replaces the upper 7 bits of a 14-bit value, as sent in the MSB sections of
+/// the one-byte protocol.
+fn with_msb(value: u16, msb: u8) -> u16 {
+    (value & 0x7F) | ((msb as u16 & 0x7F) << 7)
+}
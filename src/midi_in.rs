@@ -0,0 +1,469 @@
+//! Decoding of received MIDI.
+//!
+//! [`MidiDecoder`] turns the bytes of a DIN UART or the payload of USB-MIDI
+//! event packets into [`MidiEvent`]s, keeping the running status between
+//! messages. Realtime messages may appear between any two bytes, even inside
+//! SysEx. Complete messages, like the `data()` of a `midi2` message, are decoded
+//! with [`MidiEvent::parse`] and UMP with [`MidiDecoder::push_ump`].
+//!
+//! The events are handled by [`crate::config::Config::receive_midi`].
+
+use crate::{
+    sysex::{AssemblerError, SysexAssembler, OPENDECK_PREFIX},
+    ump,
+    usb::UsbMidiPacket,
+    MAX_MESSAGE_SIZE, SYSEX_END, SYSEX_START,
+};
+use midi2::ux::u4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Realtime {
+    Clock,
+    Start,
+    Continue,
+    Stop,
+}
+
+impl Realtime {
+    pub fn status(&self) -> u8 {
+        match self {
+            Realtime::Clock => 0xF8,
+            Realtime::Start => 0xFA,
+            Realtime::Continue => 0xFB,
+            Realtime::Stop => 0xFC,
+        }
+    }
+
+    fn from_status(status: u8) -> Option<Self> {
+        match status {
+            0xF8 => Some(Realtime::Clock),
+            0xFA => Some(Realtime::Start),
+            0xFB => Some(Realtime::Continue),
+            0xFC => Some(Realtime::Stop),
+            _ => None,
+        }
+    }
+}
+
+/// A received message used by the configuration, channels are 0-based as in
+/// the status byte.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MidiEvent<'a> {
+    NoteOff {
+        channel: u4,
+        note: u8,
+        velocity: u8,
+    },
+    /// A note on with velocity 0 is passed as received.
+    NoteOn {
+        channel: u4,
+        note: u8,
+        velocity: u8,
+    },
+    ControlChange {
+        channel: u4,
+        control: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u4,
+        program: u8,
+    },
+    Realtime(Realtime),
+    /// A complete OpenDeck SysEx message, including `F0` and `F7`.
+    Sysex(&'a [u8]),
+}
+
+impl<'a> MidiEvent<'a> {
+    /// Decodes a single complete message, without running status.
+    ///
+    /// Messages the configuration doesn't use, like pitch bend or SysEx of other
+    /// manufacturers, return `None`.
+    pub fn parse(message: &'a [u8]) -> Option<Self> {
+        let (&status, data) = message.split_first()?;
+        match status {
+            SYSEX_START => (message.starts_with(&OPENDECK_PREFIX)
+                && message.ends_with(&[SYSEX_END]))
+            .then_some(MidiEvent::Sysex(message)),
+            0xF8..=0xFF => Realtime::from_status(status).map(MidiEvent::Realtime),
+            0x80..=0xEF if data.len() >= data_length(status) => {
+                channel_message(status, data[0], data.get(1).copied().unwrap_or(0))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Number of data bytes following a status byte.
+pub(crate) fn data_length(status: u8) -> usize {
+    match status {
+        0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+        _ => 2,
+    }
+}
+
+fn channel_message(status: u8, first: u8, second: u8) -> Option<MidiEvent<'static>> {
+    let channel = u4::new(status & 0x0F);
+    match status >> 4 {
+        0x8 => Some(MidiEvent::NoteOff {
+            channel,
+            note: first,
+            velocity: second,
+        }),
+        0x9 => Some(MidiEvent::NoteOn {
+            channel,
+            note: first,
+            velocity: second,
+        }),
+        0xB => Some(MidiEvent::ControlChange {
+            channel,
+            control: first,
+            value: second,
+        }),
+        0xC => Some(MidiEvent::ProgramChange {
+            channel,
+            program: first,
+        }),
+        _ => None,
+    }
+}
+
+fn sysex(result: Option<Result<&[u8], AssemblerError>>) -> Option<MidiEvent<'_>> {
+    match result {
+        Some(Ok(message)) => Some(MidiEvent::Sysex(message)),
+        _ => None,
+    }
+}
+
+/// Decodes a stream of received bytes, SysEx of up to `N` bytes is collected.
+///
+/// Incomplete messages and SysEx that doesn't fit are dropped. Each interface,
+/// and each USB cable, needs its own decoder.
+pub struct MidiDecoder<const N: usize = MAX_MESSAGE_SIZE> {
+    /// Status of the message being received, kept for channel messages.
+    status: Option<u8>,
+    data: [u8; 2],
+    len: usize,
+    in_sysex: bool,
+    sysex: SysexAssembler<N>,
+}
+
+impl<const N: usize> Default for MidiDecoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> MidiDecoder<N> {
+    pub fn new() -> Self {
+        MidiDecoder {
+            status: None,
+            data: [0; 2],
+            len: 0,
+            in_sysex: false,
+            sysex: SysexAssembler::new(),
+        }
+    }
+
+    /// Drops the running status and a partially received message.
+    pub fn reset(&mut self) {
+        self.status = None;
+        self.len = 0;
+        self.in_sysex = false;
+        self.sysex.reset();
+    }
+
+    /// Feeds a received byte, returns the event it completes.
+    pub fn push(&mut self, byte: u8) -> Option<MidiEvent<'_>> {
+        match byte {
+            // realtime messages leave the message being received untouched
+            0xF8..=0xFF => Realtime::from_status(byte).map(MidiEvent::Realtime),
+            0x80..=0xF7 => {
+                self.len = 0;
+                self.in_sysex = byte == SYSEX_START;
+                // system common messages cancel the running status, only their
+                // data bytes are skipped
+                self.status = match byte {
+                    0x80..=0xEF | 0xF1..=0xF3 => Some(byte),
+                    _ => None,
+                };
+                sysex(self.sysex.push(byte))
+            }
+            _ if self.in_sysex => {
+                self.sysex.push(byte);
+                None
+            }
+            _ => {
+                let status = self.status?;
+                self.data[self.len] = byte;
+                self.len += 1;
+                if self.len < data_length(status) {
+                    return None;
+                }
+                self.len = 0;
+                if status >= 0xF0 {
+                    self.status = None;
+                    return None;
+                }
+                channel_message(status, self.data[0], self.data[1])
+            }
+        }
+    }
+
+    /// Feeds the payload of a USB-MIDI event packet, returns the event it
+    /// completes.
+    ///
+    /// A packet carries a single message or a part of SysEx, only its last byte
+    /// can complete an event.
+    pub fn push_packet(&mut self, packet: UsbMidiPacket) -> Option<MidiEvent<'_>> {
+        let (&last, bytes) = packet.payload().split_last()?;
+        for &byte in bytes {
+            self.push(byte);
+        }
+        self.push(last)
+    }
+
+    /// Decodes a UMP message, pass the `data()` of a `midi2` UMP message.
+    ///
+    /// MIDI 2.0 channel voice messages are scaled down to 7 bits. SysEx is
+    /// collected from its 64-bit data messages.
+    pub fn push_ump(&mut self, message: &[u32]) -> Option<MidiEvent<'_>> {
+        let first = *message.first()?;
+        let byte = |shift: u32| (first >> shift) as u8;
+        match first >> 28 {
+            ump::SYSTEM => Realtime::from_status(byte(16)).map(MidiEvent::Realtime),
+            ump::MIDI1_CHANNEL_VOICE => channel_message(byte(16), byte(8) & 0x7F, byte(0) & 0x7F),
+            ump::MIDI2_CHANNEL_VOICE => midi2_message(first, *message.get(1)?),
+            ump::DATA_64 => self.push_sysex7(first, *message.get(1)?),
+            _ => None,
+        }
+    }
+
+    fn push_sysex7(&mut self, first: u32, second: u32) -> Option<MidiEvent<'_>> {
+        let status = (first >> 20) & 0xF;
+        let len = ((first >> 16) & 0xF).min(6) as usize;
+        let [_, _, b0, b1] = first.to_be_bytes();
+        let [b2, b3, b4, b5] = second.to_be_bytes();
+        // complete in one packet or start
+        if matches!(status, 0x0 | 0x1) {
+            self.sysex.push(SYSEX_START);
+        }
+        for byte in [b0, b1, b2, b3, b4, b5].into_iter().take(len) {
+            self.sysex.push(byte & 0x7F);
+        }
+        // complete in one packet or end
+        if matches!(status, 0x0 | 0x3) {
+            return sysex(self.sysex.push(SYSEX_END));
+        }
+        None
+    }
+}
+
+fn midi2_message(first: u32, data: u32) -> Option<MidiEvent<'static>> {
+    let [_, status, index, _] = first.to_be_bytes();
+    let channel = u4::new(status & 0x0F);
+    let index = index & 0x7F;
+    let velocity = ump::scale_down(data >> 16, 16, 7) as u8;
+    match status >> 4 {
+        0x8 => Some(MidiEvent::NoteOff {
+            channel,
+            note: index,
+            velocity,
+        }),
+        // a MIDI 2.0 note on with velocity 0 is not a note off
+        0x9 => Some(MidiEvent::NoteOn {
+            channel,
+            note: index,
+            velocity: velocity.max(1),
+        }),
+        0xB => Some(MidiEvent::ControlChange {
+            channel,
+            control: index,
+            value: ump::scale_down(data, 32, 7) as u8,
+        }),
+        0xC => Some(MidiEvent::ProgramChange {
+            channel,
+            program: (data >> 24) as u8 & 0x7F,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HANDSHAKE: [u8; 8] = [0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0xF7];
+
+    fn note_on(channel: u8, note: u8, velocity: u8) -> MidiEvent<'static> {
+        MidiEvent::NoteOn {
+            channel: u4::new(channel),
+            note,
+            velocity,
+        }
+    }
+
+    #[test]
+    fn test_running_status_with_interleaved_realtime() {
+        let mut decoder: MidiDecoder = MidiDecoder::new();
+        assert_eq!(decoder.push(0x92), None);
+        assert_eq!(decoder.push(0x40), None);
+        assert_eq!(
+            decoder.push(0xF8),
+            Some(MidiEvent::Realtime(Realtime::Clock))
+        );
+        assert_eq!(decoder.push(0x7F), Some(note_on(2, 0x40, 0x7F)));
+        // running status
+        assert_eq!(decoder.push(0x41), None);
+        assert_eq!(decoder.push(0x00), Some(note_on(2, 0x41, 0x00)));
+        assert_eq!(decoder.push(0xC1), None);
+        assert_eq!(
+            decoder.push(0x05),
+            Some(MidiEvent::ProgramChange {
+                channel: u4::new(1),
+                program: 5,
+            })
+        );
+        assert_eq!(
+            decoder.push(0x06),
+            Some(MidiEvent::ProgramChange {
+                channel: u4::new(1),
+                program: 6,
+            })
+        );
+        // pitch bend is skipped, its data isn't taken for another message
+        for byte in [0xE0, 0x00, 0x40] {
+            assert_eq!(decoder.push(byte), None);
+        }
+        // a system common message cancels the running status
+        for byte in [0xF3, 0x01, 0x40, 0x7F] {
+            assert_eq!(decoder.push(byte), None);
+        }
+        assert_eq!(
+            decoder.push(0xFA),
+            Some(MidiEvent::Realtime(Realtime::Start))
+        );
+        assert_eq!(decoder.push(0xFE), None);
+    }
+
+    #[test]
+    fn test_sysex_between_channel_messages() {
+        let mut decoder: MidiDecoder = MidiDecoder::new();
+        decoder.push(0xB0);
+        for &byte in &HANDSHAKE[..7] {
+            assert_eq!(decoder.push(byte), None);
+            assert_eq!(
+                decoder.push(0xF8),
+                Some(MidiEvent::Realtime(Realtime::Clock))
+            );
+        }
+        assert_eq!(decoder.push(0xF7), Some(MidiEvent::Sysex(&HANDSHAKE)));
+        // SysEx cancelled the running status
+        assert_eq!(decoder.push(0x07), None);
+        assert_eq!(decoder.push(0xB0), None);
+        assert_eq!(decoder.push(0x07), None);
+        assert_eq!(
+            decoder.push(0x64),
+            Some(MidiEvent::ControlChange {
+                channel: u4::new(0),
+                control: 7,
+                value: 100,
+            })
+        );
+    }
+
+    #[test]
+    fn test_usb_packets() {
+        let mut decoder: MidiDecoder = MidiDecoder::new();
+        assert_eq!(
+            decoder.push_packet(UsbMidiPacket([0x09, 0x9F, 0x3C, 0x40])),
+            Some(note_on(15, 0x3C, 0x40))
+        );
+        let mut packets = crate::usb::packets(u4::new(0), &HANDSHAKE);
+        assert_eq!(decoder.push_packet(packets.next().unwrap()), None);
+        assert_eq!(
+            decoder.push_packet(UsbMidiPacket([0x0F, 0xF8, 0x00, 0x00])),
+            Some(MidiEvent::Realtime(Realtime::Clock))
+        );
+        assert_eq!(decoder.push_packet(packets.next().unwrap()), None);
+        assert_eq!(
+            decoder.push_packet(packets.next().unwrap()),
+            Some(MidiEvent::Sysex(&HANDSHAKE))
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            MidiEvent::parse(&[0x90, 0x3C, 0x7F]),
+            Some(note_on(0, 0x3C, 0x7F))
+        );
+        assert_eq!(
+            MidiEvent::parse(&[0x8A, 0x3C, 0x40]),
+            Some(MidiEvent::NoteOff {
+                channel: u4::new(10),
+                note: 0x3C,
+                velocity: 0x40,
+            })
+        );
+        assert_eq!(
+            MidiEvent::parse(&[0xFC]),
+            Some(MidiEvent::Realtime(Realtime::Stop))
+        );
+        assert_eq!(
+            MidiEvent::parse(&HANDSHAKE),
+            Some(MidiEvent::Sysex(&HANDSHAKE))
+        );
+        assert_eq!(
+            MidiEvent::parse(&[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]),
+            None
+        );
+        assert_eq!(MidiEvent::parse(&[0x90, 0x3C]), None);
+        assert_eq!(MidiEvent::parse(&[]), None);
+    }
+
+    #[test]
+    fn test_ump() {
+        let mut decoder: MidiDecoder = MidiDecoder::new();
+        assert_eq!(
+            decoder.push_ump(&[0x11F8_0000]),
+            Some(MidiEvent::Realtime(Realtime::Clock))
+        );
+        assert_eq!(
+            decoder.push_ump(&[0x2192_407F]),
+            Some(note_on(2, 0x40, 0x7F))
+        );
+        // MIDI 2.0 channel voice
+        assert_eq!(
+            decoder.push_ump(&[0x4192_4000, 0x0001_0000]),
+            Some(note_on(2, 0x40, 1))
+        );
+        assert_eq!(
+            decoder.push_ump(&[0x41B0_0700, 0x8000_0000]),
+            Some(MidiEvent::ControlChange {
+                channel: u4::new(0),
+                control: 7,
+                value: 64,
+            })
+        );
+        assert_eq!(
+            decoder.push_ump(&[0x41C3_0000, 0x0500_0000]),
+            Some(MidiEvent::ProgramChange {
+                channel: u4::new(3),
+                program: 5,
+            })
+        );
+        assert_eq!(decoder.push_ump(&[0x4192_4000]), None);
+        // the SysEx is collected over two packets
+        let message = [0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x02, 0xF7];
+        let buffer = &mut [0; 4];
+        let words = ump::from_bytes(ump::UmpMode::Midi1, u4::new(0), &message, buffer).unwrap();
+        let words = midi2::Data::data(&words);
+        assert_eq!(decoder.push_ump(&words[..2]), None);
+        assert_eq!(
+            decoder.push_ump(&words[2..]),
+            Some(MidiEvent::Sysex(&message))
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{
     analog::AnalogSection, button::ButtonSection, encoder::EncoderSection, global::GlobalSection,
     led::LedSection, Amount, AmountId, Block, BlockId, ByteOrder, ChannelOrAll, MessageStatus,
-    OpenDeckRequest, Section, SpecialRequest, ValueSize, Wish, M_ID_0, M_ID_1, M_ID_2,
+    NewValues, OpenDeckRequest, Section, SpecialRequest, ValueSize, Wish, M_ID_0, M_ID_1, M_ID_2,
     SPECIAL_REQ_MSG_SIZE, SYSEX_END, SYSEX_START,
 };
 
@@ -53,6 +53,31 @@ impl TryFrom<u8> for Wish {
     }
 }
 
+impl TryFrom<u8> for MessageStatus {
+    type Error = OpenDeckParseError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(MessageStatus::Request),
+            0x01 => Ok(MessageStatus::Response),
+            0x02 => Ok(MessageStatus::StatusError),
+            0x03 => Ok(MessageStatus::HandshakeError),
+            0x04 => Ok(MessageStatus::WishError),
+            0x05 => Ok(MessageStatus::AmountError),
+            0x06 => Ok(MessageStatus::BlockError),
+            0x07 => Ok(MessageStatus::SectionError),
+            0x08 => Ok(MessageStatus::PartError),
+            0x09 => Ok(MessageStatus::IndexError),
+            0x0A => Ok(MessageStatus::NewValueError),
+            0x0B => Ok(MessageStatus::MessageLengthError),
+            0x0C => Ok(MessageStatus::WriteError),
+            0x0D => Ok(MessageStatus::NotSupportedError),
+            0x0E => Ok(MessageStatus::ReadError),
+            0x80 => Ok(MessageStatus::UARTAllocationError),
+            _ => Err(OpenDeckParseError::StatusError(MessageStatus::StatusError)),
+        }
+    }
+}
+
 impl TryFrom<(u8, u8)> for Amount {
     type Error = OpenDeckParseError;
     fn try_from(value: (u8, u8)) -> Result<Self, Self::Error> {
@@ -71,7 +96,7 @@ impl From<u16> for ChannelOrAll {
         } else if value == 0 {
             ChannelOrAll::None
         } else {
-            ChannelOrAll::Channel(value as u8)
+            ChannelOrAll::Channel(value as u8 - 1)
         }
     }
 }
@@ -91,6 +116,33 @@ impl OpenDeckParseError {
     }
 }
 
+impl Section {
+    /// The value as a MIDI data byte.
+    pub(crate) fn data_byte(&self) -> Result<u8, OpenDeckParseError> {
+        if self.value > 0x7F {
+            return Err(OpenDeckParseError::new_value_err(self.value));
+        }
+        Ok(self.value as u8)
+    }
+
+    /// The value of an on/off setting.
+    pub(crate) fn flag(&self) -> Result<bool, OpenDeckParseError> {
+        match self.value {
+            0 => Ok(false),
+            1 => Ok(true),
+            v => Err(OpenDeckParseError::new_value_err(v)),
+        }
+    }
+
+    /// The value of a 1-based channel setting, 17 selects all channels.
+    pub(crate) fn channel(&self) -> Result<ChannelOrAll, OpenDeckParseError> {
+        if self.value > 17 {
+            return Err(OpenDeckParseError::new_value_err(self.value));
+        }
+        Ok(ChannelOrAll::from(self.value))
+    }
+}
+
 pub struct OpenDeckParser {
     value_size: ValueSize,
 }
@@ -101,33 +153,40 @@ impl OpenDeckParser {
     }
 
     pub fn parse(&self, buf: &[u8]) -> Result<OpenDeckRequest, OpenDeckParseError> {
-        if buf.len() < 8 {
-            return Err(OpenDeckParseError::BufferTooShort);
-        }
-        if ByteOrder::Start.get(buf) != SYSEX_START {
-            return Err(OpenDeckParseError::NoSysex);
-        }
-        if buf[buf.len() - 1] != SYSEX_END {
-            return Err(OpenDeckParseError::NoSysex);
-        }
-
-        if !(ByteOrder::Id1.get(buf) == M_ID_0
-            && ByteOrder::Id2.get(buf) == M_ID_1
-            && ByteOrder::Id3.get(buf) == M_ID_2)
-        {
-            return Err(OpenDeckParseError::WrongManufacturer);
-        }
+        check_frame(buf)?;
 
-        if !ByteOrder::Status.get(buf) == MessageStatus::Request as u8 {
+        if ByteOrder::Status.get(buf) != MessageStatus::Request as u8 {
             return Err(OpenDeckParseError::StatusError(MessageStatus::StatusError));
         }
         if buf.len() == SPECIAL_REQ_MSG_SIZE {
             self.parse_special_request(buf)
         } else {
+            self.check_length(buf)?;
             self.parse_request(buf)
         }
     }
 
+    /// A standard request carries an index and a value, only a Set of all values
+    /// carries more values.
+    fn check_length(&self, buf: &[u8]) -> Result<(), OpenDeckParseError> {
+        let size = self.value_size as usize;
+        let min = ByteOrder::Index as usize + 2 * size + 1;
+        let set_all = ByteOrder::Wish.get(buf) == Wish::Set as u8
+            && ByteOrder::Amount.get(buf) == AmountId::All as u8;
+        let valid = if set_all {
+            buf.len() >= min && (buf.len() - min).is_multiple_of(size)
+        } else {
+            buf.len() == min
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(OpenDeckParseError::StatusError(
+                MessageStatus::MessageLengthError,
+            ))
+        }
+    }
+
     pub fn parse_special_request(&self, buf: &[u8]) -> Result<OpenDeckRequest, OpenDeckParseError> {
         let special = SpecialRequest::try_from(ByteOrder::Wish.get(buf))?;
         Ok(OpenDeckRequest::Special(special))
@@ -139,6 +198,21 @@ impl OpenDeckParser {
         let block = self.parse_block(buf)?;
         Ok(OpenDeckRequest::Configuration(wish, amount, block))
     }
+
+    /// Decodes the values of a Set of all values, they follow the index and value
+    /// of the block.
+    pub(crate) fn parse_values(&self, buf: &[u8]) -> Result<NewValues, OpenDeckParseError> {
+        let size = self.value_size as usize;
+        let start = ByteOrder::Index as usize + 2 * size;
+        let mut values = NewValues::new();
+        for i in 0..buf.len().saturating_sub(start + 1) / size {
+            values
+                .push(self.value_size.parse_at(buf, start, i))
+                .map_err(|_| OpenDeckParseError::StatusError(MessageStatus::MessageLengthError))?;
+        }
+        Ok(values)
+    }
+
     pub fn parse_block(&self, buf: &[u8]) -> Result<Block, OpenDeckParseError> {
         let block_id = ByteOrder::Block.get(buf);
         let index = self.value_size.parse(buf, 0);
@@ -146,6 +220,37 @@ impl OpenDeckParser {
             id: ByteOrder::Section.get(buf),
             value: self.value_size.parse(buf, 1),
         };
+        Block::from_parts(block_id, index, section)
+    }
+}
+
+/// Checks the SysEx start/end bytes and the OpenDeck manufacturer id.
+pub(crate) fn check_frame(buf: &[u8]) -> Result<(), OpenDeckParseError> {
+    if buf.len() < SPECIAL_REQ_MSG_SIZE {
+        return Err(OpenDeckParseError::BufferTooShort);
+    }
+    if ByteOrder::Start.get(buf) != SYSEX_START {
+        return Err(OpenDeckParseError::NoSysex);
+    }
+    if buf[buf.len() - 1] != SYSEX_END {
+        return Err(OpenDeckParseError::NoSysex);
+    }
+
+    if !(ByteOrder::Id1.get(buf) == M_ID_0
+        && ByteOrder::Id2.get(buf) == M_ID_1
+        && ByteOrder::Id3.get(buf) == M_ID_2)
+    {
+        return Err(OpenDeckParseError::WrongManufacturer);
+    }
+    Ok(())
+}
+
+impl Block {
+    pub(crate) fn from_parts(
+        block_id: u8,
+        index: u16,
+        section: Section,
+    ) -> Result<Block, OpenDeckParseError> {
         match block_id {
             x if x == BlockId::Global as u8 => {
                 let section = GlobalSection::try_from((index, section))?;
@@ -174,7 +279,11 @@ impl OpenDeckParser {
 
 impl ValueSize {
     fn parse(&self, buf: &[u8], index: usize) -> u16 {
-        let start = ByteOrder::Index as usize;
+        self.parse_at(buf, ByteOrder::Index as usize, index)
+    }
+
+    /// Decodes the `index`-th value of a payload starting at `start`.
+    pub(crate) fn parse_at(&self, buf: &[u8], start: usize, index: usize) -> u16 {
         match self {
             ValueSize::OneByte => buf[start + index] as u16,
             ValueSize::TwoBytes => {
@@ -203,7 +312,7 @@ impl ValueSize {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::global::PresetIndex;
+    use crate::global::{MidiIndex, PresetIndex};
 
     #[test]
     fn should_parse_special_messages() {
@@ -271,6 +380,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::identity_op)]
     fn should_parse_configuration_messages() {
         let p = OpenDeckParser::new(ValueSize::OneByte);
         assert_eq!(
@@ -304,6 +414,86 @@ mod tests {
             ))
         );
     }
+    #[test]
+    fn should_reject_invalid_status_and_length() {
+        let p = OpenDeckParser::new(ValueSize::TwoBytes);
+        assert_eq!(
+            p.parse(&[0xF0, 0x00, 0x53, 0x43, 0x01, 0x00, 0x01, 0xF7]),
+            Err(OpenDeckParseError::StatusError(MessageStatus::StatusError))
+        );
+        assert_eq!(
+            p.parse(&[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0xF7]),
+            Err(OpenDeckParseError::StatusError(
+                MessageStatus::MessageLengthError
+            ))
+        );
+        assert_eq!(
+            p.parse(&[
+                0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0xF7
+            ]),
+            Err(OpenDeckParseError::StatusError(
+                MessageStatus::MessageLengthError
+            ))
+        );
+        // a Set of all values carries one value per component
+        assert_eq!(
+            p.parse(&[
+                0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x01, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0xF7
+            ]),
+            Ok(OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::All(0),
+                Block::Button(0, ButtonSection::MidiId(0)),
+            ))
+        );
+    }
+
+    #[test]
+    fn should_reject_values_out_of_range() {
+        let p = OpenDeckParser::new(ValueSize::TwoBytes);
+        let new_value_error = Err(OpenDeckParseError::StatusError(
+            MessageStatus::NewValueError,
+        ));
+        // button MidiId 128
+        assert_eq!(
+            p.parse(&[
+                0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x01, 0x02, 0x00, 0x00, 0x01, 0x00,
+                0xF7
+            ]),
+            new_value_error
+        );
+        // encoder enabled 2
+        assert_eq!(
+            p.parse(&[
+                0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x02,
+                0xF7
+            ]),
+            new_value_error
+        );
+        // LED channel 18
+        assert_eq!(
+            p.parse(&[
+                0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x04, 0x07, 0x00, 0x00, 0x00, 0x12,
+                0xF7
+            ]),
+            new_value_error
+        );
+        // global MIDI channel 17 selects all channels
+        assert_eq!(
+            p.parse(&[
+                0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x0E, 0x00, 0x11,
+                0xF7
+            ]),
+            Ok(OpenDeckRequest::Configuration(
+                Wish::Set,
+                Amount::Single,
+                Block::Global(GlobalSection::Midi(MidiIndex::GlobalMIDIchannel, 17)),
+            ))
+        );
+    }
+
     #[test]
     fn should_split_u16() {
         let buf = &[
@@ -6,7 +6,7 @@ pub struct OpenDeckRenderer<'buf> {
 use crate::{
     Amount, AmountId, Block, BlockId, ByteOrder, ChannelOrAll, HardwareUid, MessageStatus,
     NrOfSupportedComponents, OpenDeckResponse, Section, SpecialRequest, SpecialResponse, ValueSize,
-    MAX_MESSAGE_SIZE, M_ID_0, M_ID_1, M_ID_2,
+    COMPONENT_INFO_ID, MAX_MESSAGE_SIZE, M_ID_0, M_ID_1, M_ID_2,
 };
 
 use heapless::Vec;
@@ -25,7 +25,7 @@ impl From<BufferOverflow> for RenderError {
     }
 }
 
-type Buffer = Vec<u8, MAX_MESSAGE_SIZE>;
+pub(crate) type Buffer = Vec<u8, MAX_MESSAGE_SIZE>;
 
 impl<'buf> OpenDeckRenderer<'buf> {
     pub fn new<'a: 'buf>(value_size: ValueSize, buffer: &'a mut [u8]) -> Self {
@@ -125,7 +125,7 @@ impl<'buf> OpenDeckRenderer<'buf> {
         buf.push(M_ID_2).unwrap();
         buf.push(MessageStatus::Response as u8).unwrap();
         buf.push(0x00).unwrap(); // MESSAGE_PART
-        buf.push(0x49).unwrap(); // SPECIAL_MESSAGE_ID for component info
+        buf.push(COMPONENT_INFO_ID).unwrap();
         buf.push(block as u8).unwrap();
         buf = self.value_size.push(index, buf)?;
 
@@ -136,7 +136,7 @@ impl<'buf> OpenDeckRenderer<'buf> {
 }
 
 impl ValueSize {
-    fn push(&self, value: u16, mut buf: Buffer) -> Result<Buffer, RenderError> {
+    pub(crate) fn push(&self, value: u16, mut buf: Buffer) -> Result<Buffer, RenderError> {
         match self {
             ValueSize::OneByte => {
                 if value >= 128 {
@@ -193,7 +193,7 @@ impl HardwareUid {
 }
 
 impl Amount {
-    fn push(self, mut buf: Buffer) -> Buffer {
+    pub(crate) fn push(self, mut buf: Buffer) -> Buffer {
         match self {
             Amount::Single => {
                 buf.push(AmountId::Single as u8).unwrap();
@@ -218,8 +218,8 @@ impl From<ChannelOrAll> for u16 {
 }
 
 impl Block {
-    fn push(self, mut buf: Buffer, value_size: &ValueSize) -> Result<Buffer, RenderError> {
-        let (index, block_id, section) = match self {
+    pub(crate) fn into_parts(self) -> (u16, BlockId, Section) {
+        match self {
             Block::Global(section) => {
                 let result: (u16, Section) = section.into();
                 (result.0, BlockId::Global, result.1)
@@ -230,7 +230,14 @@ impl Block {
             Block::Led(i, section) => (i, BlockId::Led, section.into()),
             Block::Display => (0, BlockId::Display, Section { id: 0, value: 0 }),
             Block::Touchscreen => (0, BlockId::Touchscreen, Section { id: 0, value: 0 }),
-        };
+        }
+    }
+    pub(crate) fn push(
+        self,
+        mut buf: Buffer,
+        value_size: &ValueSize,
+    ) -> Result<Buffer, RenderError> {
+        let (index, block_id, section) = self.into_parts();
         buf.push(block_id as u8).unwrap();
         buf.push(section.id).unwrap();
         buf = value_size.push(index, buf)?;
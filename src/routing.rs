@@ -0,0 +1,217 @@
+//! MIDI thru and output routing between the USB, DIN and BLE interfaces.
+//!
+//! [`Router`] forwards received messages according to the thru settings of
+//! [`GlobalMidi`] and sends the messages of the components on every enabled
+//! interface. The firmware provides a [`MidiSink`] per interface.
+
+use crate::{
+    global::{GlobalMidi, MidiIndex},
+    handler::Messages,
+    sysex::OPENDECK_PREFIX,
+};
+use midi2::{error::BufferOverflow, Data};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Interface {
+    Usb,
+    Din,
+    Ble,
+}
+
+impl Interface {
+    pub const ALL: [Interface; 3] = [Interface::Usb, Interface::Din, Interface::Ble];
+
+    /// USB is always enabled, DIN and BLE can be turned off.
+    pub fn is_enabled(self, midi: &GlobalMidi) -> bool {
+        match self {
+            Interface::Usb => true,
+            Interface::Din => midi.get(MidiIndex::DINMIDIstate) > 0,
+            Interface::Ble => midi.get(MidiIndex::BLEMIDIstate) > 0,
+        }
+    }
+
+    /// Whether messages received on this interface are forwarded to `to`, both
+    /// interfaces must be enabled.
+    pub fn thru(self, to: Interface, midi: &GlobalMidi) -> bool {
+        let index = match (self, to) {
+            (Interface::Usb, Interface::Usb) => MidiIndex::USBtoUSBthru,
+            (Interface::Usb, Interface::Din) => MidiIndex::USBtoDINthru,
+            (Interface::Usb, Interface::Ble) => MidiIndex::USBtoBLEthru,
+            (Interface::Din, Interface::Usb) => MidiIndex::DINtoUSBthru,
+            (Interface::Din, Interface::Din) => MidiIndex::DINtoDINthru,
+            (Interface::Din, Interface::Ble) => MidiIndex::DINtoBLEthru,
+            (Interface::Ble, Interface::Usb) => MidiIndex::BLEtoUSBthru,
+            (Interface::Ble, Interface::Din) => MidiIndex::BLEtoDINthru,
+            (Interface::Ble, Interface::Ble) => MidiIndex::BLEtoBLEthru,
+        };
+        midi.get(index) > 0 && self.is_enabled(midi) && to.is_enabled(midi)
+    }
+}
+
+/// Sends complete MIDI messages on an interface.
+pub trait MidiSink {
+    fn send(&mut self, message: &[u8]);
+}
+
+/// A board without the interface.
+impl MidiSink for () {
+    fn send(&mut self, _message: &[u8]) {}
+}
+
+pub struct Router<U: MidiSink, D: MidiSink, B: MidiSink> {
+    pub usb: U,
+    pub din: D,
+    pub ble: B,
+}
+
+impl<U: MidiSink, D: MidiSink, B: MidiSink> Router<U, D, B> {
+    pub fn new(usb: U, din: D, ble: B) -> Self {
+        Router { usb, din, ble }
+    }
+
+    fn sink(&mut self, interface: Interface) -> &mut dyn MidiSink {
+        match interface {
+            Interface::Usb => &mut self.usb,
+            Interface::Din => &mut self.din,
+            Interface::Ble => &mut self.ble,
+        }
+    }
+
+    /// Forwards a message received on `source` to the interfaces the thru
+    /// settings route it to, returns the number of interfaces it was sent on.
+    pub fn route_incoming(
+        &mut self,
+        midi: &GlobalMidi,
+        source: Interface,
+        message: &[u8],
+    ) -> usize {
+        let mut count = 0;
+        for destination in Interface::ALL {
+            if source.thru(destination, midi) {
+                self.sink(destination).send(message);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Sends a locally generated message on every enabled interface, returns the
+    /// number of interfaces it was sent on.
+    ///
+    /// OpenDeck SysEx, like Component Info messages, is meant for the
+    /// configurator and only sent on USB.
+    pub fn send(&mut self, midi: &GlobalMidi, message: &[u8]) -> usize {
+        if message.starts_with(&OPENDECK_PREFIX) {
+            self.usb.send(message);
+            return 1;
+        }
+        let mut count = 0;
+        for interface in Interface::ALL {
+            if interface.is_enabled(midi) {
+                self.sink(interface).send(message);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Sends all messages of a component with [`Router::send`].
+    pub fn send_messages(
+        &mut self,
+        midi: &GlobalMidi,
+        messages: &mut Messages<'_>,
+        buffer: &mut [u8],
+    ) -> Result<(), BufferOverflow> {
+        while let Some(message) = messages.next(buffer)? {
+            self.send(midi, message.data());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{renderer::OpenDeckRenderer, BlockId, ValueSize, MAX_MESSAGE_SIZE};
+    use heapless::Vec;
+
+    #[derive(Default)]
+    struct Recorder(Vec<Vec<u8, 16>, 4>);
+
+    impl MidiSink for &mut Recorder {
+        fn send(&mut self, message: &[u8]) {
+            self.0.push(Vec::from_slice(message).unwrap()).unwrap();
+        }
+    }
+
+    impl Recorder {
+        fn sent(&self) -> Vec<&[u8], 4> {
+            self.0.iter().map(|message| message.as_slice()).collect()
+        }
+    }
+
+    fn settings(settings: &[MidiIndex]) -> GlobalMidi {
+        let mut midi = GlobalMidi::default();
+        for &index in settings {
+            midi.set(index, 1);
+        }
+        midi
+    }
+
+    #[test]
+    fn test_route_incoming() {
+        let (mut usb, mut din, mut ble) = (
+            Recorder::default(),
+            Recorder::default(),
+            Recorder::default(),
+        );
+        let note = [0x90, 0x40, 0x7F];
+        {
+            let mut router = Router::new(&mut usb, &mut din, &mut ble);
+            // DIN is disabled
+            let midi = settings(&[MidiIndex::USBtoDINthru]);
+            assert_eq!(router.route_incoming(&midi, Interface::Usb, &note), 0);
+
+            let midi = settings(&[
+                MidiIndex::DINMIDIstate,
+                MidiIndex::USBtoDINthru,
+                MidiIndex::USBtoUSBthru,
+                MidiIndex::USBtoBLEthru,
+                MidiIndex::DINtoUSBthru,
+            ]);
+            assert_eq!(router.route_incoming(&midi, Interface::Usb, &note), 2);
+            assert_eq!(router.route_incoming(&midi, Interface::Din, &[0xF8]), 1);
+            // BLE is disabled
+            assert_eq!(router.route_incoming(&midi, Interface::Ble, &note), 0);
+        }
+        assert_eq!(usb.sent(), [&note[..], &[0xF8]]);
+        assert_eq!(din.sent(), [&note[..]]);
+        assert!(ble.sent().is_empty());
+    }
+
+    #[test]
+    fn test_send_to_enabled_interfaces() {
+        let (mut usb, mut din, mut ble) = (
+            Recorder::default(),
+            Recorder::default(),
+            Recorder::default(),
+        );
+        // Component Info for the configurator
+        let buffer = &mut [0; MAX_MESSAGE_SIZE];
+        let info = OpenDeckRenderer::new(ValueSize::TwoBytes, buffer)
+            .render_component_info(BlockId::Button, 1)
+            .unwrap()
+            .unwrap();
+        let info = info.data();
+        {
+            let mut router = Router::new(&mut usb, &mut din, &mut ble);
+            let midi = settings(&[MidiIndex::BLEMIDIstate]);
+            assert_eq!(router.send(&midi, &[0xC0, 0x05]), 2);
+            assert_eq!(router.send(&midi, info), 1);
+        }
+        assert_eq!(usb.sent(), [&[0xC0, 0x05][..], info]);
+        assert!(din.sent().is_empty());
+        assert_eq!(ble.sent(), [&[0xC0, 0x05][..]]);
+    }
+}
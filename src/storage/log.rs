@@ -0,0 +1,598 @@
+//! Log-structured key/value store emulating EEPROM on flash.
+//!
+//! The storage region is split into pages of `page_size` bytes. One page is
+//! active at a time; every write appends an 8 byte record to it:
+//!
+//! | byte | content                        |
+//! |------|--------------------------------|
+//! | 0    | preset                         |
+//! | 1    | block                          |
+//! | 2    | section                        |
+//! | 3..5 | index (little endian)          |
+//! | 5..7 | value (little endian)          |
+//! | 7    | CRC-8 of bytes 0..7            |
+//!
+//! The latest record of a key wins. When the active page is full, the latest
+//! value of every key is copied to the next page in the ring, which then becomes
+//! active. Pages are used in turn, so erase cycles are spread evenly.
+//!
+//! A page becomes active by writing its 8 byte header (magic, sequence number,
+//! CRC-8) after all records have been copied. A power loss during compaction
+//! therefore leaves the previous page in charge, and a torn record fails its
+//! CRC and is skipped on replay.
+
+use crate::storage::{Storage, StorageError, StorageKey, ERASED_BYTE};
+
+const PAGE_MAGIC: [u8; 2] = *b"OL";
+const HEADER_SIZE: usize = 8;
+const RECORD_SIZE: usize = 8;
+// entries of the table of keys already copied during compaction, 2 KiB of
+// stack; it is filled to 3/4 at most, so probing stays short
+const SEEN_SLOTS: usize = 1024;
+const SEEN_KEYS: usize = SEEN_SLOTS / 4 * 3;
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 > 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn key_hash(key: StorageKey) -> usize {
+    // FNV-1a over the key bytes
+    let index = key.index.to_le_bytes();
+    let mut hash = 0x811c_9dc5u32;
+    for byte in [key.preset, key.block, key.section, index[0], index[1]] {
+        hash = (hash ^ byte as u32).wrapping_mul(0x0100_0193);
+    }
+    hash as usize
+}
+
+fn is_erased(data: &[u8]) -> bool {
+    data.iter().all(|b| *b == ERASED_BYTE)
+}
+
+fn encode_record(key: StorageKey, value: u16) -> [u8; RECORD_SIZE] {
+    let index = key.index.to_le_bytes();
+    let value = value.to_le_bytes();
+    let mut record = [
+        key.preset,
+        key.block,
+        key.section,
+        index[0],
+        index[1],
+        value[0],
+        value[1],
+        0,
+    ];
+    record[RECORD_SIZE - 1] = crc8(&record[..RECORD_SIZE - 1]);
+    record
+}
+
+fn decode_record(record: &[u8; RECORD_SIZE]) -> Option<(StorageKey, u16)> {
+    if crc8(&record[..RECORD_SIZE - 1]) != record[RECORD_SIZE - 1] {
+        return None;
+    }
+    let key = StorageKey {
+        preset: record[0],
+        block: record[1],
+        section: record[2],
+        index: u16::from_le_bytes([record[3], record[4]]),
+    };
+    Some((key, u16::from_le_bytes([record[5], record[6]])))
+}
+
+fn encode_header(sequence: u32) -> [u8; HEADER_SIZE] {
+    let seq = sequence.to_le_bytes();
+    let mut header = [
+        PAGE_MAGIC[0],
+        PAGE_MAGIC[1],
+        seq[0],
+        seq[1],
+        seq[2],
+        seq[3],
+        ERASED_BYTE,
+        0,
+    ];
+    header[HEADER_SIZE - 1] = crc8(&header[..HEADER_SIZE - 1]);
+    header
+}
+
+fn decode_header(header: &[u8; HEADER_SIZE]) -> Option<u32> {
+    if header[..2] != PAGE_MAGIC || crc8(&header[..HEADER_SIZE - 1]) != header[HEADER_SIZE - 1] {
+        return None;
+    }
+    Some(u32::from_le_bytes([
+        header[2], header[3], header[4], header[5],
+    ]))
+}
+
+/// Key/value log in a [`Storage`] of at least two pages.
+///
+/// Compaction copies the latest record of every key to a single page, so a
+/// page of `page_size` bytes holds at most [`LogStore::capacity`] =
+/// `page_size / 8 - 1` distinct keys; a write of one more key fails with
+/// [`StorageError::Full`]. [`crate::config::Config`] uses one key per preset
+/// and section of every component, one per global setting and, with the one
+/// byte protocol, one per MSB section.
+///
+/// For example 4 presets of 16 buttons (5 sections), 4 encoders (12 + 1 MSB),
+/// 4 analogs (9 + 3 MSB) and 16 LEDs (6) need 4 * 276 keys plus 23 global
+/// ones, 1127 keys in total. That takes pages of at least 9024 bytes, so three
+/// 4 KiB flash sectors per page and six sectors for the log.
+///
+/// Compaction needs about 2 KiB of stack to track the keys it has copied.
+pub struct LogStore<S: Storage> {
+    storage: S,
+    page_size: usize,
+    pages: usize,
+    active: usize,
+    sequence: u32,
+    // offset of the next free record within the active page
+    cursor: usize,
+}
+
+impl<S: Storage> LogStore<S> {
+    /// Opens the log in `storage`, formatting it if no valid page is found.
+    ///
+    /// `page_size` is a multiple of the erase granularity of the medium and of 8,
+    /// at most 512 KiB; the region must hold at least two pages. `keys` is the
+    /// number of distinct keys that will be written, a page too small for them is
+    /// rejected with [`StorageError::OutOfBounds`].
+    pub fn mount(storage: S, page_size: usize, keys: usize) -> Result<Self, StorageError> {
+        let pages = storage.capacity() / page_size.max(1);
+        if !page_size.is_multiple_of(RECORD_SIZE)
+            || page_size < HEADER_SIZE + RECORD_SIZE
+            || page_size / RECORD_SIZE > u16::MAX as usize
+            || keys > Self::capacity(page_size)
+            || pages < 2
+        {
+            return Err(StorageError::OutOfBounds);
+        }
+        let mut log = LogStore {
+            storage,
+            page_size,
+            pages,
+            active: 0,
+            sequence: 0,
+            cursor: HEADER_SIZE,
+        };
+
+        let mut newest: Option<(usize, u32)> = None;
+        for page in 0..pages {
+            let mut header = [0; HEADER_SIZE];
+            log.storage.read(page * page_size, &mut header)?;
+            if let Some(sequence) = decode_header(&header) {
+                if newest.is_none_or(|(_, s)| sequence > s) {
+                    newest = Some((page, sequence));
+                }
+            }
+        }
+        match newest {
+            Some((page, sequence)) => {
+                log.active = page;
+                log.sequence = sequence;
+                log.cursor = log.find_cursor()?;
+            }
+            None => log.format()?,
+        }
+        Ok(log)
+    }
+
+    /// Number of distinct keys a page of `page_size` bytes can hold.
+    pub const fn capacity(page_size: usize) -> usize {
+        (page_size / RECORD_SIZE).saturating_sub(1)
+    }
+
+    /// Erases the whole log.
+    pub fn format(&mut self) -> Result<(), StorageError> {
+        self.storage.erase(0, self.pages * self.page_size)?;
+        self.storage.write(0, &encode_header(0))?;
+        self.active = 0;
+        self.sequence = 0;
+        self.cursor = HEADER_SIZE;
+        Ok(())
+    }
+
+    /// Stores `value` for `key`. Writing the value a key already has is a no-op.
+    pub fn write(&mut self, key: StorageKey, value: u16) -> Result<(), StorageError> {
+        if self.read(key)? == Some(value) {
+            return Ok(());
+        }
+        if self.cursor + RECORD_SIZE > self.page_size {
+            return self.compact(key, value);
+        }
+        let offset = self.page_offset(self.active) + self.cursor;
+        // the slot is used even if the write is torn, it can't be programmed twice
+        self.cursor += RECORD_SIZE;
+        self.storage.write(offset, &encode_record(key, value))
+    }
+
+    /// Returns the latest value stored for `key`.
+    pub fn read(&mut self, key: StorageKey) -> Result<Option<u16>, StorageError> {
+        let base = self.page_offset(self.active);
+        // the newest record is the last one, so scan backwards and stop there
+        let mut offset = self.cursor;
+        while offset > HEADER_SIZE {
+            offset -= RECORD_SIZE;
+            if let Some((k, v)) = self.read_record(base + offset)? {
+                if k == key {
+                    return Ok(Some(v));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Feeds every stored record to `apply`, oldest first.
+    pub fn replay(&mut self, apply: &mut dyn FnMut(StorageKey, u16)) -> Result<(), StorageError> {
+        let base = self.page_offset(self.active);
+        let mut offset = HEADER_SIZE;
+        while offset < self.cursor {
+            if let Some((key, value)) = self.read_record(base + offset)? {
+                apply(key, value);
+            }
+            offset += RECORD_SIZE;
+        }
+        Ok(())
+    }
+
+    /// Gives back the underlying storage.
+    pub fn release(self) -> S {
+        self.storage
+    }
+
+    fn page_offset(&self, page: usize) -> usize {
+        page * self.page_size
+    }
+
+    fn read_record(&mut self, offset: usize) -> Result<Option<(StorageKey, u16)>, StorageError> {
+        let mut record = [0; RECORD_SIZE];
+        self.storage.read(offset, &mut record)?;
+        Ok(decode_record(&record))
+    }
+
+    fn find_cursor(&mut self) -> Result<usize, StorageError> {
+        let base = self.page_offset(self.active);
+        let mut offset = self.page_size;
+        // records are appended, so the log ends after the last programmed slot
+        while offset > HEADER_SIZE {
+            let mut record = [0; RECORD_SIZE];
+            self.storage
+                .read(base + offset - RECORD_SIZE, &mut record)?;
+            if !is_erased(&record) {
+                break;
+            }
+            offset -= RECORD_SIZE;
+        }
+        Ok(offset)
+    }
+
+    /// Moves the latest value of every key to the next page and appends the
+    /// pending record there.
+    ///
+    /// The active page is scanned once from its newest record backwards, so the
+    /// first record seen for a key is its latest value. The keys already copied
+    /// are looked up in a hash table of their records in the new page; beyond
+    /// 768 keys the table is full and the new page is searched instead.
+    fn compact(&mut self, key: StorageKey, value: u16) -> Result<(), StorageError> {
+        let source = self.page_offset(self.active);
+        let target_page = (self.active + 1) % self.pages;
+        let target = self.page_offset(target_page);
+        self.storage.erase(target, self.page_size)?;
+
+        // record numbers of the copies in the new page, 0 (the header) is free
+        let mut seen = [0u16; SEEN_SLOTS];
+        let mut entries = 0;
+        let mut out = HEADER_SIZE;
+        let mut offset = self.cursor;
+        while offset > HEADER_SIZE {
+            offset -= RECORD_SIZE;
+            let Some((k, v)) = self.read_record(source + offset)? else {
+                continue;
+            };
+            if k == key {
+                continue;
+            }
+            let mut free = None;
+            let mut copied = false;
+            let start = key_hash(k) % SEEN_SLOTS;
+            for probe in 0..SEEN_SLOTS {
+                let entry = (start + probe) % SEEN_SLOTS;
+                if seen[entry] == 0 {
+                    free = Some(entry);
+                    break;
+                }
+                if self
+                    .read_record(target + seen[entry] as usize * RECORD_SIZE)?
+                    .is_some_and(|(copy, _)| copy == k)
+                {
+                    copied = true;
+                    break;
+                }
+            }
+            if copied || (entries == SEEN_KEYS && self.is_copied(k, target, out)?) {
+                continue;
+            }
+            if out + 2 * RECORD_SIZE > self.page_size {
+                return Err(StorageError::Full);
+            }
+            self.storage.write(target + out, &encode_record(k, v))?;
+            if let Some(entry) = free.filter(|_| entries < SEEN_KEYS) {
+                seen[entry] = (out / RECORD_SIZE) as u16;
+                entries += 1;
+            }
+            out += RECORD_SIZE;
+        }
+        self.storage
+            .write(target + out, &encode_record(key, value))?;
+        out += RECORD_SIZE;
+
+        let sequence = self.sequence.wrapping_add(1);
+        self.storage.write(target, &encode_header(sequence))?;
+        self.active = target_page;
+        self.sequence = sequence;
+        self.cursor = out;
+        Ok(())
+    }
+
+    /// Whether a record for `key` was copied to the page at `target` before `end`.
+    fn is_copied(
+        &mut self,
+        key: StorageKey,
+        target: usize,
+        end: usize,
+    ) -> Result<bool, StorageError> {
+        let mut offset = HEADER_SIZE;
+        while offset < end {
+            if let Some((k, _)) = self.read_record(target + offset)? {
+                if k == key {
+                    return Ok(true);
+                }
+            }
+            offset += RECORD_SIZE;
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 64;
+    const KEYS: usize = PAGE_SIZE / 8 - 1;
+
+    /// NOR flash simulation: writes can only clear bits, erases work on whole
+    /// pages and are counted, so are reads. `budget` limits the number of bytes that can be
+    /// programmed before the simulated power loss.
+    struct FakeFlash<const N: usize> {
+        data: [u8; N],
+        erase_counts: [u32; 8],
+        budget: Option<usize>,
+        reads: usize,
+    }
+
+    impl<const N: usize> FakeFlash<N> {
+        fn new() -> Self {
+            FakeFlash {
+                data: [ERASED_BYTE; N],
+                erase_counts: [0; 8],
+                budget: None,
+                reads: 0,
+            }
+        }
+        fn cut_power_after(&mut self, bytes: usize) {
+            self.budget = Some(bytes);
+        }
+        fn restore_power(&mut self) {
+            self.budget = None;
+        }
+    }
+
+    impl<const N: usize> Storage for FakeFlash<N> {
+        fn capacity(&self) -> usize {
+            N
+        }
+        fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
+            self.reads += 1;
+            buf.copy_from_slice(&self.data[offset..offset + buf.len()]);
+            Ok(())
+        }
+        fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+            for (i, byte) in data.iter().enumerate() {
+                if let Some(budget) = self.budget.as_mut() {
+                    if *budget == 0 {
+                        return Err(StorageError::WriteFailed);
+                    }
+                    *budget -= 1;
+                }
+                self.data[offset + i] &= byte;
+            }
+            Ok(())
+        }
+        fn erase(&mut self, offset: usize, len: usize) -> Result<(), StorageError> {
+            if self.budget == Some(0) {
+                return Err(StorageError::EraseFailed);
+            }
+            assert_eq!(offset % PAGE_SIZE, 0);
+            assert_eq!(len % PAGE_SIZE, 0);
+            for page in offset / PAGE_SIZE..(offset + len) / PAGE_SIZE {
+                self.erase_counts[page] += 1;
+            }
+            self.data[offset..offset + len].fill(ERASED_BYTE);
+            Ok(())
+        }
+    }
+
+    fn key(index: u16) -> StorageKey {
+        StorageKey {
+            preset: 0,
+            block: 1,
+            section: 2,
+            index,
+        }
+    }
+
+    fn remount(log: LogStore<FakeFlash<256>>) -> LogStore<FakeFlash<256>> {
+        let mut flash = log.release();
+        flash.restore_power();
+        LogStore::mount(flash, PAGE_SIZE, KEYS).unwrap()
+    }
+
+    #[test]
+    fn test_mount_formats_blank_flash() {
+        let mut log = LogStore::mount(FakeFlash::<256>::new(), PAGE_SIZE, KEYS).unwrap();
+        assert_eq!(log.read(key(0)), Ok(None));
+        assert_eq!(log.release().erase_counts[..4], [1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_mount_rejects_bad_geometry() {
+        assert!(LogStore::mount(FakeFlash::<256>::new(), 60, 1).is_err());
+        assert!(LogStore::mount(FakeFlash::<256>::new(), 256, 1).is_err());
+        assert!(LogStore::mount(FakeFlash::<256>::new(), PAGE_SIZE, KEYS + 1).is_err());
+    }
+
+    #[test]
+    fn test_latest_value_wins_after_remount() {
+        let mut log = LogStore::mount(FakeFlash::<256>::new(), PAGE_SIZE, KEYS).unwrap();
+        log.write(key(1), 10).unwrap();
+        log.write(key(2), 20).unwrap();
+        log.write(key(1), 11).unwrap();
+
+        let mut log = remount(log);
+        assert_eq!(log.read(key(1)), Ok(Some(11)));
+        assert_eq!(log.read(key(2)), Ok(Some(20)));
+    }
+
+    #[test]
+    fn test_same_value_is_not_appended() {
+        let mut log = LogStore::mount(FakeFlash::<256>::new(), PAGE_SIZE, KEYS).unwrap();
+        log.write(key(1), 10).unwrap();
+        let cursor = log.cursor;
+        log.write(key(1), 10).unwrap();
+        assert_eq!(log.cursor, cursor);
+    }
+
+    #[test]
+    fn test_compaction_keeps_latest_values() {
+        let mut log = LogStore::mount(FakeFlash::<256>::new(), PAGE_SIZE, KEYS).unwrap();
+        // 7 records fit a page, the 8th write triggers compaction
+        for value in 0..20 {
+            log.write(key(value % 3), value).unwrap();
+        }
+        let mut log = remount(log);
+        assert_eq!(log.read(key(0)), Ok(Some(18)));
+        assert_eq!(log.read(key(1)), Ok(Some(19)));
+        assert_eq!(log.read(key(2)), Ok(Some(17)));
+    }
+
+    #[test]
+    fn test_compaction_reads_records_once() {
+        let mut log = LogStore::mount(FakeFlash::<256>::new(), PAGE_SIZE, KEYS).unwrap();
+        for index in 0..7 {
+            log.write(key(index % 6), index).unwrap();
+        }
+        log.storage.reads = 0;
+        log.write(key(6), 1).unwrap();
+        // the no-op check and the compaction both read the 7 records once, the
+        // older record of key 0 is recognised by reading back its copy
+        assert_eq!(log.storage.reads, 2 * 7 + 1);
+        assert_eq!(log.read(key(0)), Ok(Some(6)));
+        assert_eq!(log.read(key(5)), Ok(Some(5)));
+    }
+
+    #[test]
+    fn test_compaction_of_more_keys_than_the_seen_table() {
+        const LARGE_PAGE: usize = 8 * (SEEN_KEYS + 16);
+        let mut log = LogStore::mount(
+            crate::storage::MemoryStorage::<{ 2 * LARGE_PAGE }>::new(),
+            LARGE_PAGE,
+            SEEN_KEYS + 8,
+        )
+        .unwrap();
+        let keys = SEEN_KEYS as u16 + 8;
+        // two rounds overflow the page, the second one compacts
+        for round in 0..2 {
+            for index in 0..keys {
+                log.write(key(index), index + round).unwrap();
+            }
+        }
+        for index in 0..keys {
+            assert_eq!(log.read(key(index)), Ok(Some(index + 1)));
+        }
+    }
+
+    #[test]
+    fn test_compaction_spreads_erases() {
+        let mut log = LogStore::mount(FakeFlash::<256>::new(), PAGE_SIZE, KEYS).unwrap();
+        for value in 0..400 {
+            log.write(key(value % 2), value).unwrap();
+        }
+        let counts = log.release().erase_counts;
+        let min = counts[..4].iter().min().unwrap();
+        let max = counts[..4].iter().max().unwrap();
+        assert!(*max > 10);
+        assert!(max - min <= 1);
+    }
+
+    #[test]
+    fn test_full_when_distinct_keys_exceed_page() {
+        let mut log = LogStore::mount(FakeFlash::<256>::new(), PAGE_SIZE, KEYS).unwrap();
+        for index in 0..7 {
+            log.write(key(index), 1).unwrap();
+        }
+        assert_eq!(log.write(key(7), 1), Err(StorageError::Full));
+    }
+
+    #[test]
+    fn test_power_loss_during_append_keeps_previous_value() {
+        let mut log = LogStore::mount(FakeFlash::<256>::new(), PAGE_SIZE, KEYS).unwrap();
+        log.write(key(1), 10).unwrap();
+        log.storage.cut_power_after(4);
+        assert_eq!(log.write(key(1), 0x1234), Err(StorageError::WriteFailed));
+
+        let mut log = remount(log);
+        assert_eq!(log.read(key(1)), Ok(Some(10)));
+        log.write(key(2), 20).unwrap();
+        let mut log = remount(log);
+        assert_eq!(log.read(key(1)), Ok(Some(10)));
+        assert_eq!(log.read(key(2)), Ok(Some(20)));
+    }
+
+    #[test]
+    fn test_power_loss_at_every_point_of_compaction() {
+        // fill the first page, the next write compacts
+        let mut reference = LogStore::mount(FakeFlash::<256>::new(), PAGE_SIZE, KEYS).unwrap();
+        for value in 0..7 {
+            reference.write(key(value % 2), value).unwrap();
+        }
+        let flash = reference.release();
+
+        for budget in 0..32 {
+            let mut copy = FakeFlash::<256>::new();
+            copy.data = flash.data;
+            let mut log = LogStore::mount(copy, PAGE_SIZE, KEYS).unwrap();
+            log.storage.cut_power_after(budget);
+            let result = log.write(key(0), 100);
+
+            let mut log = remount(log);
+            let expected = if result.is_ok() { 100 } else { 6 };
+            let value = log.read(key(0)).unwrap();
+            assert!(
+                value == Some(expected) || value == Some(100),
+                "budget {budget}: {value:?}"
+            );
+            assert_eq!(log.read(key(1)), Ok(Some(5)), "budget {budget}");
+        }
+    }
+}
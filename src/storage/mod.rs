@@ -0,0 +1,204 @@
+//! Persistent storage for the configuration.
+//!
+//! The library only needs a byte-addressed region it can read, write and erase.
+//! Firmware implements [`Storage`] on top of EEPROM or flash; [`MemoryStorage`]
+//! is a RAM-backed stand-in for tests and for boards without persistent memory.
+//! [`log::LogStore`] keeps individual values in a wear-levelled log on flash.
+
+use crate::{parser::OpenDeckParseError, Block, MessageStatus, Section};
+
+pub mod log;
+
+/// Value of an erased byte, matching NOR flash and most EEPROM emulations.
+pub const ERASED_BYTE: u8 = 0xFF;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StorageError {
+    /// The access lies (partly) outside of the storage region.
+    OutOfBounds,
+    /// The medium reported a failure while reading.
+    ReadFailed,
+    /// The medium reported a failure while writing.
+    WriteFailed,
+    /// The medium reported a failure while erasing.
+    EraseFailed,
+    /// The region does not contain a configuration image.
+    NoImage,
+    /// The image was written with a layout this build does not understand.
+    UnsupportedLayout(u16),
+    /// The image contains a value that cannot be decoded.
+    InvalidValue,
+    /// The image was written for a different number of presets or components.
+    ComponentMismatch,
+    /// The image was written by a newer firmware.
+    FirmwareMismatch,
+    /// The checksum of the image does not match its content.
+    ChecksumMismatch,
+    /// There is no room left for the value, even after compaction.
+    Full,
+}
+
+impl From<StorageError> for MessageStatus {
+    fn from(err: StorageError) -> Self {
+        match err {
+            StorageError::ReadFailed => MessageStatus::ReadError,
+            _ => MessageStatus::WriteError,
+        }
+    }
+}
+
+/// Identifies a single configuration value, the way it is addressed over SysEx.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StorageKey {
+    /// Preset the value belongs to, 0 for global settings.
+    pub preset: u8,
+    pub block: u8,
+    pub section: u8,
+    pub index: u16,
+}
+
+impl StorageKey {
+    /// Splits a `Wish::Set` block into its key and new value.
+    pub(crate) fn from_block(preset: usize, block: Block) -> (StorageKey, u16) {
+        let (index, block_id, section) = block.into_parts();
+        let key = StorageKey {
+            preset: preset as u8,
+            block: block_id as u8,
+            section: section.id,
+            index,
+        };
+        (key, section.value)
+    }
+    /// Rebuilds the block a stored value was split from.
+    pub(crate) fn to_block(self, value: u16) -> Result<Block, OpenDeckParseError> {
+        let section = Section {
+            id: self.section,
+            value,
+        };
+        Block::from_parts(self.block, self.index, section)
+    }
+}
+
+/// A byte-addressed persistent memory region.
+pub trait Storage {
+    /// Size of the region in bytes.
+    fn capacity(&self) -> usize;
+    /// Fills `buf` with the bytes starting at `offset`.
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError>;
+    /// Writes `data` starting at `offset`. The range must have been erased before.
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError>;
+    /// Resets `len` bytes starting at `offset` to [`ERASED_BYTE`].
+    fn erase(&mut self, offset: usize, len: usize) -> Result<(), StorageError>;
+}
+
+/// RAM-backed [`Storage`] of `N` bytes.
+pub struct MemoryStorage<const N: usize> {
+    data: [u8; N],
+}
+
+impl<const N: usize> MemoryStorage<N> {
+    pub fn new() -> Self {
+        MemoryStorage {
+            data: [ERASED_BYTE; N],
+        }
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+    fn check_bounds(offset: usize, len: usize) -> Result<(), StorageError> {
+        match offset.checked_add(len) {
+            Some(end) if end <= N => Ok(()),
+            _ => Err(StorageError::OutOfBounds),
+        }
+    }
+}
+
+impl<const N: usize> Default for MemoryStorage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Storage for MemoryStorage<N> {
+    fn capacity(&self) -> usize {
+        N
+    }
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), StorageError> {
+        Self::check_bounds(offset, buf.len())?;
+        buf.copy_from_slice(&self.data[offset..offset + buf.len()]);
+        Ok(())
+    }
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+        Self::check_bounds(offset, data.len())?;
+        self.data[offset..offset + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+    fn erase(&mut self, offset: usize, len: usize) -> Result<(), StorageError> {
+        Self::check_bounds(offset, len)?;
+        self.data[offset..offset + len].fill(ERASED_BYTE);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_storage_starts_erased() {
+        let storage = MemoryStorage::<16>::new();
+        assert!(storage.as_slice().iter().all(|b| *b == ERASED_BYTE));
+    }
+
+    #[test]
+    fn test_memory_storage_write_read_erase() {
+        let mut storage = MemoryStorage::<16>::new();
+        storage.write(4, &[1, 2, 3]).unwrap();
+        let mut buf = [0; 3];
+        storage.read(4, &mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3]);
+        storage.erase(5, 2).unwrap();
+        storage.read(4, &mut buf).unwrap();
+        assert_eq!(buf, [1, ERASED_BYTE, ERASED_BYTE]);
+    }
+
+    #[test]
+    fn test_storage_key_roundtrip() {
+        use crate::button::ButtonSection;
+        let block = Block::Button(3, ButtonSection::MidiId(64));
+        let (key, value) = StorageKey::from_block(1, block);
+        assert_eq!(
+            key,
+            StorageKey {
+                preset: 1,
+                block: 1,
+                section: 2,
+                index: 3
+            }
+        );
+        assert_eq!(value, 64);
+        assert_eq!(key.to_block(value), Ok(block));
+    }
+
+    #[test]
+    fn test_storage_key_keeps_channel() {
+        use crate::{led::LedSection, ChannelOrAll};
+        let block = Block::Led(0, LedSection::Channel(ChannelOrAll::Channel(4)));
+        let (key, value) = StorageKey::from_block(0, block);
+        assert_eq!(value, 5);
+        assert_eq!(key.to_block(value), Ok(block));
+    }
+
+    #[test]
+    fn test_memory_storage_rejects_out_of_bounds() {
+        let mut storage = MemoryStorage::<16>::new();
+        assert_eq!(storage.write(15, &[1, 2]), Err(StorageError::OutOfBounds));
+        assert_eq!(storage.read(16, &mut [0]), Err(StorageError::OutOfBounds));
+        assert_eq!(storage.erase(usize::MAX, 2), Err(StorageError::OutOfBounds));
+    }
+}
@@ -0,0 +1,233 @@
+//! Reassembly of SysEx messages received in fragments.
+//!
+//! USB delivers SysEx in event packets of up to 3 bytes and a UART byte by
+//! byte, with realtime messages like the MIDI clock interleaved anywhere.
+//! [`SysexAssembler`] collects the fragments until a complete OpenDeck message
+//! can be handed to [`crate::config::Config::process_sysex`].
+
+use crate::{usb::UsbMidiPacket, MAX_MESSAGE_SIZE, M_ID_0, M_ID_1, M_ID_2, SYSEX_END, SYSEX_START};
+use heapless::Vec;
+
+pub(crate) const OPENDECK_PREFIX: [u8; 4] = [SYSEX_START, M_ID_0, M_ID_1, M_ID_2];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AssemblerError {
+    /// The message didn't fit into the assembler, it was dropped.
+    Overflow,
+    /// A status byte other than a realtime message or SysEx end interrupted the
+    /// message, it was dropped.
+    Aborted,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    Idle,
+    Receiving,
+    /// The message is addressed to another manufacturer.
+    Ignoring,
+    Overflowed,
+}
+
+/// Collects a SysEx message of up to `N` bytes, including `F0` and `F7`.
+///
+/// Only OpenDeck messages are collected, SysEx for other manufacturers is
+/// skipped without filling the buffer. Realtime messages are ignored. USB
+/// cables carry independent streams, use one assembler per cable.
+pub struct SysexAssembler<const N: usize = MAX_MESSAGE_SIZE> {
+    buffer: Vec<u8, N>,
+    state: State,
+}
+
+impl<const N: usize> Default for SysexAssembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> SysexAssembler<N> {
+    pub fn new() -> Self {
+        SysexAssembler {
+            buffer: Vec::new(),
+            state: State::Idle,
+        }
+    }
+
+    /// Drops a partially received message.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.state = State::Idle;
+    }
+
+    /// Feeds a received byte, returns the message it completes or the error that
+    /// ended the current message.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&[u8], AssemblerError>> {
+        let outcome = self.feed(byte);
+        outcome.map(|outcome| outcome.map(|_| self.buffer.as_slice()))
+    }
+
+    /// Feeds the bytes of a USB-MIDI event packet, regardless of its cable.
+    ///
+    /// A packet completes at most one message, as only a SysEx end packet carries
+    /// `F7` and it is its last byte.
+    pub fn push_packet(&mut self, packet: UsbMidiPacket) -> Option<Result<&[u8], AssemblerError>> {
+        let mut outcome = None;
+        for &byte in packet.payload() {
+            if let Some(result) = self.feed(byte) {
+                outcome = Some(result);
+            }
+        }
+        outcome.map(|outcome| outcome.map(|_| self.buffer.as_slice()))
+    }
+
+    fn feed(&mut self, byte: u8) -> Option<Result<(), AssemblerError>> {
+        match byte {
+            // realtime messages may appear anywhere, even inside SysEx
+            0xF8..=0xFF => None,
+            SYSEX_START => {
+                let aborted = self.interrupt();
+                self.buffer.push(byte).ok();
+                self.state = State::Receiving;
+                aborted
+            }
+            SYSEX_END => {
+                let state = self.state;
+                self.state = State::Idle;
+                match state {
+                    State::Receiving if self.buffer.len() < OPENDECK_PREFIX.len() => None,
+                    State::Receiving => match self.buffer.push(byte) {
+                        Ok(()) => Some(Ok(())),
+                        Err(_) => Some(Err(AssemblerError::Overflow)),
+                    },
+                    State::Overflowed => Some(Err(AssemblerError::Overflow)),
+                    State::Idle | State::Ignoring => None,
+                }
+            }
+            0x80..=0xF6 => {
+                let aborted = self.interrupt();
+                self.state = State::Idle;
+                aborted
+            }
+            _ => {
+                if self.state == State::Receiving {
+                    let len = self.buffer.len();
+                    if len < OPENDECK_PREFIX.len() && OPENDECK_PREFIX[len] != byte {
+                        self.state = State::Ignoring;
+                    } else if self.buffer.push(byte).is_err() {
+                        self.state = State::Overflowed;
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Ends the current message before a status byte, reports it if it was ours.
+    fn interrupt(&mut self) -> Option<Result<(), AssemblerError>> {
+        let aborted = matches!(self.state, State::Receiving | State::Overflowed)
+            && self.buffer.len() >= OPENDECK_PREFIX.len();
+        self.buffer.clear();
+        aborted.then_some(Err(AssemblerError::Aborted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::OpenDeckParser, OpenDeckRequest, SpecialRequest, ValueSize};
+
+    const HANDSHAKE: [u8; 8] = [0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0xF7];
+
+    #[test]
+    fn test_bytes_with_interleaved_realtime() {
+        let mut assembler = SysexAssembler::<16>::new();
+        let (last, bytes) = HANDSHAKE.split_last().unwrap();
+        for &byte in bytes {
+            assert_eq!(assembler.push(byte), None);
+            assert_eq!(assembler.push(0xF8), None);
+        }
+        let message = assembler.push(*last).unwrap().unwrap();
+        assert_eq!(message, &HANDSHAKE);
+        assert_eq!(
+            OpenDeckParser::new(ValueSize::TwoBytes).parse(message),
+            Ok(OpenDeckRequest::Special(SpecialRequest::Handshake))
+        );
+    }
+
+    #[test]
+    fn test_usb_packets() {
+        let mut assembler: SysexAssembler = SysexAssembler::new();
+        for packet in [
+            [0x04, 0xF0, 0x00, 0x53],
+            [0x0F, 0xF8, 0x00, 0x00],
+            [0x04, 0x43, 0x00, 0x00],
+        ] {
+            assert_eq!(assembler.push_packet(UsbMidiPacket(packet)), None);
+        }
+        assert_eq!(
+            assembler.push_packet(UsbMidiPacket([0x06, 0x01, 0xF7, 0x00])),
+            Some(Ok(&HANDSHAKE[..]))
+        );
+        // the next message starts from scratch
+        assert_eq!(
+            assembler.push_packet(UsbMidiPacket([0x04, 0xF0, 0x00, 0x53])),
+            None
+        );
+        assert_eq!(
+            assembler.push_packet(UsbMidiPacket([0x07, 0x43, 0x01, 0xF7])),
+            Some(Ok(&[0xF0, 0x00, 0x53, 0x43, 0x01, 0xF7][..]))
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut assembler = SysexAssembler::<8>::new();
+        for &byte in &HANDSHAKE[..7] {
+            assert_eq!(assembler.push(byte), None);
+        }
+        assert_eq!(assembler.push(0x02), None);
+        assert_eq!(assembler.push(0xF7), Some(Err(AssemblerError::Overflow)));
+        // a message of exactly the capacity fits
+        for &byte in &HANDSHAKE[..7] {
+            assembler.push(byte);
+        }
+        assert_eq!(assembler.push(0xF7), Some(Ok(&HANDSHAKE[..])));
+    }
+
+    #[test]
+    fn test_aborted_messages() {
+        let mut assembler = SysexAssembler::<16>::new();
+        for &byte in &HANDSHAKE[..5] {
+            assembler.push(byte);
+        }
+        // note on
+        assert_eq!(assembler.push(0x90), Some(Err(AssemblerError::Aborted)));
+        assert_eq!(assembler.push(0x40), None);
+        assert_eq!(assembler.push(0xF7), None);
+
+        for &byte in &HANDSHAKE[..5] {
+            assembler.push(byte);
+        }
+        // a new message starts
+        assert_eq!(assembler.push(0xF0), Some(Err(AssemblerError::Aborted)));
+        for &byte in &HANDSHAKE[1..7] {
+            assert_eq!(assembler.push(byte), None);
+        }
+        assert_eq!(assembler.push(0xF7), Some(Ok(&HANDSHAKE[..])));
+    }
+
+    #[test]
+    fn test_other_manufacturers_are_skipped() {
+        let mut assembler = SysexAssembler::<8>::new();
+        assert_eq!(assembler.push(0xF0), None);
+        assert_eq!(assembler.push(0x7E), None);
+        // longer than the buffer, but not ours
+        for _ in 0..32 {
+            assert_eq!(assembler.push(0x01), None);
+        }
+        assert_eq!(assembler.push(0xF7), None);
+        assert_eq!(assembler.push(0xF0), None);
+        assert_eq!(assembler.push(0x00), None);
+        assert_eq!(assembler.push(0xB0), None);
+    }
+}
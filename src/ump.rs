@@ -0,0 +1,260 @@
+//! Universal MIDI Packet (UMP) output.
+//!
+//! The handlers render MIDI 1.0 byte messages. In UMP mode these are sent as
+//! MIDI 1.0 channel voice messages in UMP, or as MIDI 2.0 channel voice messages
+//! with their values scaled up to 16 or 32 bits. 14-bit controllers, which take
+//! several MIDI 1.0 messages, become a single MIDI 2.0 message.
+
+use crate::{SYSEX_END, SYSEX_START};
+use midi2::{error::BufferOverflow, ux::u4, UmpMessage};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UmpMode {
+    /// MIDI 1.0 channel voice messages in UMP.
+    Midi1,
+    /// MIDI 2.0 channel voice messages.
+    Midi2,
+}
+
+pub(crate) const SYSTEM: u32 = 0x1;
+pub(crate) const MIDI1_CHANNEL_VOICE: u32 = 0x2;
+pub(crate) const DATA_64: u32 = 0x3;
+pub(crate) const MIDI2_CHANNEL_VOICE: u32 = 0x4;
+
+/// Status of the MIDI 2.0 Assignable Controller, which replaces NRPN.
+pub(crate) const ASSIGNABLE_CONTROLLER: u8 = 0x3;
+pub(crate) const CONTROL_CHANGE: u8 = 0xB;
+
+const SYSEX_BYTES_PER_PACKET: usize = 6;
+
+/// Size of the longest MIDI 1.0 message of the handlers, an MMC SysEx message.
+pub(crate) const MAX_BYTES: usize = 6;
+
+/// Scales a value of `from` bits up to `to` bits, keeping the minimum, center
+/// and maximum as in the MIDI 2.0 translation rules.
+pub fn scale_up(value: u32, from: u32, to: u32) -> u32 {
+    let shift = to - from;
+    let shifted = value << shift;
+    if value <= 1 << (from - 1) {
+        return shifted;
+    }
+    // repeat the bits below the center to fill the lower bits
+    let repeat_bits = from - 1;
+    let mut repeat = value & ((1 << repeat_bits) - 1);
+    repeat = if shift > repeat_bits {
+        repeat << (shift - repeat_bits)
+    } else {
+        repeat >> (repeat_bits - shift)
+    };
+    let mut result = shifted;
+    while repeat != 0 {
+        result |= repeat;
+        repeat >>= repeat_bits;
+    }
+    result
+}
+
+/// Scales a value of `from` bits down to `to` bits by dropping the lower bits.
+pub fn scale_down(value: u32, from: u32, to: u32) -> u32 {
+    value >> (from - to)
+}
+
+/// Renders a MIDI 2.0 channel voice message.
+pub(crate) fn midi2(
+    group: u4,
+    status: u8,
+    channel: u4,
+    index: (u8, u8),
+    data: u32,
+    buffer: &mut [u32],
+) -> Result<UmpMessage<&[u32]>, BufferOverflow> {
+    if buffer.len() < 2 {
+        return Err(BufferOverflow);
+    }
+    buffer[0] = (MIDI2_CHANNEL_VOICE << 28)
+        | (u32::from(u8::from(group)) << 24)
+        | ((status as u32 & 0xF) << 20)
+        | (u32::from(u8::from(channel)) << 16)
+        | ((index.0 as u32 & 0x7F) << 8)
+        | (index.1 as u32 & 0x7F);
+    buffer[1] = data;
+    Ok(message(&buffer[..2]))
+}
+
+/// Renders a MIDI 1.0 byte message as UMP.
+pub(crate) fn from_bytes<'buf>(
+    mode: UmpMode,
+    group: u4,
+    bytes: &[u8],
+    buffer: &'buf mut [u32],
+) -> Result<UmpMessage<&'buf [u32]>, BufferOverflow> {
+    let byte = |i: usize| bytes.get(i).copied().unwrap_or(0);
+    let status = byte(0);
+    if status == SYSEX_START {
+        return sysex7(group, bytes, buffer);
+    }
+    if status < 0xF0 && mode == UmpMode::Midi2 {
+        let channel = u4::new(status & 0x0F);
+        let (index, data) = match status >> 4 {
+            // note off and on carry a 16-bit velocity and no attribute
+            0x8 | 0x9 => ((byte(1), 0), scale_up(byte(2) as u32, 7, 16) << 16),
+            0xA | 0xB => ((byte(1), 0), scale_up(byte(2) as u32, 7, 32)),
+            // program change without bank select
+            0xC => ((0, 0), (byte(1) as u32) << 24),
+            0xD => ((0, 0), scale_up(byte(1) as u32, 7, 32)),
+            _ => {
+                let bend = (byte(2) as u32) << 7 | byte(1) as u32;
+                ((0, 0), scale_up(bend, 14, 32))
+            }
+        };
+        // a note on with velocity 0 is a note off in MIDI 1.0 only
+        let opcode = match (status >> 4, byte(2)) {
+            (0x9, 0) => 0x8,
+            (opcode, _) => opcode,
+        };
+        return midi2(group, opcode, channel, index, data, buffer);
+    }
+    let Some(word) = buffer.first_mut() else {
+        return Err(BufferOverflow);
+    };
+    let message_type = if status < 0xF0 {
+        MIDI1_CHANNEL_VOICE
+    } else {
+        SYSTEM
+    };
+    *word = (message_type << 28)
+        | (u32::from(u8::from(group)) << 24)
+        | (status as u32) << 16
+        | (byte(1) as u32) << 8
+        | byte(2) as u32;
+    Ok(message(&buffer[..1]))
+}
+
+/// Splits a SysEx message into 64-bit data messages of up to 6 bytes each.
+fn sysex7<'buf>(
+    group: u4,
+    bytes: &[u8],
+    buffer: &'buf mut [u32],
+) -> Result<UmpMessage<&'buf [u32]>, BufferOverflow> {
+    let payload = bytes.strip_prefix(&[SYSEX_START]).unwrap_or(bytes);
+    let payload = payload.strip_suffix(&[SYSEX_END]).unwrap_or(payload);
+    let packets = payload.len().div_ceil(SYSEX_BYTES_PER_PACKET).max(1);
+    if buffer.len() < packets * 2 {
+        return Err(BufferOverflow);
+    }
+    for packet in 0..packets {
+        let start = packet * SYSEX_BYTES_PER_PACKET;
+        let end = (start + SYSEX_BYTES_PER_PACKET).min(payload.len());
+        let status: u32 = match (packet, packets) {
+            (_, 1) => 0x0,
+            (0, _) => 0x1,
+            (p, n) if p + 1 == n => 0x3,
+            _ => 0x2,
+        };
+        let mut data = [0u8; SYSEX_BYTES_PER_PACKET];
+        data[..end - start].copy_from_slice(&payload[start..end]);
+        buffer[packet * 2] = (DATA_64 << 28)
+            | (u32::from(u8::from(group)) << 24)
+            | (status << 20)
+            | ((end - start) as u32) << 16
+            | (data[0] as u32) << 8
+            | data[1] as u32;
+        buffer[packet * 2 + 1] = u32::from_be_bytes([data[2], data[3], data[4], data[5]]);
+    }
+    Ok(message(&buffer[..packets * 2]))
+}
+
+fn message(buffer: &[u32]) -> UmpMessage<&[u32]> {
+    // the words are built above, they always form a valid message
+    UmpMessage::try_from(buffer).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midi2::Data;
+
+    fn convert(mode: UmpMode, bytes: &[u8]) -> heapless::Vec<u32, 8> {
+        let buffer = &mut [0; 8];
+        let message = from_bytes(mode, u4::new(1), bytes, buffer).unwrap();
+        heapless::Vec::from_slice(message.data()).unwrap()
+    }
+
+    #[test]
+    fn test_scale_up() {
+        assert_eq!(scale_up(0, 7, 32), 0);
+        assert_eq!(scale_up(64, 7, 32), 0x8000_0000);
+        assert_eq!(scale_up(127, 7, 32), 0xFFFF_FFFF);
+        assert_eq!(scale_up(127, 7, 16), 0xFFFF);
+        assert_eq!(scale_up(0x2000, 14, 32), 0x8000_0000);
+        assert_eq!(scale_up(0x3FFF, 14, 32), 0xFFFF_FFFF);
+        assert_eq!(scale_up(0x1000, 14, 32), 0x4000_0000);
+    }
+
+    #[test]
+    fn test_midi1_in_ump() {
+        assert_eq!(convert(UmpMode::Midi1, &[0x92, 0x40, 0x7F]), [0x2192_407F]);
+        assert_eq!(convert(UmpMode::Midi1, &[0xC0, 0x05]), [0x21C0_0500]);
+        // system messages are the same in both modes
+        assert_eq!(convert(UmpMode::Midi1, &[0xFA]), [0x11FA_0000]);
+        assert_eq!(convert(UmpMode::Midi2, &[0xF8]), [0x11F8_0000]);
+    }
+
+    #[test]
+    fn test_midi2_channel_voice() {
+        assert_eq!(
+            convert(UmpMode::Midi2, &[0x92, 0x40, 0x7F]),
+            [0x4192_4000, 0xFFFF_0000]
+        );
+        assert_eq!(
+            convert(UmpMode::Midi2, &[0x90, 0x40, 0x00]),
+            [0x4180_4000, 0x0000_0000]
+        );
+        assert_eq!(
+            convert(UmpMode::Midi2, &[0xB0, 0x07, 0x40]),
+            [0x41B0_0700, 0x8000_0000]
+        );
+        assert_eq!(
+            convert(UmpMode::Midi2, &[0xC3, 0x05]),
+            [0x41C3_0000, 0x0500_0000]
+        );
+        assert_eq!(
+            convert(UmpMode::Midi2, &[0xE0, 0x00, 0x40]),
+            [0x41E0_0000, 0x8000_0000]
+        );
+    }
+
+    #[test]
+    fn test_sysex7() {
+        // MMC play
+        assert_eq!(
+            convert(UmpMode::Midi2, &[0xF0, 0x7F, 0x01, 0x06, 0x02, 0xF7]),
+            [0x3104_7F01, 0x0602_0000]
+        );
+        assert_eq!(
+            convert(
+                UmpMode::Midi1,
+                &[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0xF7]
+            ),
+            [0x3106_0053, 0x4300_0001]
+        );
+        assert_eq!(
+            convert(
+                UmpMode::Midi1,
+                &[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x02, 0xF7]
+            ),
+            [0x3116_0053, 0x4300_0001, 0x3131_0200, 0x0000_0000]
+        );
+        let buffer = &mut [0; 3];
+        assert_eq!(
+            from_bytes(
+                UmpMode::Midi1,
+                u4::new(0),
+                &[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x02, 0xF7],
+                buffer
+            ),
+            Err(BufferOverflow)
+        );
+    }
+}
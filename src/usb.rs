@@ -0,0 +1,178 @@
+//! USB-MIDI 1.0 event packets.
+//!
+//! USB carries MIDI in 4-byte packets: the upper nibble of the header is the
+//! virtual cable, the lower nibble the Code Index Number (CIN) which tells how
+//! many of the following 3 bytes belong to the message. [`packets`] encodes the
+//! messages of the crate, [`crate::sysex::SysexAssembler`] decodes SysEx.
+
+use midi2::ux::u4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UsbMidiPacket(pub [u8; 4]);
+
+impl From<[u8; 4]> for UsbMidiPacket {
+    fn from(bytes: [u8; 4]) -> Self {
+        UsbMidiPacket(bytes)
+    }
+}
+
+impl UsbMidiPacket {
+    pub fn cable(&self) -> u4 {
+        u4::new(self.0[0] >> 4)
+    }
+
+    pub fn code_index(&self) -> u8 {
+        self.0[0] & 0x0F
+    }
+
+    /// Returns the MIDI bytes of the packet, without the padding.
+    pub fn payload(&self) -> &[u8] {
+        let len = match self.code_index() {
+            // reserved for future extensions
+            0x0 | 0x1 => 0,
+            // single byte: SysEx end or system common, or a byte of its own
+            0x5 | 0xF => 1,
+            // two byte system common, SysEx ends with two bytes, program change
+            // and channel pressure
+            0x2 | 0x6 | 0xC | 0xD => 2,
+            _ => 3,
+        };
+        &self.0[1..1 + len]
+    }
+}
+
+/// Splits a MIDI message into event packets for `cable`, pass the `data()` of a
+/// message rendered by the crate.
+///
+/// SysEx is spread over as many packets as needed, any other message takes a
+/// single packet.
+pub fn packets(cable: u4, message: &[u8]) -> UsbMidiPackets<'_> {
+    UsbMidiPackets {
+        cable,
+        sysex: message.first() == Some(&crate::SYSEX_START),
+        bytes: message,
+    }
+}
+
+pub struct UsbMidiPackets<'a> {
+    cable: u4,
+    sysex: bool,
+    bytes: &'a [u8],
+}
+
+impl Iterator for UsbMidiPackets<'_> {
+    type Item = UsbMidiPacket;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let status = *self.bytes.first()?;
+        let (code_index, len) = if self.sysex {
+            match self.bytes.len() {
+                1 => (0x5, 1),
+                2 => (0x6, 2),
+                3 => (0x7, 3),
+                _ => (0x4, 3),
+            }
+        } else {
+            match status {
+                0x80..=0xBF | 0xE0..=0xEF => (status >> 4, 3),
+                0xC0..=0xDF => (status >> 4, 2),
+                0xF1 | 0xF3 => (0x2, 2),
+                0xF2 => (0x3, 3),
+                0xF4..=0xF7 => (0x5, 1),
+                // realtime messages and bytes without a status
+                _ => (0xF, 1),
+            }
+        };
+        let (bytes, rest) = self.bytes.split_at(len.min(self.bytes.len()));
+        self.bytes = rest;
+
+        let mut packet = [(u8::from(self.cable) << 4) | code_index, 0, 0, 0];
+        packet[1..1 + bytes.len()].copy_from_slice(bytes);
+        Some(UsbMidiPacket(packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::Vec;
+
+    fn encode(cable: u8, message: &[u8]) -> Vec<[u8; 4], 32> {
+        packets(u4::new(cable), message)
+            .map(|packet| packet.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_channel_and_system_messages() {
+        assert_eq!(encode(0, &[0x90, 0x40, 0x7F]), [[0x09, 0x90, 0x40, 0x7F]]);
+        assert_eq!(encode(3, &[0xC1, 0x05]), [[0x3C, 0xC1, 0x05, 0x00]]);
+        assert_eq!(encode(0, &[0xE0, 0x00, 0x40]), [[0x0E, 0xE0, 0x00, 0x40]]);
+        assert_eq!(encode(0, &[0xF2, 0x10, 0x01]), [[0x03, 0xF2, 0x10, 0x01]]);
+        assert_eq!(encode(0, &[0xF3, 0x02]), [[0x02, 0xF3, 0x02, 0x00]]);
+        assert_eq!(encode(15, &[0xF8]), [[0xFF, 0xF8, 0x00, 0x00]]);
+        assert!(encode(0, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_sysex_is_split() {
+        // handshake request
+        assert_eq!(
+            encode(0, &[0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0xF7]),
+            [
+                [0x04, 0xF0, 0x00, 0x53],
+                [0x04, 0x43, 0x00, 0x00],
+                [0x06, 0x01, 0xF7, 0x00]
+            ]
+        );
+        assert_eq!(
+            encode(1, &[0xF0, 0x00, 0x53, 0x43, 0x01, 0xF7]),
+            [[0x14, 0xF0, 0x00, 0x53], [0x17, 0x43, 0x01, 0xF7]]
+        );
+        assert_eq!(
+            encode(0, &[0xF0, 0x00, 0x53, 0xF7]),
+            [[0x04, 0xF0, 0x00, 0x53], [0x05, 0xF7, 0x00, 0x00]]
+        );
+        assert_eq!(encode(0, &[0xF0, 0xF7]), [[0x06, 0xF0, 0xF7, 0x00]]);
+    }
+
+    #[test]
+    fn test_packets_reassemble() {
+        use crate::sysex::SysexAssembler;
+
+        let message = [
+            0xF0, 0x00, 0x53, 0x43, 0x00, 0x00, 0x01, 0x00, 0x01, 0x02, 0xF7,
+        ];
+        let mut assembler: SysexAssembler = SysexAssembler::new();
+        let mut packets = packets(u4::new(2), &message).peekable();
+        while let Some(packet) = packets.next() {
+            let result = assembler.push_packet(packet);
+            if packets.peek().is_some() {
+                assert_eq!(result, None);
+            } else {
+                assert_eq!(result, Some(Ok(&message[..])));
+            }
+        }
+    }
+
+    #[test]
+    fn test_payload_length_follows_code_index() {
+        let packet = UsbMidiPacket([0x19, 0x90, 0x40, 0x7F]);
+        assert_eq!(packet.cable(), u4::new(1));
+        assert_eq!(packet.code_index(), 0x9);
+        assert_eq!(packet.payload(), &[0x90, 0x40, 0x7F]);
+
+        assert_eq!(UsbMidiPacket([0x05, 0xF7, 0, 0]).payload(), &[0xF7]);
+        assert_eq!(
+            UsbMidiPacket([0x06, 0x01, 0xF7, 0]).payload(),
+            &[0x01, 0xF7]
+        );
+        assert_eq!(
+            UsbMidiPacket([0x0C, 0xC0, 0x05, 0]).payload(),
+            &[0xC0, 0x05]
+        );
+        assert_eq!(UsbMidiPacket([0x0F, 0xF8, 0, 0]).payload(), &[0xF8]);
+        assert!(UsbMidiPacket([0x01, 0x90, 0x40, 0x7F]).payload().is_empty());
+    }
+}
@@ -499,6 +499,7 @@ mod tests {
     /// Wiki: Section 0, NEW_VALUE range 0-1 (Off/On)
     /// https://github.com/shanteacontrols/OpenDeck/wiki/Sysex-Configuration#output-state
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_parse_output_state_section() {
         // SET output 0, section 0 (state), value 1 (on)
         let request = [